@@ -0,0 +1,155 @@
+// Background library analysis: decodes each file once and feeds the loudness
+// meter and the tempo/key analyzer, stores the results in the library cache,
+// then aggregates album loudness and optionally writes ReplayGain tags.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::library::LibraryCache;
+use crate::loudness::{self, LoudnessMeter, TrackLoudness};
+use crate::tempo_key::{TempoKey, TempoKeyAnalyzer};
+
+// Save the library cache every this many analyzed files
+const SAVE_EVERY: usize = 10;
+
+#[derive(Debug, Clone)]
+pub(crate) enum AnalysisEvent {
+    Progress { done: usize, total: usize, current: String },
+    Finished(AnalysisReport),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AnalysisReport {
+    pub(crate) library: LibraryCache,
+    pub(crate) analyzed: usize,
+    pub(crate) tagged: usize,
+    // Files that got ReplayGain values but can't be tagged (not FLAC)
+    pub(crate) untaggable: usize,
+    pub(crate) errors: Vec<String>,
+    pub(crate) cancelled: bool,
+}
+
+// What was measured for one file
+struct FileAnalysis {
+    loudness: Option<TrackLoudness>,
+    tempo_key: Option<TempoKey>,
+}
+
+// Measure one file; only the parts asked for are computed. Returns Ok(None)
+// if cancelled part-way.
+fn analyze_file(
+    path: &Path,
+    want_loudness: bool,
+    want_tempo_key: bool,
+    cancel: &AtomicBool,
+) -> Result<Option<FileAnalysis>, String> {
+    let (mut format, track) = crate::engine::open_with_symphonia(path).ok_or("unsupported format")?;
+    let mut analyzers: Option<(Option<LoudnessMeter>, Option<TempoKeyAnalyzer>, usize)> = None;
+    crate::engine::decode_with_symphonia(format.as_mut(), &track, |samples, channels, rate| {
+        // Measure with the layout of the first packet; changes mid-file are rare
+        let (meter, tempo_key, first_channels) = analyzers.get_or_insert_with(|| {
+            (
+                want_loudness.then(|| LoudnessMeter::new(channels, rate)),
+                want_tempo_key.then(|| TempoKeyAnalyzer::new(rate)),
+                channels,
+            )
+        });
+        if *first_channels == channels {
+            if let Some(meter) = meter {
+                meter.push(samples);
+            }
+            if let Some(tempo_key) = tempo_key {
+                tempo_key.push(samples, channels);
+            }
+        }
+        !cancel.load(Ordering::Relaxed)
+    })
+    .ok_or("no decoder for this codec")?;
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    let (meter, tempo_key, _) = analyzers.ok_or("no audio decoded")?;
+    Ok(Some(FileAnalysis { loudness: meter.map(LoudnessMeter::finish), tempo_key: tempo_key.map(TempoKeyAnalyzer::finish) }))
+}
+
+// Analyze every file that's missing results in the library cache, then compute
+// album loudness per folder and optionally write ReplayGain tags. Runs on a
+// worker thread; `send` reports progress.
+pub(crate) fn run_job(paths: Vec<PathBuf>, mut library: LibraryCache, write_tags: bool, cancel: &AtomicBool, send: impl Fn(AnalysisEvent)) {
+    let total = paths.len();
+    let mut analyzed = 0;
+    let mut unsaved = 0;
+    let mut errors = Vec::new();
+    for (done, path) in paths.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let cached = library.get(path);
+        let want_loudness = cached.is_none_or(|e| e.loudness.is_none());
+        let want_tempo_key = cached.is_none_or(|e| e.tempo_key.is_none());
+        if !want_loudness && !want_tempo_key {
+            continue;
+        }
+        let current = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        send(AnalysisEvent::Progress { done, total, current: current.clone() });
+        match analyze_file(path, want_loudness, want_tempo_key, cancel) {
+            Ok(Some(result)) => {
+                let entry = library.entry_mut(path);
+                if result.loudness.is_some() {
+                    entry.loudness = result.loudness;
+                }
+                if result.tempo_key.is_some() {
+                    entry.tempo_key = result.tempo_key;
+                }
+                analyzed += 1;
+                unsaved += 1;
+                if unsaved == SAVE_EVERY {
+                    library.save();
+                    unsaved = 0;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => errors.push(format!("{current}: {e}")),
+        }
+    }
+    let cancelled = cancel.load(Ordering::Relaxed);
+    let mut tagged = 0;
+    let mut untaggable = 0;
+    if !cancelled {
+        send(AnalysisEvent::Progress { done: total, total, current: "album loudness".into() });
+        // Albums are folders
+        let mut albums: BTreeMap<PathBuf, Vec<&PathBuf>> = BTreeMap::new();
+        for path in &paths {
+            albums.entry(path.parent().map(Path::to_path_buf).unwrap_or_default()).or_default().push(path);
+        }
+        for tracks in albums.values() {
+            let measured: Vec<TrackLoudness> = tracks.iter().filter_map(|p| library.get(p)?.loudness.clone()).collect();
+            let album = loudness::album_loudness(&measured.iter().collect::<Vec<_>>());
+            for path in tracks {
+                if library.get(path).is_some_and(|e| e.loudness.is_some()) {
+                    library.entry_mut(path).album = Some(album);
+                }
+            }
+        }
+        if write_tags {
+            for path in &paths {
+                let Some(entry) = library.get(path) else { continue };
+                let Some(tags) = loudness::replaygain_tags(entry.loudness.as_ref(), entry.album.as_ref()) else { continue };
+                if !path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("flac")) {
+                    untaggable += 1;
+                    continue;
+                }
+                match loudness::write_flac_replaygain(path, &tags) {
+                    Ok(true) => {
+                        tagged += 1;
+                        library.refresh_stamp(path);
+                    }
+                    Ok(false) => {}
+                    Err(e) => errors.push(format!("{}: {e}", path.display())),
+                }
+            }
+        }
+    }
+    library.save();
+    send(AnalysisEvent::Finished(AnalysisReport { library, analyzed, tagged, untaggable, errors, cancelled }));
+}
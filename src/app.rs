@@ -1,28 +1,48 @@
-use iced::widget::{button, column, container, row, scrollable, slider, text, text_input, Space, svg};
+use iced::widget::{button, canvas, column, container, pick_list, row, scrollable, slider, stack, text, text_input, Space, svg};
 use iced::{Element, Length, Result as IcedResult, Task, Subscription};
 use iced::widget::svg::Handle as SvgHandle;
-use std::fs;
+use iced::widget::image::Handle as ImageHandle;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicU64, Ordering};
-
-// Symphonia is used to probe duration for formats where rodio's Decoder
-// cannot determine it up-front (e.g., some MP3/streamable formats).
-// This enables the seekbar to work more reliably.
-use symphonia::core::formats::FormatOptions as SymFormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions as SymMetadataOptions;
-use symphonia::core::probe::Hint as SymHint;
-use symphonia::default::get_probe as sym_get_probe;
-use symphonia::core::codecs::DecoderOptions as SymDecoderOptions;
-use symphonia::default::get_codecs as sym_get_codecs;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::artwork;
+use crate::convolution::{self, ImpulseResponse};
+use crate::cue::TrackRange;
+use crate::engine::{
+    list_output_devices, opt_path, scan_audio_files, AudioEngine, AudioFile, BufferSizeChoice, ChannelSettings,
+    CompressorParam, CompressorSettings, CrossfeedPreset, CrossfeedSettings, DspChainSettings, DspEffects, EffectKind,
+    EngineEvent, OutputSettings, ResamplerQuality, ReverbSettings, SampleRateChoice, AUDIO_RETRY_INTERVAL, CROSSFEED_CUTOFF_RANGE,
+    CROSSFEED_FEED_RANGE, DEFAULT_DEVICE_LABEL, MAX_SPEED, MIN_SPEED, PREAMP_RANGE,
+};
+use crate::library::{LibraryCache, LibraryEntry};
+use crate::mpris::{Mpris, MprisCommand, MprisEvent, PlaybackStatus, PlayerState, SharedReceiver, TrackInfo};
+use crate::query::TrackQuery;
+use crate::remote::{self, RemoteCall, RemoteRequest, RemoteServer, RemoteSettings};
+use crate::silence::{self, SilenceTrimSettings};
+use crate::analysis::{self, AnalysisEvent};
+use crate::lyrics::{self, Lyrics};
+use crate::meter::{self, MeterDisplay};
+use crate::spectrum::{self, SpectrumAnalyzer, SpectrumResolution};
+use crate::waveform::{self, Waveform};
 
 pub fn run() -> IcedResult {
     iced::application("Rust Audio Player", update, view)
         .subscription(subscription)
         .theme(app_theme)
-        .run()
+        .run_with(|| {
+            let mut state = AudioPlayer::default();
+            // Kick off thumbnail and cached analysis loading for the folder restored
+            // from config, and reload the convolution impulse response
+            let task = Task::batch([
+                load_thumbnails(&mut state),
+                load_track_info(&state),
+                load_impulse_response_task(state.reverb_settings.ir_path.clone()),
+            ]);
+            (state, task)
+        })
 }
 
 fn app_theme(state: &AudioPlayer) -> iced::Theme {
@@ -38,11 +58,21 @@ async fn pick_folder_async() -> Option<PathBuf> {
         .map(|h| h.path().to_path_buf())
 }
 
+// Run blocking work (file IO, decoding) on a worker thread so the UI stays responsive.
+async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let (tx, rx) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.await.ok()
+}
+
 #[derive(Debug, Clone)]
 enum Message {
     ChooseFolder,
     FolderChosen(Option<PathBuf>),
     SelectTrack(usize),
+    PlayTrack(usize),
     TogglePlayPause,
     ToggleTheme,
     Stop,
@@ -52,211 +82,104 @@ enum Message {
     // Seek bar interactions
     SeekChanged(f32),
     SeekReleased,
-    // periodic UI refresh
+    VolumeChanged(f32),
+    // Playback events from the engine
+    Engine(EngineEvent),
+    // Remote control over D-Bus (MPRIS) and HTTP
+    Mpris(MprisEvent),
+    Remote(RemoteCall),
+    RemoteToggled(bool),
+    RemotePortChanged(String),
+    RemotePortSubmitted,
+    RemoteNewToken,
+    // Periodic audio output retry, only while the output is down
     Tick,
     None,
     // Equalizer
     ToggleEq,
     EqBandChanged(usize, f32),
     EqClose,
+    // Cover art loaded off the UI thread
+    NowPlayingArt(PathBuf, Option<ImageHandle>),
+    ThumbnailLoaded(PathBuf, Option<ImageHandle>),
+    // Lyrics pane
+    ToggleLyrics,
+    LyricsLoaded(PathBuf, Option<Lyrics>),
+    // Cached analysis results for the files of a folder
+    TrackInfoLoaded(Option<PathBuf>, HashMap<PathBuf, LibraryEntry>),
+    WaveformToggled(bool),
+    AnalyzeLibrary,
+    CancelAnalysis,
+    Analysis(AnalysisEvent),
+    SortBy(SortColumn),
+    ReplayGainTagsToggled(bool),
+    WaveformLoaded(PathBuf, Option<Arc<Waveform>>),
+    LyricClicked(usize),
+    // Playback speed
+    SpeedChanged(f32),
+    SpeedChangesPitch(bool),
+    ResetSpeed,
+    // Pitch shift (semitones, cents)
+    PitchSemitonesChanged(f32),
+    PitchCentsChanged(f32),
+    ResetPitch,
+    // Re-open audio output after an init failure
+    RetryAudio,
+    // Output device picker
+    RefreshDevices,
+    DeviceSelected(String),
+    // Output format settings panel
+    ToggleSettings,
+    SampleRateSelected(SampleRateChoice),
+    BufferSizeSelected(BufferSizeChoice),
+    ResamplerSelected(ResamplerQuality),
+    // Channel balance, mono downmix, L/R swap
+    BalanceChanged(f32),
+    MonoToggled(bool),
+    SwapChannelsToggled(bool),
+    // Headphone crossfeed
+    CrossfeedPresetSelected(CrossfeedPreset),
+    CrossfeedCutoffChanged(f32),
+    CrossfeedFeedChanged(f32),
+    // Dynamics (compressor/limiter) panel
+    ToggleDynamics,
+    CompressorParamChanged(CompressorParam, f32),
+    NightMode,
+    // Effect chain panel
+    ToggleEffects,
+    EffectEnabled(EffectKind, bool),
+    EffectBypassed(EffectKind, bool),
+    MoveEffect(usize, isize),
+    BypassAllToggled(bool),
+    PreampChanged(f32),
+    // Convolution (impulse response) stage
+    ChooseImpulseResponse,
+    ImpulseResponseChosen(Option<PathBuf>),
+    ImpulseResponseLoaded(PathBuf, Result<Arc<ImpulseResponse>, String>),
+    ReverbMixChanged(f32),
+    ReverbNormalizeToggled(bool),
+    // Spectrum analyzer
+    ToggleSpectrum,
+    SpectrumResolutionSelected(SpectrumResolution),
+    SpectrumFrame,
+    // A-B loop
+    SetLoopA,
+    SetLoopB,
+    ClearLoop,
+    ResetClipIndicators,
+    // Silence trimming; detection results carry the threshold they were made at
+    TrimSilenceToggled(bool),
+    SilenceThresholdChanged(f32),
+    SilenceThresholdReleased,
+    SilenceDetected(PathBuf, f32, Option<TrackRange>),
 }
 
-struct AudioFile {
-    name: String,
-    path: PathBuf,
-}
-
-struct AudioEngine {
-    stream: rodio::stream::OutputStream,
-    sink: Option<rodio::Sink>,
-    now_playing: Option<String>,
-    current_path: Option<PathBuf>,
-    duration: Option<Duration>,
-    start_instant: Option<Instant>,
-    paused_at: Option<Duration>,
-    position_offset: Duration,
-    // Equalizer state
-    eq: Arc<Equalizer>, // shared with UI for live updates
-}
-
-impl AudioEngine {
-    fn new() -> Result<Self, String> {
-        // Open the default output stream using the new rodio 0.21 API
-        let stream = rodio::OutputStreamBuilder::open_default_stream()
-            .map_err(|e| format!("Audio output error: {e}"))?;
-        Ok(Self {
-            stream,
-            sink: None,
-            now_playing: None,
-            current_path: None,
-            duration: None,
-            start_instant: None,
-            paused_at: None,
-            position_offset: Duration::ZERO,
-            eq: Arc::new(Equalizer::default()),
-        })
-    }
-
-    fn stop(&mut self) {
-        if let Some(sink) = self.sink.take() {
-            sink.stop();
-        }
-        self.now_playing = None;
-        self.current_path = None;
-        self.duration = None;
-        self.start_instant = None;
-        self.paused_at = None;
-        self.position_offset = Duration::ZERO;
-    }
-
-    fn play_file(&mut self, path: &Path) -> Result<(), String> {
-        self.play_from(path, Duration::ZERO, false)
-    }
-
-    fn play_from(&mut self, path: &Path, position: Duration, resume_paused: bool) -> Result<(), String> {
-        use rodio::Source as _;
-
-        if let Some(sink) = self.sink.take() { sink.stop(); }
-
-        let file = std::fs::File::open(path)
-            .map_err(|e| format!("Failed to open file: {e}"))?;
-        // Decoder::try_from(File) wraps in BufReader and sets byte_len for accurate seeking
-        let decoder = rodio::Decoder::try_from(file)
-            .map_err(|e| format!("Failed to decode audio: {e}"))?;
-
-        // Prefer rodio's duration, but if it's not available, try probing with symphonia.
-        // Avoid re-probing if we already know duration for the same track.
-        let same_track = self.current_path.as_ref().is_some_and(|p| p == path);
-        if !same_track || self.duration.is_none() {
-            self.duration = decoder.total_duration().or_else(|| probe_duration_with_symphonia(path));
-        }
-
-    // Apply EQ by wrapping the source
-    let source = decoder.skip_duration(position);
-    let source = EqSource::new(source, self.eq.clone());
-
-        // Create a sink we can control and append the (possibly skipped) source
-        let sink = rodio::Sink::connect_new(&self.stream.mixer());
-    sink.append(source);
-        self.sink = Some(sink);
-        self.now_playing = Some(
-            path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("Unknown")
-                .to_string(),
-        );
-        self.current_path = Some(path.to_path_buf());
-        self.position_offset = position;
-        self.paused_at = None;
-        self.start_instant = Some(Instant::now());
-
-        if resume_paused {
-            if let Some(s) = &self.sink { s.pause(); }
-        }
-
-        Ok(())
-    }
-
-    fn pause(&mut self) {
-        if let Some(s) = &self.sink {
-            if !s.is_paused() {
-                s.pause();
-                let pos = self.current_position();
-                self.paused_at = Some(pos);
-                self.start_instant = None;
-            }
-        }
-    }
-
-    fn resume(&mut self) {
-        if let Some(s) = &self.sink {
-            if s.is_paused() {
-                s.play();
-                if let Some(p) = self.paused_at.take() {
-                    self.position_offset = p;
-                }
-                self.start_instant = Some(Instant::now());
-            }
-        }
-    }
-
-    fn seek_to(&mut self, position: Duration) -> Result<(), String> {
-        let clamped = if let Some(d) = self.duration { position.min(d) } else { position };
-        if let Some(path) = self.current_path.clone() {
-            let was_paused = self.sink.as_ref().is_some_and(|s| s.is_paused());
-            // If position is close to current, do nothing
-            if (self.current_position().as_secs_f32() - clamped.as_secs_f32()).abs() < 0.01 { return Ok(()); }
-            self.play_from(&path, clamped, was_paused)
-        } else { Ok(()) }
-    }
-
-    fn is_playing(&self) -> bool {
-        if let Some(s) = &self.sink {
-            !s.is_paused() && !s.empty()
-        } else {
-            false
-        }
-    }
-
-    fn total_duration(&self) -> Option<Duration> {
-        self.duration
-    }
-
-    fn current_position(&self) -> Duration {
-        if let Some(paused) = self.paused_at {
-            paused
-        } else if let Some(start) = self.start_instant {
-            self.position_offset + start.elapsed()
-        } else {
-            self.position_offset
-        }
-    }
-}
-
-fn probe_duration_with_symphonia(path: &Path) -> Option<Duration> {
-    let mut hint = SymHint::new();
-    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
-    }
-    let file = std::fs::File::open(path).ok()?;
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
-    let probed = sym_get_probe()
-        .format(&hint, mss, &SymFormatOptions::default(), &SymMetadataOptions::default())
-        .ok()?;
-    let mut format = probed.format;
-    // Choose the default track or the first track with a sample rate.
-    let track = format
-        .default_track()
-        .cloned()
-        .or_else(|| format.tracks().iter().find(|t| t.codec_params.sample_rate.is_some()).cloned())?;
-
-    let params = &track.codec_params;
-    if let (Some(sr), Some(n_frames)) = (params.sample_rate, params.n_frames) {
-        let secs = n_frames as f64 / sr as f64;
-        return Some(Duration::from_secs_f64(secs));
-    }
-
-    // As a last resort, decode and count frames to compute duration.
-    let mut decoder = sym_get_codecs().make(params, &SymDecoderOptions::default()).ok()?;
-    let mut total_frames: u64 = 0;
-    let mut sr_opt = params.sample_rate;
-    let track_id = track.id;
-
-    while let Ok(packet) = format.next_packet() {
-        if packet.track_id() != track_id { continue; }
-        if let Ok(audio_buf) = decoder.decode(&packet) {
-            total_frames += audio_buf.frames() as u64;
-            let rate = audio_buf.spec().rate;
-            if sr_opt.is_none() { sr_opt = Some(rate); }
-        }
-    }
-
-    let sr = sr_opt?;
-    if total_frames > 0 {
-        return Some(Duration::from_secs_f64(total_frames as f64 / sr as f64));
-    }
-
-    None
+// Library analysis (loudness, tempo, key) running in the background
+struct AnalysisJob {
+    cancel: Arc<AtomicBool>,
+    done: usize,
+    total: usize,
+    current: String,
 }
 
 struct AudioPlayer {
@@ -278,16 +201,79 @@ struct AudioPlayer {
     // EQ UI state and bands (gain in dB)
     eq_visible: bool,
     eq_gains_db: [f32; 10],
+    // Balance / mono / swap settings, pushed to the engine on change
+    channel_settings: ChannelSettings,
+    crossfeed_settings: CrossfeedSettings,
+    dynamics_visible: bool,
+    compressor_settings: CompressorSettings,
+    // Effect chain order/enable/bypass and the gain stage's level
+    effects_visible: bool,
+    dsp_chain: DspChainSettings,
+    preamp_db: f32,
+    // Convolution settings and the loaded impulse response
+    reverb_settings: ReverbSettings,
+    impulse_response: Option<Arc<ImpulseResponse>>,
+    // Spectrum analyzer panel
+    spectrum_visible: bool,
+    spectrum: SpectrumAnalyzer,
+    // Peak/RMS meter readings, refreshed on every tick
+    levels: MeterDisplay,
+    // Waveform seek bar (optional) and the overview of the file it belongs to
+    waveform_enabled: bool,
+    waveform: Option<(PathBuf, Arc<Waveform>)>,
+    // Per-file analysis results, and the analysis job while it runs.
+    // `track_info` holds the up-to-date entries for the listed files.
+    library: LibraryCache,
+    track_info: HashMap<PathBuf, LibraryEntry>,
+    analysis_job: Option<AnalysisJob>,
+    // File list order
+    sort: SortColumn,
+    sort_descending: bool,
+    write_replaygain_tags: bool,
+    // Skip leading/trailing silence of whole-file tracks
+    silence_trim: SilenceTrimSettings,
+    // Output volume, 0..1
+    volume: f32,
+    // Event channel of the current engine, taken by the subscription once it
+    // runs; the id changes whenever the engine is recreated
+    engine_events: EngineEvents,
+    // MPRIS server (Linux); None where unsupported
+    mpris: Option<Mpris>,
+    // HTTP remote control, running while enabled (and the port was free)
+    remote_settings: RemoteSettings,
+    remote: Option<RemoteServer>,
+    remote_port_input: String,
+    // Track the now-playing art/lyrics belong to, and list thumbnails by path
+    now_playing_path: Option<PathBuf>,
+    now_playing_art: Option<ImageHandle>,
+    thumbnails: HashMap<PathBuf, ImageHandle>,
+    // Stops the thumbnail worker of the previous folder
+    thumbnails_cancel: Arc<AtomicBool>,
+    // Lyrics pane state; `lyrics_line` is the highlighted line we last scrolled to
+    lyrics_visible: bool,
+    lyrics: Option<Lyrics>,
+    lyrics_line: Option<usize>,
+    // Output devices offered in the picker, and the one chosen in settings
+    output_devices: Vec<String>,
+    output_device: Option<String>,
+    // When we last tried to open audio output after a failure
+    last_audio_retry: Option<Instant>,
+    // Output format settings and whether their panel is shown
+    settings_visible: bool,
+    output_settings: OutputSettings,
 }
 
 impl Default for AudioPlayer {
     fn default() -> Self {
         // Start with defaults, then try loading persisted config
+        let cfg = load_config();
+        let device = cfg.as_ref().and_then(|c| c.output_device.clone());
+        let output_settings = cfg.as_ref().map(|c| c.output).unwrap_or_default();
         let mut me = Self {
             folder: None,
             files: Vec::new(),
             selected: None,
-            audio: AudioEngine::new(),
+            audio: AudioEngine::new(device.as_deref(), output_settings),
             status: None,
             last_click: None,
             seek_value: 0.0,
@@ -298,19 +284,87 @@ impl Default for AudioPlayer {
             dark_mode: false,
             eq_visible: false,
             eq_gains_db: [0.0; 10],
+            channel_settings: ChannelSettings::default(),
+            crossfeed_settings: CrossfeedSettings::default(),
+            dynamics_visible: false,
+            compressor_settings: CompressorSettings::default(),
+            effects_visible: false,
+            dsp_chain: DspChainSettings::default(),
+            preamp_db: 0.0,
+            reverb_settings: ReverbSettings::default(),
+            impulse_response: None,
+            spectrum_visible: false,
+            spectrum: SpectrumAnalyzer::new(SpectrumResolution::default()),
+            levels: MeterDisplay::default(),
+            waveform_enabled: false,
+            waveform: None,
+            library: LibraryCache::load(),
+            track_info: HashMap::new(),
+            analysis_job: None,
+            sort: SortColumn::Name,
+            sort_descending: false,
+            write_replaygain_tags: false,
+            silence_trim: SilenceTrimSettings::default(),
+            volume: 1.0,
+            engine_events: EngineEvents::default(),
+            mpris: Mpris::start(),
+            remote_settings: RemoteSettings::default(),
+            remote: None,
+            remote_port_input: remote::DEFAULT_PORT.to_string(),
+            now_playing_path: None,
+            now_playing_art: None,
+            thumbnails: HashMap::new(),
+            thumbnails_cancel: Arc::new(AtomicBool::new(false)),
+            lyrics_visible: false,
+            lyrics: None,
+            lyrics_line: None,
+            output_devices: list_output_devices(),
+            output_device: device.clone(),
+            last_audio_retry: None,
+            settings_visible: false,
+            output_settings,
         };
-        if let Some(cfg) = load_config() {
+        me.engine_events.connect(&me.audio);
+        if let (Some(wanted), Ok(None)) = (&device, me.audio.as_ref().map(|e| e.active_device())) {
+            me.status = Some(format!("Output device \"{wanted}\" not found; using the default device."));
+        }
+        if let Some(cfg) = cfg {
             me.dark_mode = cfg.dark_mode;
             me.folder = cfg.last_folder;
             if let Some(eq) = cfg.eq {
                 me.eq_gains_db = eq;
             }
-            if let Ok(engine) = &mut me.audio { engine.eq.set_gains_db(me.eq_gains_db); }
+            me.channel_settings = cfg.channels;
+            me.crossfeed_settings = cfg.crossfeed;
+            me.compressor_settings = cfg.compressor;
+            me.dsp_chain = cfg.dsp_chain.normalized();
+            me.preamp_db = cfg.preamp_db;
+            me.reverb_settings = cfg.reverb;
+            me.spectrum = SpectrumAnalyzer::new(cfg.spectrum_resolution);
+            me.waveform_enabled = cfg.waveform_seek_bar;
+            me.write_replaygain_tags = cfg.write_replaygain_tags;
+            me.silence_trim = cfg.silence_trim;
+            me.volume = cfg.volume.clamp(0.0, 1.0);
+            me.remote_settings = cfg.remote;
+            me.remote_port_input = me.remote_settings.port.to_string();
+            if me.remote_settings.token.is_empty() {
+                me.remote_settings.token = remote::new_token();
+            }
+            if me.remote_settings.enabled {
+                me.restart_remote();
+            }
+            let volume = me.volume;
+            if let Ok(engine) = &mut me.audio {
+                engine.set_volume(volume);
+            }
+            if let Ok(engine) = &me.audio {
+                me.push_effect_settings(engine.effects());
+            }
             if let Some(folder) = me.folder.clone() {
                 let (files, err) = scan_audio_files(&folder);
                 me.files = files;
                 me.selected = if me.files.is_empty() { None } else { Some(0) };
-                me.status = err;
+                me.status = err.or(me.status);
             }
         }
         me
@@ -330,8 +384,11 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
             state.files = files;
             state.selected = if state.files.is_empty() { None } else { Some(0) };
             state.status = errors;
+            state.track_info.clear();
             // Persist last folder
-            save_config(&AppConfig { dark_mode: state.dark_mode, last_folder: state.folder.clone(), eq: Some(state.eq_gains_db) });
+            state.save_settings();
+            state.thumbnails.clear();
+            return Task::batch([load_thumbnails(state), load_track_info(state)]);
         }
         Message::FolderChosen(None) => {
             // user canceled
@@ -352,7 +409,7 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
                             let target_idx = filtered[pos];
                             if let Some(file) = state.files.get(target_idx) {
                                 state.selected = Some(target_idx);
-                                if let Err(e) = engine.play_file(&file.path) {
+                                if let Err(e) = engine.play_track(file) {
                                     state.status = Some(e);
                                 } else {
                                     state.status = Some(format!("Playing: {}", file.name));
@@ -376,7 +433,7 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
                                 let target_idx = filtered[pos + 1];
                                 if let Some(file) = state.files.get(target_idx) {
                                     state.selected = Some(target_idx);
-                                    if let Err(e) = engine.play_file(&file.path) {
+                                    if let Err(e) = engine.play_track(file) {
                                         state.status = Some(e);
                                     } else {
                                         state.status = Some(format!("Playing: {}", file.name));
@@ -408,38 +465,38 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
 
             if is_double {
                 // Double click: start playing the clicked item
-                if let Ok(engine) = &mut state.audio {
-                    if let Some(file) = state.files.get(idx) {
-                        if let Err(e) = engine.play_file(&file.path) {
-                            state.status = Some(e);
-                        } else {
-                            state.status = Some(format!("Playing: {}", file.name));
-                        }
-                    }
-                }
+                return update(state, Message::PlayTrack(idx));
             } else {
                 // Single click behavior
                 if let Ok(engine) = &mut state.audio {
-                    if let Some(sink) = &engine.sink {
-                        if engine.is_playing() {
-                            engine.pause();
-                            state.status = Some("Paused".into());
-                        } else if sink.is_paused() {
-                            engine.resume();
-                            state.status = Some("Resumed".into());
-                        }
+                    if engine.is_playing() {
+                        engine.pause();
+                        state.status = Some("Paused".into());
+                    } else if engine.is_paused() {
+                        engine.play();
+                        state.status = Some("Resumed".into());
                     }
                 }
             }
         }
+        Message::PlayTrack(idx) => {
+            if let (Ok(engine), Some(file)) = (&mut state.audio, state.files.get(idx)) {
+                state.selected = Some(idx);
+                if let Err(e) = engine.play_track(file) {
+                    state.status = Some(e);
+                } else {
+                    state.status = Some(format!("Playing: {}", file.name));
+                }
+            }
+        }
         Message::TogglePlayPause => {
             match &mut state.audio {
                 Ok(engine) => {
                     match &state.selected {
-                        Some(idx) if engine.sink.as_ref().map(|s| s.empty()).unwrap_or(true) => {
+                        Some(idx) if !engine.is_playing() && !engine.is_paused() => {
                             // No active audio in sink -> (re)start selected track
                             if let Some(file) = state.files.get(*idx) {
-                                if let Err(e) = engine.play_file(&file.path) {
+                                if let Err(e) = engine.play_track(file) {
                                     state.status = Some(e);
                                 } else {
                                     state.status = Some(format!("Playing: {}", file.name));
@@ -448,18 +505,16 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
                         }
                         _ => {
                             // Toggle pause/resume on existing sink, if any
-                            if let Some(s) = &engine.sink {
-                                if s.is_paused() {
-                                    engine.resume();
-                                    state.status = Some("Resumed".into());
-                                } else {
-                                    engine.pause();
-                                    state.status = Some("Paused".into());
-                                }
+                            if engine.is_paused() {
+                                engine.play();
+                                state.status = Some("Resumed".into());
+                            } else if engine.current_path().is_some() {
+                                engine.pause();
+                                state.status = Some("Paused".into());
                             } else if let Some(idx) = state.selected {
                                 // No sink yet, start playback of selected
                                 if let Some(file) = state.files.get(idx) {
-                                    if let Err(e) = engine.play_file(&file.path) {
+                                    if let Err(e) = engine.play_track(file) {
                                         state.status = Some(e);
                                     } else {
                                         state.status = Some(format!("Playing: {}", file.name));
@@ -472,12 +527,24 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
                     }
                 }
                 Err(e) => {
+                    // Try once more right away; if that works, start playback.
+                    let e = e.clone();
+                    if state.retry_audio() {
+                        return update(state, Message::TogglePlayPause);
+                    }
                     state.status = Some(format!(
-                        "Audio not initialized: {e}. Try restarting the app."
+                        "Audio not initialized: {e}. Press \"Retry audio\" once a device is available."
                     ));
                 }
             }
         }
+        Message::RetryAudio => {
+            if state.retry_audio() {
+                state.status = Some("Audio output ready.".into());
+            } else if let Err(e) = &state.audio {
+                state.status = Some(format!("Audio still unavailable: {e}"));
+            }
+        }
         Message::Stop => {
             if let Ok(engine) = &mut state.audio {
                 engine.stop();
@@ -486,7 +553,7 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
         }
         Message::ToggleTheme => {
             state.dark_mode = !state.dark_mode;
-            save_config(&AppConfig { dark_mode: state.dark_mode, last_folder: state.folder.clone(), eq: Some(state.eq_gains_db) });
+            state.save_settings();
         }
         Message::ToggleEq => {
             state.eq_visible = !state.eq_visible;
@@ -497,11 +564,18 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
                 let gain_db = (val - 0.5) * 24.0;
                 state.eq_gains_db[idx] = gain_db;
                 // Update engine's EQ immediately; restart current playback at same position to apply
-                if let Ok(engine) = &mut state.audio { engine.eq.set_gains_db(state.eq_gains_db); }
-                save_config(&AppConfig { dark_mode: state.dark_mode, last_folder: state.folder.clone(), eq: Some(state.eq_gains_db) });
+                if let Ok(engine) = &mut state.audio { engine.set_eq_gains(state.eq_gains_db); }
+                state.save_settings();
             }
         }
         Message::EqClose => { state.eq_visible = false; }
+        Message::VolumeChanged(volume) => {
+            state.volume = volume;
+            if let Ok(engine) = &mut state.audio {
+                engine.set_volume(volume);
+            }
+            state.save_settings();
+        }
         Message::SearchChanged(q) => {
             state.search_query = q;
             // Optionally, maintain selection if still visible. If not visible, keep it unchanged.
@@ -517,67 +591,1016 @@ fn update(state: &mut AudioPlayer, message: Message) -> Task<Message> {
                     engine.pause();
                 }
             }
-            state.is_seeking = true;
-        }
-        Message::SeekReleased => {
-            // Apply a single seek when the user releases the slider, then resume if needed.
-            if let Ok(engine) = &mut state.audio {
-                if let Some(total) = engine.total_duration() {
-                    let position = Duration::from_secs_f32(total.as_secs_f32() * state.seek_value);
-                    match engine.seek_to(position) {
-                        Ok(()) => {
-                            if state.pre_seek_was_playing {
-                                engine.resume();
-                            }
-                        }
-                        Err(e) => state.status = Some(e),
-                    }
-                }
+            state.is_seeking = true;
+        }
+        Message::SeekReleased => {
+            // Apply a single seek when the user releases the slider, then resume if needed.
+            if let Ok(engine) = &mut state.audio {
+                if let Some(total) = engine.total_duration() {
+                    let position = Duration::from_secs_f32(total.as_secs_f32() * state.seek_value);
+                    match engine.seek_to(position) {
+                        Ok(()) => {
+                            if state.pre_seek_was_playing {
+                                engine.play();
+                            }
+                        }
+                        Err(e) => state.status = Some(e),
+                    }
+                }
+            }
+            state.is_seeking = false;
+            state.last_seek_apply = None;
+            state.pre_seek_was_playing = false;
+        }
+        Message::Engine(event) => return handle_engine_event(state, event),
+        Message::Mpris(MprisEvent::Command(command)) => return handle_mpris_command(state, command),
+        Message::Mpris(MprisEvent::Failed(e)) => {
+            state.mpris = None;
+            state.status = Some(format!("Desktop media controls unavailable: {e}"));
+        }
+        Message::Remote(call) => return handle_remote_call(state, call),
+        Message::RemoteToggled(enabled) => {
+            state.remote_settings.enabled = enabled;
+            state.restart_remote();
+            state.save_settings();
+        }
+        Message::RemotePortChanged(input) => {
+            state.remote_port_input = input.chars().filter(char::is_ascii_digit).collect();
+        }
+        Message::RemotePortSubmitted => match state.remote_port_input.parse::<u16>() {
+            Ok(port) if port > 0 => {
+                state.remote_settings.port = port;
+                state.restart_remote();
+                state.save_settings();
+            }
+            _ => state.status = Some("Enter a port between 1 and 65535.".into()),
+        },
+        Message::RemoteNewToken => {
+            state.remote_settings.token = remote::new_token();
+            state.restart_remote();
+            state.save_settings();
+        }
+        Message::Tick => {
+            if let Some(msg) = state.audio.as_mut().ok().and_then(|e| e.recover_lost_device()) {
+                state.status = Some(msg);
+            }
+            // Audio failed to start: keep retrying in the background
+            if state.audio.is_err() && state.last_audio_retry.is_none_or(|t| t.elapsed() >= AUDIO_RETRY_INTERVAL) && state.retry_audio() {
+                state.status = Some("Audio output ready.".into());
+            }
+        }
+        Message::NowPlayingArt(path, art) => {
+            // Ignore results for a track that is no longer current
+            if state.now_playing_path.as_ref() == Some(&path) {
+                state.now_playing_art = art;
+            }
+        }
+        Message::TrackInfoLoaded(folder, track_info) => {
+            // Ignore results for a folder that is no longer shown
+            if state.folder == folder {
+                state.track_info = track_info;
+            }
+        }
+        Message::ThumbnailLoaded(path, art) => {
+            if let Some(art) = art {
+                state.thumbnails.insert(path, art);
+            }
+        }
+        Message::ToggleLyrics => {
+            state.lyrics_visible = !state.lyrics_visible;
+            // Force a re-scroll to the current line when the pane opens
+            state.lyrics_line = None;
+        }
+        Message::LyricsLoaded(path, lyrics) => {
+            if state.now_playing_path.as_ref() == Some(&path) {
+                state.lyrics = lyrics;
+                state.lyrics_line = None;
+            }
+        }
+        Message::WaveformToggled(enabled) => {
+            state.waveform_enabled = enabled;
+            state.save_settings();
+            let current = state.audio.as_ref().ok().and_then(|e| e.current_path().map(|p| p.to_path_buf()));
+            if let Some(path) = current.filter(|p| enabled && state.waveform.as_ref().is_none_or(|(w, _)| w != p)) {
+                return load_waveform_task(path);
+            }
+        }
+        Message::AnalyzeLibrary => {
+            if state.analysis_job.is_none() {
+                return start_analysis_job(state);
+            }
+        }
+        Message::CancelAnalysis => {
+            if let Some(job) = &state.analysis_job {
+                job.cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        Message::Analysis(AnalysisEvent::Progress { done, total, current }) => {
+            if let Some(job) = &mut state.analysis_job {
+                job.done = done;
+                job.total = total;
+                job.current = current;
+            }
+        }
+        Message::Analysis(AnalysisEvent::Finished(report)) => {
+            state.analysis_job = None;
+            state.library = report.library;
+            let mut summary = if report.cancelled {
+                format!("Analysis cancelled after {} tracks.", report.analyzed)
+            } else {
+                format!("Analysis finished: {} tracks analyzed", report.analyzed)
+            };
+            if state.write_replaygain_tags && !report.cancelled {
+                summary.push_str(&format!(", ReplayGain tags written to {} files", report.tagged));
+                if report.untaggable > 0 {
+                    summary.push_str(&format!(" ({} skipped: only FLAC files can be tagged)", report.untaggable));
+                }
+            }
+            if !report.errors.is_empty() {
+                summary.push_str(&format!("; {} failed ({})", report.errors.len(), report.errors.join("; ")));
+            }
+            state.status = Some(summary);
+            return load_track_info(state);
+        }
+        Message::SortBy(column) => {
+            // Clicking the current column again flips the direction
+            state.sort_descending = state.sort == column && !state.sort_descending;
+            state.sort = column;
+        }
+        Message::ReplayGainTagsToggled(enabled) => {
+            state.write_replaygain_tags = enabled;
+            state.save_settings();
+        }
+        Message::WaveformLoaded(path, waveform) => {
+            if state.now_playing_path.as_ref() == Some(&path) {
+                state.waveform = waveform.map(|w| (path, w));
+            }
+        }
+        Message::LyricClicked(idx) => {
+            let target = state.lyrics.as_ref().filter(|l| l.synced).and_then(|l| l.lines.get(idx)).map(|l| l.time);
+            if let (Some(target), Ok(engine)) = (target, &mut state.audio) {
+                match engine.seek_to(target) {
+                    Ok(()) => state.status = Some(format!("Jumped to {}", format_time(target))),
+                    Err(e) => state.status = Some(e),
+                }
+            }
+        }
+        Message::SetLoopA => match &mut state.audio {
+            Ok(engine) if engine.current_path().is_some() => {
+                let a = engine.current_position();
+                // Keep B only if it is still after the new A
+                let (_, b) = engine.loop_points();
+                engine.set_loop_points(Some(a), b.filter(|b| *b > a));
+                state.status = Some(format!("Loop start set at {}", format_time(a)));
+            }
+            _ => {}
+        },
+        Message::SetLoopB => match &mut state.audio {
+            Ok(engine) if engine.current_path().is_some() => {
+                let b = engine.current_position();
+                let a = engine.loop_points().0.unwrap_or(Duration::ZERO);
+                if b > a {
+                    engine.set_loop_points(Some(a), Some(b));
+                    state.status = Some(format!("Looping {} – {}", format_time(a), format_time(b)));
+                } else {
+                    state.status = Some("Loop end must be after loop start.".into());
+                }
+            }
+            _ => {}
+        },
+        Message::SpeedChanged(factor) => {
+            if let Ok(engine) = &mut state.audio {
+                engine.set_speed(factor);
+            }
+        }
+        Message::SpeedChangesPitch(changes_pitch) => {
+            let result = match &mut state.audio {
+                Ok(engine) => engine.set_speed_changes_pitch(changes_pitch),
+                Err(_) => Ok(()),
+            };
+            if let Err(e) = result {
+                state.status = Some(e);
+            }
+        }
+        Message::ResetSpeed => {
+            if let Ok(engine) = &mut state.audio {
+                engine.set_speed(1.0);
+            }
+        }
+        Message::PitchSemitonesChanged(semitones) => {
+            if let Ok(engine) = &mut state.audio {
+                let (_, cents) = engine.pitch().snapshot();
+                engine.pitch().set(semitones.round() as i32, cents);
+            }
+        }
+        Message::PitchCentsChanged(cents) => {
+            if let Ok(engine) = &mut state.audio {
+                let (semitones, _) = engine.pitch().snapshot();
+                engine.pitch().set(semitones, cents.round());
+            }
+        }
+        Message::ResetPitch => {
+            if let Ok(engine) = &mut state.audio {
+                engine.pitch().set(0, 0.0);
+            }
+        }
+        Message::RefreshDevices => {
+            state.output_devices = list_output_devices();
+        }
+        Message::DeviceSelected(name) => {
+            let name = if name == DEFAULT_DEVICE_LABEL { None } else { Some(name) };
+            if let Ok(engine) = &mut state.audio {
+                match engine.switch_device(name.clone()) {
+                    Ok(()) => {
+                        state.status = Some(match engine.active_device() {
+                            Some(active) if Some(active) == name.as_deref() => format!("Output: {active}"),
+                            _ => "Output: system default".into(),
+                        });
+                        state.output_device = name;
+                        state.save_settings();
+                    }
+                    Err(e) => state.status = Some(e),
+                }
+            }
+        }
+        Message::ToggleSettings => {
+            state.settings_visible = !state.settings_visible;
+        }
+        Message::SampleRateSelected(choice) => {
+            let output = OutputSettings { sample_rate: choice, ..state.output_settings };
+            apply_output_settings(state, output);
+        }
+        Message::BufferSizeSelected(choice) => {
+            let output = OutputSettings { buffer_size: choice, ..state.output_settings };
+            apply_output_settings(state, output);
+        }
+        Message::ResamplerSelected(quality) => {
+            let output = OutputSettings { resampler: quality, ..state.output_settings };
+            apply_output_settings(state, output);
+        }
+        Message::BalanceChanged(balance) => {
+            let settings = ChannelSettings { balance, ..state.channel_settings };
+            apply_channel_settings(state, settings);
+        }
+        Message::MonoToggled(mono) => {
+            let settings = ChannelSettings { mono, ..state.channel_settings };
+            apply_channel_settings(state, settings);
+        }
+        Message::SwapChannelsToggled(swap) => {
+            let settings = ChannelSettings { swap, ..state.channel_settings };
+            apply_channel_settings(state, settings);
+        }
+        Message::CrossfeedPresetSelected(preset) => {
+            let (cutoff_hz, feed_db) = preset.params().unwrap_or((state.crossfeed_settings.cutoff_hz, state.crossfeed_settings.feed_db));
+            let settings = CrossfeedSettings { preset, cutoff_hz, feed_db };
+            apply_crossfeed_settings(state, settings);
+        }
+        Message::CrossfeedCutoffChanged(cutoff_hz) => {
+            let settings = CrossfeedSettings { preset: CrossfeedPreset::Custom, cutoff_hz: cutoff_hz.round(), ..state.crossfeed_settings };
+            apply_crossfeed_settings(state, settings);
+        }
+        Message::CrossfeedFeedChanged(feed_db) => {
+            let settings = CrossfeedSettings { preset: CrossfeedPreset::Custom, feed_db, ..state.crossfeed_settings };
+            apply_crossfeed_settings(state, settings);
+        }
+        Message::ToggleDynamics => {
+            state.dynamics_visible = !state.dynamics_visible;
+        }
+        Message::CompressorParamChanged(param, value) => {
+            let mut settings = state.compressor_settings;
+            *param.field(&mut settings) = value;
+            apply_compressor_settings(state, settings);
+        }
+        Message::NightMode => {
+            apply_compressor_settings(state, CompressorSettings::night_mode());
+            update_dsp_chain(state, |chain| {
+                if let Some(slot) = chain.slot_mut(EffectKind::Compressor) {
+                    slot.enabled = true;
+                    slot.bypass = false;
+                }
+            });
+            state.status = Some("Night mode on: quiet passages raised, loud peaks tamed.".into());
+        }
+        Message::ToggleEffects => {
+            state.effects_visible = !state.effects_visible;
+        }
+        Message::EffectEnabled(kind, enabled) => {
+            update_dsp_chain(state, |chain| {
+                if let Some(slot) = chain.slot_mut(kind) { slot.enabled = enabled; }
+            });
+        }
+        Message::EffectBypassed(kind, bypass) => {
+            update_dsp_chain(state, |chain| {
+                if let Some(slot) = chain.slot_mut(kind) { slot.bypass = bypass; }
+            });
+        }
+        Message::MoveEffect(index, delta) => {
+            update_dsp_chain(state, |chain| {
+                let target = index.checked_add_signed(delta).filter(|t| *t < chain.slots.len());
+                if let Some(target) = target.filter(|_| index < chain.slots.len()) {
+                    chain.slots.swap(index, target);
+                }
+            });
+        }
+        Message::BypassAllToggled(bypass_all) => {
+            update_dsp_chain(state, |chain| chain.bypass_all = bypass_all);
+        }
+        Message::PreampChanged(gain_db) => {
+            state.preamp_db = gain_db;
+            if let Ok(engine) = &state.audio {
+                engine.effects().preamp.set_gain_db(gain_db);
+            }
+            state.save_settings();
+        }
+        Message::ChooseImpulseResponse => {
+            return Task::perform(
+                async {
+                    rfd::AsyncFileDialog::new()
+                        .set_title("Choose Impulse Response")
+                        .add_filter("WAV", &["wav", "wave"])
+                        .pick_file()
+                        .await
+                        .map(|h| h.path().to_path_buf())
+                },
+                Message::ImpulseResponseChosen,
+            );
+        }
+        Message::ImpulseResponseChosen(path) => {
+            if let Some(path) = path {
+                state.status = Some(format!("Loading impulse response {}…", path.display()));
+                return load_impulse_response_task(Some(path));
+            }
+        }
+        Message::ImpulseResponseLoaded(path, Ok(ir)) => {
+            state.status = Some(format!(
+                "Impulse response: {} ({} ch, {:.2} s)",
+                path.file_name().and_then(|n| n.to_str()).unwrap_or("?"),
+                ir.channels.len(),
+                ir.duration_secs()
+            ));
+            state.reverb_settings.ir_path = Some(path);
+            state.impulse_response = Some(ir.clone());
+            if let Ok(engine) = &state.audio {
+                engine.effects().reverb.set_impulse_response(Some(ir));
+            }
+            state.save_settings();
+        }
+        Message::ImpulseResponseLoaded(path, Err(e)) => {
+            state.status = Some(format!("{}: {e}", path.display()));
+        }
+        Message::ReverbMixChanged(mix) => {
+            let settings = ReverbSettings { mix, ..state.reverb_settings.clone() };
+            apply_reverb_settings(state, settings);
+        }
+        Message::ReverbNormalizeToggled(normalize) => {
+            let settings = ReverbSettings { normalize, ..state.reverb_settings.clone() };
+            apply_reverb_settings(state, settings);
+        }
+        Message::ToggleSpectrum => {
+            state.spectrum_visible = !state.spectrum_visible;
+        }
+        Message::SpectrumResolutionSelected(resolution) => {
+            state.spectrum = SpectrumAnalyzer::new(resolution);
+            state.save_settings();
+        }
+        Message::SpectrumFrame => {
+            if let Ok(engine) = &state.audio {
+                state.spectrum.update(engine.tap());
+            }
+            // Only redraw; skip the now-playing sync below
+            return Task::none();
+        }
+        Message::ResetClipIndicators => {
+            state.levels.reset_clips();
+        }
+        Message::TrimSilenceToggled(enabled) => {
+            state.silence_trim.enabled = enabled;
+            state.save_settings();
+            if enabled {
+                return detect_current_silence(state);
+            }
+            if let Err(e) = state.audio.as_mut().map_or(Ok(()), |engine| engine.set_trim(None)) {
+                state.status = Some(e);
+            }
+        }
+        Message::SilenceThresholdChanged(db) => {
+            state.silence_trim.threshold_db = db;
+        }
+        Message::SilenceThresholdReleased => {
+            state.save_settings();
+            if state.silence_trim.enabled {
+                return detect_current_silence(state);
+            }
+        }
+        Message::SilenceDetected(path, threshold_db, span) => {
+            let wanted = state.silence_trim.enabled && state.silence_trim.threshold_db == threshold_db;
+            let result = match &mut state.audio {
+                Ok(engine) if wanted && engine.current_path() == Some(path.as_path()) => engine.set_trim(span),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                state.status = Some(e);
+            }
+        }
+        Message::ClearLoop => {
+            if let Ok(engine) = &mut state.audio {
+                engine.set_loop_points(None, None);
+            }
+            state.status = Some("Loop cleared.".into());
+        }
+        Message::None => {}
+    }
+    // Drop the level bars once playback pauses or stops; no events come in then
+    if let Some(engine) = state.audio.as_ref().ok().filter(|e| !e.is_playing()) {
+        state.levels.update(engine.meter(), false);
+    }
+    sync_mpris(state);
+    sync_remote(state);
+    // Playback may have moved to another track; fetch its art/lyrics in the background.
+    Task::batch([sync_now_playing(state), follow_lyrics(state)])
+}
+
+// Turn a D-Bus request into the Message the matching control would send.
+fn handle_mpris_command(state: &mut AudioPlayer, command: MprisCommand) -> Task<Message> {
+    let (playing, position, total) = match &state.audio {
+        Ok(engine) => (engine.is_playing(), engine.current_position(), engine.total_duration()),
+        Err(_) => (false, Duration::ZERO, None),
+    };
+    let seek = |state: &mut AudioPlayer, target: Duration| match total {
+        // Seeking past the end moves on, as the spec asks
+        Some(total) if target > total => update(state, Message::NextTrack),
+        _ => seek_current_track(state, target),
+    };
+    let message = match command {
+        MprisCommand::PlayPause => Message::TogglePlayPause,
+        MprisCommand::Play if !playing => Message::TogglePlayPause,
+        MprisCommand::Pause if playing => Message::TogglePlayPause,
+        MprisCommand::Play | MprisCommand::Pause => return Task::none(),
+        MprisCommand::Stop => Message::Stop,
+        MprisCommand::Next => Message::NextTrack,
+        MprisCommand::Previous => Message::PrevTrack,
+        MprisCommand::GoTo(idx) => Message::PlayTrack(idx),
+        MprisCommand::SetVolume(volume) => Message::VolumeChanged(volume.clamp(0.0, 1.0) as f32),
+        // A rate of zero means pause
+        MprisCommand::SetRate(rate) if rate <= 0.0 && playing => Message::TogglePlayPause,
+        MprisCommand::SetRate(rate) if rate <= 0.0 => return Task::none(),
+        MprisCommand::SetRate(rate) => Message::SpeedChanged((rate as f32).clamp(MIN_SPEED, MAX_SPEED)),
+        MprisCommand::Seek(offset_us) => {
+            let offset = Duration::from_micros(offset_us.unsigned_abs());
+            let target = if offset_us < 0 { position.saturating_sub(offset) } else { position + offset };
+            return seek(state, target);
+        }
+        MprisCommand::SetPosition(idx, target) if current_index(state) == Some(idx) => return seek(state, target),
+        MprisCommand::SetPosition(..) => return Task::none(),
+    };
+    update(state, message)
+}
+
+// Seek the way the seek bar does: drag to the spot, then release.
+fn seek_current_track(state: &mut AudioPlayer, target: Duration) -> Task<Message> {
+    match state.audio.as_ref().ok().and_then(|e| e.total_duration()) {
+        Some(total) if !total.is_zero() => {
+            let task = update(state, Message::SeekChanged(target.as_secs_f32() / total.as_secs_f32()));
+            Task::batch([task, update(state, Message::SeekReleased)])
+        }
+        _ => Task::none(),
+    }
+}
+
+// Answer an HTTP API call; commands go through the same Messages as the controls.
+fn handle_remote_call(state: &mut AudioPlayer, call: RemoteCall) -> Task<Message> {
+    let (task, reply) = match &call.request {
+        RemoteRequest::Status => (Task::none(), Ok(remote_status(state))),
+        RemoteRequest::NowPlaying => (Task::none(), Ok(remote_now_playing(state))),
+        RemoteRequest::Queue => {
+            let queue = compute_filtered_indices(state).into_iter().map(|i| remote_track(state, i)).collect();
+            (Task::none(), Ok(serde_json::Value::Array(queue)))
+        }
+        RemoteRequest::Search(q) => {
+            // Same syntax as the search box, over the whole folder
+            let query = TrackQuery::parse(q);
+            let found = (0..state.files.len())
+                .filter(|&i| {
+                    let tempo_key = state.track_info.get(&state.files[i].path).and_then(|e| e.tempo_key);
+                    query.matches(&state.files[i].name, tempo_key.as_ref())
+                })
+                .map(|i| remote_track(state, i))
+                .collect();
+            (Task::none(), Ok(serde_json::Value::Array(found)))
+        }
+        request => match remote_command(state, request) {
+            Ok(task) => (task, Ok(remote_status(state))),
+            Err(e) => (Task::none(), Err(e)),
+        },
+    };
+    call.reply(reply);
+    task
+}
+
+fn remote_command(state: &mut AudioPlayer, request: &RemoteRequest) -> Result<Task<Message>, String> {
+    let playing = state.audio.as_ref().is_ok_and(|e| e.is_playing());
+    let has_track = state.audio.as_ref().is_ok_and(|e| e.current_path().is_some());
+    let message = match request {
+        RemoteRequest::Play if playing => return Ok(Task::none()),
+        RemoteRequest::Pause if !playing => return Ok(Task::none()),
+        RemoteRequest::Play | RemoteRequest::Pause | RemoteRequest::Toggle => Message::TogglePlayPause,
+        RemoteRequest::Stop => Message::Stop,
+        RemoteRequest::Next => Message::NextTrack,
+        RemoteRequest::Previous => Message::PrevTrack,
+        RemoteRequest::Seek(_) if !has_track => return Err("Nothing is playing".into()),
+        RemoteRequest::Seek(secs) if secs.is_finite() && *secs >= 0.0 => {
+            return Ok(seek_current_track(state, Duration::from_secs_f64(*secs)));
+        }
+        RemoteRequest::Seek(_) => return Err("Position must be a number of seconds, 0 or more".into()),
+        RemoteRequest::Volume(volume) if (0.0..=1.0).contains(volume) => Message::VolumeChanged(*volume as f32),
+        RemoteRequest::Volume(_) => return Err("Volume must be between 0 and 1".into()),
+        RemoteRequest::EqGains(gains) => {
+            let gains: [f32; 10] = gains.as_slice().try_into().map_err(|_| "Expected 10 EQ gains in dB".to_string())?;
+            set_eq_bands(state, gains);
+            return Ok(Task::none());
+        }
+        RemoteRequest::EqPreset(name) => {
+            let gains = crate::engine::eq_preset(name).ok_or_else(|| format!("Unknown EQ preset \"{name}\""))?;
+            set_eq_bands(state, gains);
+            return Ok(Task::none());
+        }
+        RemoteRequest::Status | RemoteRequest::NowPlaying | RemoteRequest::Queue | RemoteRequest::Search(_) => {
+            return Ok(Task::none());
+        }
+    };
+    Ok(update(state, message))
+}
+
+// Move every EQ slider to the given gains (clamped to ±12 dB) in one go
+fn set_eq_bands(state: &mut AudioPlayer, gains: [f32; 10]) {
+    state.eq_gains_db = gains.map(|gain| gain.clamp(-12.0, 12.0));
+    if let Ok(engine) = &mut state.audio {
+        engine.set_eq_gains(state.eq_gains_db);
+    }
+    state.save_settings();
+}
+
+fn remote_track(state: &AudioPlayer, index: usize) -> serde_json::Value {
+    let Some(file) = state.files.get(index) else { return serde_json::Value::Null };
+    let tempo_key = state.track_info.get(&file.path).and_then(|e| e.tempo_key);
+    serde_json::json!({
+        "index": index,
+        "name": file.name,
+        "path": file.path.display().to_string(),
+        "bpm": tempo_key.and_then(|t| t.bpm),
+        "key": tempo_key.and_then(|t| t.key).map(|k| k.to_string()),
+    })
+}
+
+fn remote_now_playing(state: &AudioPlayer) -> serde_json::Value {
+    let Ok(engine) = &state.audio else { return serde_json::Value::Null };
+    if engine.current_path().is_none() {
+        return serde_json::Value::Null;
+    }
+    serde_json::json!({
+        "track": current_index(state).map(|i| remote_track(state, i)),
+        "position": round_secs(engine.current_position()),
+        "duration": engine.total_duration().map(round_secs),
+    })
+}
+
+fn remote_status(state: &AudioPlayer) -> serde_json::Value {
+    let (playback, speed) = match &state.audio {
+        Ok(engine) if engine.is_playing() => ("playing", engine.speed().factor()),
+        Ok(engine) if engine.is_paused() => ("paused", engine.speed().factor()),
+        Ok(engine) => ("stopped", engine.speed().factor()),
+        Err(_) => ("unavailable", 1.0),
+    };
+    serde_json::json!({
+        "state": playback,
+        "now_playing": remote_now_playing(state),
+        "volume": state.volume,
+        "speed": speed,
+        "eq": state.eq_gains_db,
+    })
+}
+
+// Seconds with millisecond precision
+fn round_secs(d: Duration) -> f64 {
+    (d.as_secs_f64() * 1000.0).round() / 1000.0
+}
+
+// Push state changes to the remote control's event streams, if anyone listens.
+fn sync_remote(state: &mut AudioPlayer) {
+    if !state.remote.as_ref().is_some_and(|r| r.has_listeners()) {
+        return;
+    }
+    let status = remote_status(state);
+    if let Some(remote) = &mut state.remote {
+        remote.publish(status);
+    }
+}
+
+// Publish the current player state to MPRIS clients.
+fn sync_mpris(state: &mut AudioPlayer) {
+    if state.mpris.is_none() {
+        return;
+    }
+    let order = compute_filtered_indices(state);
+    let current_idx = current_index(state);
+    let mut player = PlayerState {
+        volume: state.volume as f64,
+        rate: 1.0,
+        min_rate: MIN_SPEED as f64,
+        max_rate: MAX_SPEED as f64,
+        ..PlayerState::default()
+    };
+    if let Ok(engine) = &state.audio {
+        player.rate = engine.speed().factor() as f64;
+        if engine.current_path().is_some() {
+            player.status = if engine.is_playing() {
+                PlaybackStatus::Playing
+            } else if engine.is_paused() {
+                PlaybackStatus::Paused
+            } else {
+                PlaybackStatus::Stopped
+            };
+            player.position = engine.current_position();
+            player.current = current_idx.and_then(|i| {
+                let mut track = TrackInfo::new(i, state.files.get(i)?);
+                track.length = engine.total_duration();
+                Some(track)
+            });
+        }
+    }
+    let pos = current_idx.and_then(|i| order.iter().position(|&x| x == i));
+    player.can_go_next = pos.is_some_and(|p| p + 1 < order.len());
+    player.can_go_previous = player.current.is_some();
+    let Some(mpris) = &mut state.mpris else { return };
+    player.tracks = mpris.track_list(&order, &state.files);
+    mpris.update(player);
+}
+
+fn handle_engine_event(state: &mut AudioPlayer, event: EngineEvent) -> Task<Message> {
+    match event {
+        EngineEvent::PositionChanged(_) => {
+            if let Ok(engine) = &state.audio {
+                state.levels.update(engine.meter(), engine.is_playing());
+            }
+            // Ten times a second: only the lyrics follow the position
+            return follow_lyrics(state);
+        }
+        EngineEvent::TrackEnded => advance_after_track_end(state),
+        EngineEvent::Error(e) => {
+            state.status = Some(e);
+            if let Some(msg) = state.audio.as_mut().ok().and_then(|e| e.recover_lost_device()) {
+                state.status = Some(msg);
+            }
+        }
+        // The view reads the track and device from the engine; the redraw is enough
+        EngineEvent::TrackStarted | EngineEvent::DeviceChanged(_) => {}
+    }
+    // Tracks change here without any other message (auto-advance, CUE handoff)
+    sync_mpris(state);
+    sync_remote(state);
+    Task::batch([sync_now_playing(state), follow_lyrics(state)])
+}
+
+// Auto-advance to the next visible track, or stop after the last one.
+fn advance_after_track_end(state: &mut AudioPlayer) {
+    let current_idx = current_index(state).or(state.selected);
+    let filtered = compute_filtered_indices(state);
+    let Ok(engine) = &mut state.audio else { return };
+    // A stale event from a track that has since been replaced
+    if !engine.track_ended() {
+        return;
+    }
+    let Some(pos) = current_idx.and_then(|idx| filtered.iter().position(|&x| x == idx)) else { return };
+    let Some(&target_idx) = filtered.get(pos + 1) else {
+        // Reached the end, stop and clear.
+        engine.stop();
+        state.status = Some("Playback finished.".into());
+        return;
+    };
+    let Some(file) = state.files.get(target_idx) else { return };
+    state.selected = Some(target_idx);
+    if engine.can_continue_into(file) {
+        engine.continue_into(file);
+        state.status = Some(format!("Playing: {}", file.name));
+    } else if let Err(e) = engine.play_track(file) {
+        state.status = Some(e);
+    } else {
+        state.status = Some(format!("Playing: {}", file.name));
+    }
+}
+
+// Store new output settings and reopen the stream with them.
+fn apply_output_settings(state: &mut AudioPlayer, output: OutputSettings) {
+    state.output_settings = output;
+    if let Ok(engine) = &mut state.audio {
+        state.status = Some(match engine.set_output_settings(output) {
+            Ok(()) => format!("Output format: {}", engine.format_summary()),
+            Err(e) => e,
+        });
+    }
+    state.save_settings();
+}
+
+fn apply_channel_settings(state: &mut AudioPlayer, settings: ChannelSettings) {
+    state.channel_settings = settings;
+    if let Ok(engine) = &state.audio {
+        engine.effects().channel_mix.set(settings);
+    }
+    state.save_settings();
+}
+
+fn apply_crossfeed_settings(state: &mut AudioPlayer, settings: CrossfeedSettings) {
+    state.crossfeed_settings = settings;
+    if let Ok(engine) = &state.audio {
+        engine.effects().crossfeed.set(settings);
+    }
+    state.save_settings();
+}
+
+// Edit the effect chain, push it to the engine and persist it.
+fn update_dsp_chain(state: &mut AudioPlayer, edit: impl FnOnce(&mut DspChainSettings)) {
+    edit(&mut state.dsp_chain);
+    if let Ok(engine) = &state.audio {
+        engine.effects().chain.set(state.dsp_chain.clone());
+    }
+    state.save_settings();
+}
+
+fn apply_reverb_settings(state: &mut AudioPlayer, settings: ReverbSettings) {
+    state.reverb_settings = settings;
+    if let Ok(engine) = &state.audio {
+        engine.effects().reverb.set(state.reverb_settings.clone());
+    }
+    state.save_settings();
+}
+
+// Decode an impulse response on a worker thread.
+fn load_impulse_response_task(path: Option<PathBuf>) -> Task<Message> {
+    let Some(path) = path else { return Task::none() };
+    Task::perform(
+        async move {
+            let result = run_blocking({
+                let path = path.clone();
+                move || convolution::load_impulse_response(&path).map(Arc::new)
+            })
+            .await
+            .unwrap_or_else(|| Err("Impulse response loader stopped".into()));
+            (path, result)
+        },
+        |(path, result)| Message::ImpulseResponseLoaded(path, result),
+    )
+}
+
+fn apply_compressor_settings(state: &mut AudioPlayer, settings: CompressorSettings) {
+    state.compressor_settings = settings;
+    if let Ok(engine) = &state.audio {
+        engine.effects().compressor.set(settings);
+    }
+    state.save_settings();
+}
+
+// Start loading artwork and lyrics whenever the engine's current track changes.
+fn sync_now_playing(state: &mut AudioPlayer) -> Task<Message> {
+    let current = state.audio.as_ref().ok().and_then(|e| e.current_path().map(|p| p.to_path_buf()));
+    if current == state.now_playing_path {
+        return Task::none();
+    }
+    state.now_playing_path = current.clone();
+    state.now_playing_art = None;
+    state.lyrics = None;
+    state.lyrics_line = None;
+    let Some(path) = current else { return Task::none() };
+    let waveform = if state.waveform_enabled { load_waveform_task(path.clone()) } else { Task::none() };
+    let silence = if state.silence_trim.enabled { detect_silence_task(path.clone(), state.silence_trim.threshold_db) } else { Task::none() };
+    let art = Task::perform(
+        {
+            let path = path.clone();
+            async move {
+                let art = run_blocking({
+                    let path = path.clone();
+                    move || artwork::load_artwork(&path, artwork::NOW_PLAYING_SIZE)
+                })
+                .await
+                .flatten();
+                (path, art)
+            }
+        },
+        |(path, art)| Message::NowPlayingArt(path, art),
+    );
+    let lyrics = Task::perform(
+        async move {
+            let lyrics = run_blocking({
+                let path = path.clone();
+                move || lyrics::load_lyrics(&path)
+            })
+            .await
+            .flatten();
+            (path, lyrics)
+        },
+        |(path, lyrics)| Message::LyricsLoaded(path, lyrics),
+    );
+    Task::batch([art, lyrics, waveform, silence])
+}
+
+// Pick up the cached analysis results for the listed files. Checking that the
+// files haven't changed stats each one, so it runs off the UI thread.
+fn load_track_info(state: &AudioPlayer) -> Task<Message> {
+    let folder = state.folder.clone();
+    let paths: Vec<PathBuf> = state.files.iter().map(|f| f.path.clone()).collect();
+    let library = state.library.clone();
+    Task::perform(
+        async move {
+            let track_info = run_blocking(move || {
+                paths.into_iter().filter_map(|path| Some((path.clone(), library.get(&path)?.clone()))).collect()
+            })
+            .await
+            .unwrap_or_default();
+            (folder, track_info)
+        },
+        |(folder, track_info)| Message::TrackInfoLoaded(folder, track_info),
+    )
+}
+
+// Analyze every file in the list on a worker thread, streaming progress back.
+fn start_analysis_job(state: &mut AudioPlayer) -> Task<Message> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for file in &state.files {
+        // CUE virtual tracks share one file
+        if !paths.contains(&file.path) {
+            paths.push(file.path.clone());
+        }
+    }
+    if paths.is_empty() {
+        state.status = Some("No tracks to analyze.".into());
+        return Task::none();
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    state.analysis_job = Some(AnalysisJob { cancel: cancel.clone(), done: 0, total: paths.len(), current: String::new() });
+    let (tx, rx) = iced::futures::channel::mpsc::unbounded();
+    let library = state.library.clone();
+    let write_tags = state.write_replaygain_tags;
+    std::thread::spawn(move || {
+        analysis::run_job(paths, library, write_tags, &cancel, |event| {
+            let _ = tx.unbounded_send(event);
+        });
+    });
+    Task::run(rx, Message::Analysis)
+}
+
+// Compute (or read from cache) the seek bar overview for a file.
+fn load_waveform_task(path: PathBuf) -> Task<Message> {
+    Task::perform(
+        async move {
+            let waveform = run_blocking({
+                let path = path.clone();
+                move || waveform::load_waveform(&path).map(Arc::new)
+            })
+            .await
+            .flatten();
+            (path, waveform)
+        },
+        |(path, waveform)| Message::WaveformLoaded(path, waveform),
+    )
+}
+
+// Find the audible span of a file at the given threshold.
+fn detect_silence_task(path: PathBuf, threshold_db: f32) -> Task<Message> {
+    Task::perform(
+        async move {
+            let span = run_blocking({
+                let path = path.clone();
+                move || silence::detect_audible_span(&path, threshold_db)
+            })
+            .await
+            .flatten();
+            (path, span)
+        },
+        move |(path, span)| Message::SilenceDetected(path, threshold_db, span),
+    )
+}
+
+// (Re)detect silence for the playing file, e.g. after the threshold changed.
+fn detect_current_silence(state: &AudioPlayer) -> Task<Message> {
+    match state.audio.as_ref().ok().and_then(|e| e.current_path().map(|p| p.to_path_buf())) {
+        Some(path) => detect_silence_task(path, state.silence_trim.threshold_db),
+        None => Task::none(),
+    }
+}
+
+// Keep the highlighted lyric line in view as playback moves on.
+fn follow_lyrics(state: &mut AudioPlayer) -> Task<Message> {
+    if !state.lyrics_visible {
+        return Task::none();
+    }
+    let (Some(lyrics), Ok(engine)) = (&state.lyrics, &state.audio) else { return Task::none() };
+    let line = lyrics.line_at(engine.current_position());
+    if line == state.lyrics_line {
+        return Task::none();
+    }
+    state.lyrics_line = line;
+    let Some(line) = line else { return Task::none() };
+    let y = line as f32 / (lyrics.lines.len().max(2) - 1) as f32;
+    scrollable::snap_to(lyrics_scroll_id(), scrollable::RelativeOffset { x: 0.0, y })
+}
+
+fn lyrics_scroll_id() -> scrollable::Id {
+    scrollable::Id::new("lyrics")
+}
+
+// Stream list thumbnails as they are decoded, one worker thread per folder scan.
+// Starting a new scan stops the previous one.
+fn load_thumbnails(state: &mut AudioPlayer) -> Task<Message> {
+    state.thumbnails_cancel.store(true, Ordering::Relaxed);
+    if state.files.is_empty() {
+        return Task::none();
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    state.thumbnails_cancel = cancel.clone();
+    let paths: Vec<PathBuf> = state.files.iter().map(|f| f.path.clone()).collect();
+    let (tx, rx) = iced::futures::channel::mpsc::unbounded();
+    std::thread::spawn(move || {
+        for path in paths {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let art = artwork::load_artwork(&path, artwork::THUMB_SIZE);
+            // Receiver dropped (app closing) -> stop early
+            if tx.unbounded_send((path, art)).is_err() {
+                break;
             }
-            state.is_seeking = false;
-            state.last_seek_apply = None;
-            state.pre_seek_was_playing = false;
         }
-        Message::Tick => {
-            // Auto-advance when the current sink finishes.
-            let current_idx = current_index(state).or(state.selected);
-            let filtered = compute_filtered_indices(state);
-            if let Ok(engine) = &mut state.audio {
-                if let Some(sink) = &engine.sink {
-                    // If playing and became empty => advance
-                    if !sink.is_paused() && sink.empty() {
-                        if let Some(idx) = current_idx {
-                            if let Some(pos) = filtered.iter().position(|&x| x == idx) {
-                                if pos + 1 < filtered.len() {
-                                    let target_idx = filtered[pos + 1];
-                                    if let Some(file) = state.files.get(target_idx) {
-                                        state.selected = Some(target_idx);
-                                        if let Err(e) = engine.play_file(&file.path) {
-                                            state.status = Some(e);
-                                        } else {
-                                            state.status = Some(format!("Playing: {}", file.name));
-                                        }
-                                    }
-                                } else {
-                                    // Reached the end, stop and clear.
-                                    engine.stop();
-                                    state.status = Some("Playback finished.".into());
-                                }
-                            }
-                        }
-                    }
+    });
+    Task::run(rx, |(path, art)| Message::ThumbnailLoaded(path, art))
+}
+
+fn subscription(state: &AudioPlayer) -> Subscription<Message> {
+    // Engine events drive the progress/time display while playing; nothing
+    // wakes the UI up while idle unless the audio output needs retrying
+    let mut subscriptions = vec![state.engine_events.subscription()];
+    if let Some(mpris) = &state.mpris {
+        subscriptions.push(channel_subscription("mpris", mpris.events(), Message::Mpris));
+    }
+    if let Some(remote) = &state.remote {
+        subscriptions.push(channel_subscription(("remote", remote.id()), remote.calls(), Message::Remote));
+    }
+    if state.audio.as_ref().map_or(true, |e| e.output_failed()) {
+        subscriptions.push(iced::time::every(Duration::from_secs(1)).map(|_| Message::Tick));
+    }
+    if state.spectrum_visible {
+        // The analyzer redraws at the display's frame rate
+        subscriptions.push(iced::window::frames().map(|_| Message::SpectrumFrame));
+    }
+    Subscription::batch(subscriptions)
+}
+
+// Forward a std channel (engine events, MPRIS commands) into the app. The
+// receiver is taken when the subscription starts; `id` keeps it running
+// across calls, and a new id replaces it.
+fn channel_subscription<T: Send + 'static>(
+    id: impl std::hash::Hash + 'static,
+    receiver: SharedReceiver<T>,
+    to_message: fn(T) -> Message,
+) -> Subscription<Message> {
+    let stream = iced::stream::channel(64, move |mut output| async move {
+        use iced::futures::{SinkExt, StreamExt};
+        let Some(items) = receiver.lock().ok().and_then(|mut r| r.take()) else { return };
+        // The std channel blocks; forward it from a thread
+        let (tx, mut rx) = iced::futures::channel::mpsc::unbounded();
+        std::thread::spawn(move || {
+            while let Ok(item) = items.recv() {
+                if tx.unbounded_send(item).is_err() {
+                    break;
                 }
             }
+        });
+        while let Some(item) = rx.next().await {
+            if output.send(to_message(item)).await.is_err() {
+                break;
+            }
         }
-        Message::None => {}
-    }
-    // No background task; return none. The UI will refresh on interactions.
-    Task::none()
+    });
+    Subscription::run_with_id(id, stream)
+}
+
+// The current engine's event channel; recreating the engine connects a new
+// one under a new id, which replaces the old subscription.
+#[derive(Default)]
+struct EngineEvents {
+    id: u64,
+    receiver: SharedReceiver<EngineEvent>,
 }
 
-fn subscription(_state: &AudioPlayer) -> Subscription<Message> {
-    // Refresh UI at ~10 FPS so the progress/time update while playing
-    iced::time::every(Duration::from_millis(100)).map(|_| Message::Tick)
+impl EngineEvents {
+    fn connect(&mut self, audio: &Result<AudioEngine, String>) {
+        if let Ok(engine) = audio {
+            self.id += 1;
+            self.receiver = Arc::new(Mutex::new(Some(engine.subscribe())));
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        channel_subscription(("engine-events", self.id), self.receiver.clone(), Message::Engine)
+    }
 }
 
 fn view(state: &AudioPlayer) -> Element<'_, Message> {
@@ -599,7 +1622,7 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
     let playing_idx = current_index(state);
     let (is_playing, is_paused) = match &state.audio {
         Ok(engine) => {
-            let paused = engine.sink.as_ref().is_some_and(|s| s.is_paused());
+            let paused = engine.is_paused();
             (engine.is_playing(), paused)
         }
         Err(_) => (false, false),
@@ -617,8 +1640,28 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
                 label = format!("[PLAYING] {}", label);
             }
         }
+        let thumb: Element<'_, Message> = match state.thumbnails.get(&file.path) {
+            Some(handle) => iced::widget::image(handle.clone())
+                .width(Length::Fixed(32.0))
+                .height(Length::Fixed(32.0))
+                .into(),
+            None => Space::new(Length::Fixed(32.0), Length::Fixed(32.0)).into(),
+        };
+        // BPM and key columns, blank until the library has been analyzed
+        let tempo_key = state.track_info.get(&file.path).and_then(|e| e.tempo_key);
+        let bpm = tempo_key.and_then(|t| t.bpm).map(|b| format!("{b:.1}")).unwrap_or_default();
+        let key = tempo_key.and_then(|t| t.key).map(|k| format!("{k} · {}", k.camelot())).unwrap_or_default();
         files_col = files_col.push(
-            button(text(label))
+            button(
+                row![
+                    thumb,
+                    text(label).width(Length::Fill),
+                    text(bpm).width(Length::Fixed(BPM_COLUMN_WIDTH)),
+                    text(key).width(Length::Fixed(KEY_COLUMN_WIDTH)),
+                ]
+                .spacing(10)
+                .align_y(iced::alignment::Vertical::Center),
+            )
                 .on_press(Message::SelectTrack(i))
                 .width(Length::Fill)
                 .padding([6, 10])
@@ -648,6 +1691,24 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
         .height(Length::Fill)
         .width(Length::Fill);
 
+    // Column headers; click to sort, click again to reverse
+    let sort_header = |label: &str, column: SortColumn| {
+        let arrow = match (state.sort == column, state.sort_descending) {
+            (true, false) => " ▲",
+            (true, true) => " ▼",
+            (false, _) => "",
+        };
+        button(text(format!("{label}{arrow}")).size(13)).style(button::text).padding([2, 0]).on_press(Message::SortBy(column))
+    };
+    let columns_header = row![
+        Space::with_width(Length::Fixed(32.0)),
+        sort_header("Name", SortColumn::Name).width(Length::Fill),
+        sort_header("BPM", SortColumn::Bpm).width(Length::Fixed(BPM_COLUMN_WIDTH)),
+        sort_header("Key", SortColumn::Key).width(Length::Fixed(KEY_COLUMN_WIDTH)),
+    ]
+    .spacing(10)
+    .padding([0, 14]);
+
     let is_playing_now = match &state.audio { Ok(e) => e.is_playing(), Err(_) => false };
     // Determine availability of prev/next based on current selection
     let curr_idx = current_index(state).or(state.selected);
@@ -716,12 +1777,26 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
         Space::with_width(Length::Fixed(20.0)),
         stop_btn,
         Space::with_width(Length::Fill),
+        text("Volume").size(14),
+        slider(0.0..=1.0, state.volume, Message::VolumeChanged).step(0.01).width(Length::Fixed(120.0)),
+        text(format!("{:.0}%", state.volume * 100.0)).size(14).width(Length::Fixed(40.0)),
     ]
     .spacing(8)
     .align_y(iced::alignment::Vertical::Center)
     .width(Length::Fill);
 
     let eq_btn = round_icon_button(EQ_SVG, Some(Message::ToggleEq));
+    // Output device picker; the first entry follows the system default
+    let device_options: Vec<String> = std::iter::once(DEFAULT_DEVICE_LABEL.to_string())
+        .chain(state.output_devices.iter().cloned())
+        .collect();
+    let device_selected = match &state.audio {
+        Ok(engine) => Some(engine.active_device().unwrap_or(DEFAULT_DEVICE_LABEL).to_string()),
+        Err(_) => None,
+    };
+    let device_picker = pick_list(device_options, device_selected, Message::DeviceSelected)
+        .placeholder("Output device")
+        .width(Length::Fixed(200.0));
     let header = row![
         text("Rust Audio Player").size(22),
         Space::with_width(Length::FillPortion(1)),
@@ -729,6 +1804,19 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
         Space::with_width(Length::Fixed(8.0)),
         eq_btn,
         Space::with_width(Length::Fixed(8.0)),
+        button("Lyrics").on_press(Message::ToggleLyrics),
+        Space::with_width(Length::Fixed(8.0)),
+        button("Spectrum").on_press(Message::ToggleSpectrum),
+        Space::with_width(Length::Fixed(8.0)),
+        button("Effects").on_press(Message::ToggleEffects),
+        Space::with_width(Length::Fixed(8.0)),
+        button("Dynamics").on_press(Message::ToggleDynamics),
+        Space::with_width(Length::Fixed(8.0)),
+        button("Output").on_press(Message::ToggleSettings),
+        Space::with_width(Length::Fixed(8.0)),
+        device_picker,
+        button(text("⟳")).on_press(Message::RefreshDevices),
+        Space::with_width(Length::Fixed(8.0)),
         button("Choose Folder").on_press(Message::ChooseFolder),
         Space::with_width(Length::Fixed(12.0)),
         text(state.folder_display()).size(16)
@@ -738,6 +1826,18 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
     .width(Length::Fill);
 
     // Build progress/seek UI
+    let (loop_a, loop_b) = match &state.audio {
+        Ok(engine) => match engine.total_duration() {
+            Some(total) if total > Duration::ZERO => {
+                let (a, b) = engine.loop_points();
+                let ratio = |d: Duration| (d.as_secs_f32() / total.as_secs_f32()).clamp(0.0, 1.0);
+                (a.map(ratio), b.map(ratio))
+            }
+            _ => (None, None),
+        },
+        Err(_) => (None, None),
+    };
+
     let (slider_enabled, slider_value, time_text) = match &state.audio {
         Ok(engine) => {
             if let Some(total) = engine.total_duration() {
@@ -761,15 +1861,183 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
         slider(0.0..=1.0, 0.0, |_| Message::None).width(Length::Fill)
     };
 
-    let progress_row = row![seek_bar, Space::with_width(Length::Fixed(8.0)), text(time_text)]
+    // Now-playing panel: cover art next to the track name
+    let now_playing_panel = match &state.audio {
+        Ok(engine) if engine.now_playing().is_some() => {
+            let art: Element<'_, Message> = match &state.now_playing_art {
+                Some(handle) => iced::widget::image(handle.clone())
+                    .width(Length::Fixed(128.0))
+                    .height(Length::Fixed(128.0))
+                    .into(),
+                None => container(text("No artwork").size(12))
+                    .center_x(Length::Fixed(128.0))
+                    .center_y(Length::Fixed(128.0))
+                    .style(container::bordered_box)
+                    .into(),
+            };
+            let name = engine.now_playing().unwrap_or_default().to_string();
+            // Measured loudness, once the library has been analyzed
+            let loudness = engine
+                .current_path()
+                .and_then(|p| state.track_info.get(p)?.loudness.as_ref())
+                .map(|l| text(l.summary()).size(12));
+            Some(
+                row![art, column![text(name).size(18)].push_maybe(loudness).spacing(4)]
+                    .spacing(16)
+                    .align_y(iced::alignment::Vertical::Center)
+                    .width(Length::Fill),
+            )
+        }
+        _ => None,
+    };
+
+    // Waveform overview of the current file (only the played span for CUE and trimmed tracks)
+    let waveform_peaks = match (&state.audio, &state.waveform) {
+        (Ok(engine), Some((path, waveform))) if state.waveform_enabled && slider_enabled && engine.current_path() == Some(path.as_path()) => {
+            Some(match engine.span() {
+                Some(r) => waveform.span(r.start, r.end),
+                None => &waveform.peaks[..],
+            })
+        }
+        _ => None,
+    };
+    let seek_bar: Element<'_, Message> = match waveform_peaks {
+        Some(peaks) => canvas(WaveformSeekBar { peaks, position: slider_value, a: loop_a, b: loop_b })
+            .width(Length::Fill)
+            .height(Length::Fixed(48.0))
+            .into(),
+        // Loop markers are drawn over the slider; the canvas ignores input so dragging still works
+        None => stack![
+            seek_bar,
+            canvas(LoopMarkers { a: loop_a, b: loop_b }).width(Length::Fill).height(Length::Fixed(16.0)),
+        ]
+        .into(),
+    };
+    let has_track = matches!(&state.audio, Ok(e) if e.current_path().is_some());
+    let loop_controls = row![
+        button(text("A").size(14)).on_press_maybe(has_track.then_some(Message::SetLoopA)),
+        button(text("B").size(14)).on_press_maybe(has_track.then_some(Message::SetLoopB)),
+        button(text("Clear loop").size(14)).on_press_maybe(loop_a.or(loop_b).map(|_| Message::ClearLoop)),
+        iced::widget::checkbox("Waveform", state.waveform_enabled).on_toggle(Message::WaveformToggled).size(16),
+    ]
+    .spacing(4)
+    .align_y(iced::alignment::Vertical::Center);
+
+    let progress_row = row![seek_bar, Space::with_width(Length::Fixed(8.0)), text(time_text), loop_controls]
         .spacing(8)
+        .align_y(iced::alignment::Vertical::Center)
         .width(Length::Fill);
 
+    // Peak/RMS meters, one bar per output channel, with clip indicators
+    let meter_row = (!state.levels.channels.is_empty()).then(|| {
+        let channels = state.levels.channels.len() as f32;
+        let hold = state.levels.channels.iter().map(|c| c.hold_db).fold(meter::FLOOR_DB, f32::max);
+        let hold_text = if hold > meter::FLOOR_DB { format!("{hold:.1} dB") } else { "-inf dB".to_string() };
+        row![
+            canvas(LevelMeterView { levels: &state.levels.channels })
+                .width(Length::Fill)
+                .height(Length::Fixed(channels * 8.0)),
+            text(hold_text).size(12).width(Length::Fixed(56.0)),
+            button(text("Reset clip").size(12)).on_press_maybe(state.levels.any_clipped().then_some(Message::ResetClipIndicators)),
+        ]
+        .spacing(8)
+        .align_y(iced::alignment::Vertical::Center)
+        .width(Length::Fill)
+    });
+
+    // Playback speed: 0.5x-2.0x, pitch preserved unless "Change pitch" is ticked
+    let speed_row = match &state.audio {
+        Ok(engine) => {
+            let factor = engine.speed().factor();
+            let changes_pitch = !engine.speed().preserve_pitch.load(Ordering::Relaxed);
+            row![
+                text("Speed").size(14),
+                slider(MIN_SPEED..=MAX_SPEED, factor, Message::SpeedChanged).step(0.05).width(Length::Fixed(200.0)),
+                text(format!("{factor:.2}×")).size(14),
+                button(text("Reset").size(14)).on_press_maybe(((factor - 1.0).abs() > f32::EPSILON).then_some(Message::ResetSpeed)),
+                iced::widget::checkbox("Change pitch", changes_pitch).on_toggle(Message::SpeedChangesPitch).size(16),
+            ]
+            .spacing(8)
+            .align_y(iced::alignment::Vertical::Center)
+        }
+        Err(_) => row![],
+    };
+
+    // Pitch shift: whole semitones plus a cents fine-tune, tempo unchanged
+    let pitch_row = match &state.audio {
+        Ok(engine) => {
+            let (semitones, cents) = engine.pitch().snapshot();
+            row![
+                text("Pitch").size(14),
+                slider(-12.0..=12.0, semitones as f32, Message::PitchSemitonesChanged).step(1.0).width(Length::Fixed(200.0)),
+                text(format!("{semitones:+} st")).size(14),
+                slider(-50.0..=50.0, cents, Message::PitchCentsChanged).step(1.0).width(Length::Fixed(120.0)),
+                text(format!("{cents:+.0} ct")).size(14),
+                button(text("Reset").size(14)).on_press_maybe((semitones != 0 || cents != 0.0).then_some(Message::ResetPitch)),
+            ]
+            .spacing(8)
+            .align_y(iced::alignment::Vertical::Center)
+        }
+        Err(_) => row![],
+    };
+
+    // Silence trimming at track start and end
+    let trim_row = row![
+        iced::widget::checkbox("Trim silence", state.silence_trim.enabled).on_toggle(Message::TrimSilenceToggled).size(16),
+        text("Threshold").size(14),
+        slider(silence::THRESHOLD_RANGE, state.silence_trim.threshold_db, Message::SilenceThresholdChanged)
+            .on_release(Message::SilenceThresholdReleased)
+            .step(1.0)
+            .width(Length::Fixed(160.0)),
+        text(format!("{:.0} dB", state.silence_trim.threshold_db)).size(14),
+    ]
+    .spacing(8)
+    .align_y(iced::alignment::Vertical::Center);
+
+    // Balance, mono downmix and channel swap
+    let channels_row = {
+        let ch = state.channel_settings;
+        let balance_label = match ch.balance {
+            b if b < -0.005 => format!("L {:.0}%", -b * 100.0),
+            b if b > 0.005 => format!("R {:.0}%", b * 100.0),
+            _ => "Center".to_string(),
+        };
+        row![
+            text("Balance").size(14),
+            slider(-1.0..=1.0, ch.balance, Message::BalanceChanged).step(0.05).width(Length::Fixed(200.0)),
+            text(balance_label).size(14),
+            button(text("Center").size(14)).on_press_maybe((ch.balance != 0.0).then_some(Message::BalanceChanged(0.0))),
+            iced::widget::checkbox("Mono", ch.mono).on_toggle(Message::MonoToggled).size(16),
+            iced::widget::checkbox("Swap L/R", ch.swap).on_toggle(Message::SwapChannelsToggled).size(16),
+        ]
+        .spacing(8)
+        .align_y(iced::alignment::Vertical::Center)
+    };
+
+    // Headphone crossfeed: presets, or custom cutoff and feed level
+    let crossfeed_row = {
+        let cf = state.crossfeed_settings;
+        row![
+            iced::widget::checkbox("Crossfeed", state.dsp_chain.is_enabled(EffectKind::Crossfeed))
+                .on_toggle(|on| Message::EffectEnabled(EffectKind::Crossfeed, on))
+                .size(16),
+            pick_list(CrossfeedPreset::ALL, Some(cf.preset), Message::CrossfeedPresetSelected).text_size(14),
+            text("Cutoff").size(14),
+            slider(CROSSFEED_CUTOFF_RANGE, cf.cutoff_hz, Message::CrossfeedCutoffChanged).step(10.0).width(Length::Fixed(140.0)),
+            text(format!("{:.0} Hz", cf.cutoff_hz)).size(14),
+            text("Feed").size(14),
+            slider(CROSSFEED_FEED_RANGE, cf.feed_db, Message::CrossfeedFeedChanged).step(0.5).width(Length::Fixed(120.0)),
+            text(format!("{:.1} dB", cf.feed_db)).size(14),
+        ]
+        .spacing(8)
+        .align_y(iced::alignment::Vertical::Center)
+    };
+
     let status_line = {
         let audio_line = match &state.audio {
             Ok(engine) => {
-                if let Some(np) = &engine.now_playing {
-                    if engine.sink.as_ref().is_some_and(|s| s.is_paused()) {
+                if let Some(np) = engine.now_playing() {
+                    if engine.is_paused() {
                         format!("Paused: {}", np)
                     } else {
                         format!("Now playing: {}", np)
@@ -780,13 +2048,39 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
             }
             Err(e) => format!("Audio init error: {e}"),
         };
-        let extra = state.status.as_deref().unwrap_or("");
+        let progress = state.analysis_job.as_ref().map(|job| {
+            format!("Analyzing {}/{}: {}", job.done, job.total, job.current)
+        });
+        let extra = progress.as_deref().or(state.status.as_deref()).unwrap_or("");
         let combined = if extra.is_empty() {
             audio_line
         } else {
             format!("{audio_line} — {extra}")
         };
-        text(combined)
+        let retry = state.audio.is_err().then(|| button(text("Retry audio").size(14)).on_press(Message::RetryAudio));
+        // Effective device format, so users can see what the hardware is running at
+        let format = state.audio.as_ref().ok().map(|engine| text(engine.format_summary()).size(12));
+        let analysis: Element<'_, Message> = match &state.analysis_job {
+            Some(job) => button(text("Cancel").size(14))
+                .on_press_maybe((!job.cancel.load(Ordering::Relaxed)).then_some(Message::CancelAnalysis))
+                .into(),
+            None => row![
+                iced::widget::checkbox("Write ReplayGain tags", state.write_replaygain_tags)
+                    .on_toggle(Message::ReplayGainTagsToggled)
+                    .size(14)
+                    .text_size(12),
+                button(text("Analyze library").size(14)).on_press_maybe((!state.files.is_empty()).then_some(Message::AnalyzeLibrary)),
+            ]
+            .spacing(8)
+            .align_y(iced::alignment::Vertical::Center)
+            .into(),
+        };
+        row![text(combined).width(Length::Fill)]
+            .push_maybe(format)
+            .push_maybe(retry)
+            .push(analysis)
+            .spacing(8)
+            .align_y(iced::alignment::Vertical::Center)
     };
 
     // Optional EQ popup panel
@@ -821,17 +2115,226 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
         .width(Length::Fill))
     } else { None };
 
+    // Optional spectrum analyzer panel
+    let spectrum_panel = state.spectrum_visible.then(|| {
+        container(
+            column![
+                row![
+                    text("Spectrum").size(18),
+                    Space::with_width(Length::Fixed(12.0)),
+                    pick_list(SpectrumResolution::ALL, Some(state.spectrum.resolution()), Message::SpectrumResolutionSelected).text_size(14),
+                    Space::with_width(Length::Fill),
+                    button("Close").on_press(Message::ToggleSpectrum)
+                ]
+                .spacing(8)
+                .align_y(iced::alignment::Vertical::Center),
+                canvas(SpectrumView { bars: &state.spectrum.bars, peaks: &state.spectrum.peaks })
+                    .width(Length::Fill)
+                    .height(Length::Fixed(160.0)),
+            ]
+            .spacing(8)
+            .padding(8),
+        )
+        .width(Length::Fill)
+    });
+
+    // Optional effect chain panel: order, enable and bypass each effect
+    let effects_panel = state.effects_visible.then(|| {
+        let chain = &state.dsp_chain;
+        let last = chain.slots.len().saturating_sub(1);
+        let mut rows = column![].spacing(4);
+        for (i, slot) in chain.slots.iter().enumerate() {
+            let kind = slot.kind;
+            let mut r = row![
+                button(text("▲").size(12)).on_press_maybe((i > 0).then_some(Message::MoveEffect(i, -1))),
+                button(text("▼").size(12)).on_press_maybe((i < last).then_some(Message::MoveEffect(i, 1))),
+                iced::widget::checkbox(kind.label(), slot.enabled).on_toggle(move |on| Message::EffectEnabled(kind, on)).size(16).width(Length::Fixed(220.0)),
+                iced::widget::checkbox("Bypass", slot.bypass).on_toggle_maybe(slot.enabled.then_some(move |b| Message::EffectBypassed(kind, b))).size(16),
+            ]
+            .spacing(8)
+            .align_y(iced::alignment::Vertical::Center);
+            if kind == EffectKind::Gain {
+                r = r.push(slider(PREAMP_RANGE, state.preamp_db, Message::PreampChanged).step(0.5).width(Length::Fixed(160.0)));
+                r = r.push(text(format!("{:+.1} dB", state.preamp_db)).size(14));
+            }
+            if kind == EffectKind::Convolution {
+                let rv = &state.reverb_settings;
+                let ir_name = match (&rv.ir_path, &state.impulse_response) {
+                    (Some(path), Some(_)) => path.file_name().and_then(|n| n.to_str()).unwrap_or("?").to_string(),
+                    _ => "No impulse response".to_string(),
+                };
+                r = r.push(button(text("Load IR…").size(14)).on_press(Message::ChooseImpulseResponse));
+                r = r.push(text(ir_name).size(14));
+                r = r.push(text("Wet").size(14));
+                r = r.push(slider(0.0..=1.0, rv.mix, Message::ReverbMixChanged).step(0.01).width(Length::Fixed(120.0)));
+                r = r.push(text(format!("{:.0}%", rv.mix * 100.0)).size(14));
+                r = r.push(iced::widget::checkbox("Normalize", rv.normalize).on_toggle(Message::ReverbNormalizeToggled).size(16));
+            }
+            rows = rows.push(r);
+        }
+        container(
+            column![
+                row![
+                    text("Effects").size(18),
+                    Space::with_width(Length::Fixed(12.0)),
+                    iced::widget::checkbox("Bypass all", chain.bypass_all).on_toggle(Message::BypassAllToggled).size(16),
+                    Space::with_width(Length::Fill),
+                    button("Close").on_press(Message::ToggleEffects)
+                ]
+                .spacing(8)
+                .align_y(iced::alignment::Vertical::Center),
+                text("Effects run top to bottom.").size(12),
+                rows,
+            ]
+            .spacing(8)
+            .padding(8),
+        )
+        .width(Length::Fill)
+    });
+
+    // Optional dynamics panel: compressor/limiter with a night mode preset
+    let dynamics_panel = state.dynamics_visible.then(|| {
+        let comp = state.compressor_settings;
+        let compressor_on = state.dsp_chain.is_enabled(EffectKind::Compressor);
+        let reduction = match &state.audio {
+            Ok(engine) if compressor_on => format!("Gain reduction {:.1} dB", engine.effects().compressor.gain_reduction_db()),
+            _ => String::new(),
+        };
+        let mut sliders = row![].spacing(10);
+        for param in CompressorParam::ALL {
+            let (range, step) = param.range();
+            let value = param.value(comp);
+            sliders = sliders.push(
+                column![
+                    text(param.label()).size(12),
+                    slider(range, value, move |v| Message::CompressorParamChanged(param, v)).step(step).width(Length::Fixed(140.0)),
+                    text(param.format(value)).size(12),
+                ]
+                .spacing(6)
+                .width(Length::Fixed(160.0)),
+            );
+        }
+        container(
+            column![
+                row![
+                    text("Dynamics").size(18),
+                    Space::with_width(Length::Fixed(12.0)),
+                    iced::widget::checkbox("Compressor", compressor_on)
+                        .on_toggle(|on| Message::EffectEnabled(EffectKind::Compressor, on))
+                        .size(16),
+                    button(text("Night mode").size(14)).on_press(Message::NightMode),
+                    text(reduction).size(14),
+                    Space::with_width(Length::Fill),
+                    button("Close").on_press(Message::ToggleDynamics)
+                ]
+                .spacing(8)
+                .align_y(iced::alignment::Vertical::Center),
+                sliders,
+            ]
+            .spacing(8)
+            .padding(8),
+        )
+        .width(Length::Fill)
+    });
+
+    // Optional output format panel
+    let settings_panel = state.settings_visible.then(|| {
+        let output = state.output_settings;
+        container(
+            column![
+                row![text("Output format").size(18), Space::with_width(Length::Fill), button("Close").on_press(Message::ToggleSettings)],
+                row![
+                    text("Sample rate").size(14),
+                    pick_list(SampleRateChoice::ALL, Some(output.sample_rate), Message::SampleRateSelected),
+                    text("Buffer size").size(14),
+                    pick_list(BufferSizeChoice::ALL, Some(output.buffer_size), Message::BufferSizeSelected),
+                    text("Resampler").size(14),
+                    pick_list(ResamplerQuality::ALL, Some(output.resampler), Message::ResamplerSelected),
+                ]
+                .spacing(8)
+                .align_y(iced::alignment::Vertical::Center),
+                text("Remote control").size(18),
+                row![
+                    iced::widget::checkbox("HTTP API on localhost", state.remote_settings.enabled)
+                        .on_toggle(Message::RemoteToggled)
+                        .size(16),
+                    text("Port").size(14),
+                    text_input("8787", &state.remote_port_input)
+                        .on_input(Message::RemotePortChanged)
+                        .on_submit(Message::RemotePortSubmitted)
+                        .width(Length::Fixed(80.0)),
+                    text(format!("Token: {}", state.remote_settings.token)).size(14),
+                    button(text("New token").size(14)).on_press(Message::RemoteNewToken),
+                ]
+                .spacing(8)
+                .align_y(iced::alignment::Vertical::Center),
+            ]
+            .spacing(8)
+            .padding(8),
+        )
+        .width(Length::Fill)
+    });
+
+    // Optional lyrics pane; the current line is highlighted and clickable when synced
+    let lyrics_pane = if state.lyrics_visible {
+        let body: Element<'_, Message> = match &state.lyrics {
+            Some(lyrics) => {
+                let mut lines = column![].spacing(4).width(Length::Fill);
+                for (i, line) in lyrics.lines.iter().enumerate() {
+                    let current = lyrics.synced && state.lyrics_line == Some(i);
+                    let label = text(if line.text.is_empty() { "♪".to_string() } else { line.text.clone() })
+                        .size(if current { 18 } else { 14 });
+                    let mut b = button(label)
+                        .width(Length::Fill)
+                        .padding([2, 8])
+                        .style(move |theme, status| {
+                            use iced::widget::button;
+                            if current { button::primary(theme, status) } else { button::text(theme, status) }
+                        });
+                    if lyrics.synced {
+                        b = b.on_press(Message::LyricClicked(i));
+                    }
+                    lines = lines.push(b);
+                }
+                scrollable(lines).id(lyrics_scroll_id()).height(Length::Fixed(180.0)).width(Length::Fill).into()
+            }
+            None => text("No lyrics found for this track.").size(14).into(),
+        };
+        Some(container(
+            column![
+                row![text("Lyrics").size(18), Space::with_width(Length::Fill), button("Close").on_press(Message::ToggleLyrics)],
+                body
+            ]
+            .spacing(8)
+            .padding(8)
+        )
+        .width(Length::Fill))
+    } else { None };
+
     let content_col = column![
         header,
         Space::with_height(8),
+        if let Some(panel) = now_playing_panel { panel.into() } else { Element::from(Space::with_height(0)) },
         controls,
         Space::with_height(8),
         progress_row,
+        if let Some(meters) = meter_row { meters.into() } else { Element::from(Space::with_height(0)) },
+        speed_row,
+        pitch_row,
+        trim_row,
+        channels_row,
+        crossfeed_row,
         Space::with_height(8),
     if let Some(eq) = eq_popup { eq } else { container(Space::with_height(0)).into() },
+        if let Some(spectrum) = spectrum_panel { spectrum } else { container(Space::with_height(0)) },
+        if let Some(effects) = effects_panel { effects } else { container(Space::with_height(0)) },
+        if let Some(dynamics) = dynamics_panel { dynamics } else { container(Space::with_height(0)) },
+        if let Some(settings) = settings_panel { settings } else { container(Space::with_height(0)) },
+        if let Some(lyrics) = lyrics_pane { lyrics } else { container(Space::with_height(0)) },
         Space::with_height(8),
         search_bar,
         Space::with_height(8),
+        columns_header,
         container(files_list)
             .height(Length::Fill)
             .width(Length::Fill)
@@ -849,7 +2352,220 @@ fn view(state: &AudioPlayer) -> Element<'_, Message> {
         .into()
 }
 
-fn format_time(dur: Duration) -> String {
+// Shaded A-B region with edge markers, positioned like the slider's handle.
+struct LoopMarkers {
+    a: Option<f32>,
+    b: Option<f32>,
+}
+
+impl<Message> canvas::Program<Message> for LoopMarkers {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &iced::Renderer,
+        theme: &iced::Theme,
+        bounds: iced::Rectangle,
+        _cursor: iced::mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+        // The default slider handle is 14px wide, so values map inside that margin
+        let handle = 14.0;
+        let x = |v: f32| handle / 2.0 + v * (bounds.width - handle).max(0.0);
+        let color = theme.extended_palette().danger.base.color;
+        if let (Some(a), Some(b)) = (self.a, self.b) {
+            frame.fill_rectangle(
+                iced::Point::new(x(a), 0.0),
+                iced::Size::new(x(b) - x(a), bounds.height),
+                iced::Color { a: 0.25, ..color },
+            );
+        }
+        for v in [self.a, self.b].into_iter().flatten() {
+            frame.fill_rectangle(iced::Point::new(x(v) - 1.0, 0.0), iced::Size::new(2.0, bounds.height), color);
+        }
+        vec![frame.into_geometry()]
+    }
+}
+
+// Log-frequency bar graph with held peaks; levels are dBFS from FLOOR_DB to 0.
+struct SpectrumView<'a> {
+    bars: &'a [f32],
+    peaks: &'a [f32],
+}
+
+impl<Message> canvas::Program<Message> for SpectrumView<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &iced::Renderer,
+        theme: &iced::Theme,
+        bounds: iced::Rectangle,
+        _cursor: iced::mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+        let palette = theme.extended_palette();
+        let label_h = 14.0;
+        let plot_h = (bounds.height - label_h).max(0.0);
+        let height = |db: f32| ((db - spectrum::FLOOR_DB) / -spectrum::FLOOR_DB).clamp(0.0, 1.0) * plot_h;
+        let bar_w = bounds.width / self.bars.len().max(1) as f32;
+
+        frame.fill_rectangle(iced::Point::ORIGIN, iced::Size::new(bounds.width, plot_h), palette.background.weak.color);
+        for (i, (&bar, &peak)) in self.bars.iter().zip(self.peaks).enumerate() {
+            let x = i as f32 * bar_w;
+            let h = height(bar);
+            frame.fill_rectangle(iced::Point::new(x + 1.0, plot_h - h), iced::Size::new((bar_w - 2.0).max(1.0), h), palette.primary.base.color);
+            let p = height(peak);
+            if p > 0.0 {
+                frame.fill_rectangle(iced::Point::new(x + 1.0, plot_h - p - 1.0), iced::Size::new((bar_w - 2.0).max(1.0), 2.0), palette.danger.base.color);
+            }
+        }
+        for (freq, label) in [(100.0, "100"), (1_000.0, "1k"), (10_000.0, "10k")] {
+            let x = SpectrumAnalyzer::bar_position(freq) * bounds.width;
+            frame.fill_rectangle(iced::Point::new(x, 0.0), iced::Size::new(1.0, plot_h), iced::Color { a: 0.3, ..palette.background.strong.color });
+            frame.fill_text(canvas::Text {
+                content: label.to_string(),
+                position: iced::Point::new(x + 2.0, plot_h + 1.0),
+                color: palette.background.base.text,
+                size: iced::Pixels(11.0),
+                ..canvas::Text::default()
+            });
+        }
+        vec![frame.into_geometry()]
+    }
+}
+
+// Min/max waveform overview used as a seek bar: the played part is drawn in
+// the accent colour, and clicking or dragging seeks through the same
+// messages as the slider.
+struct WaveformSeekBar<'a> {
+    peaks: &'a [(f32, f32)],
+    // Playback position and loop points, 0..1
+    position: f32,
+    a: Option<f32>,
+    b: Option<f32>,
+}
+
+impl canvas::Program<Message> for WaveformSeekBar<'_> {
+    // Whether a drag is in progress
+    type State = bool;
+
+    fn update(
+        &self,
+        dragging: &mut bool,
+        event: canvas::Event,
+        bounds: iced::Rectangle,
+        cursor: iced::mouse::Cursor,
+    ) -> (canvas::event::Status, Option<Message>) {
+        use iced::mouse::{Button, Event as MouseEvent};
+        let ratio = |x: f32| ((x - bounds.x) / bounds.width.max(1.0)).clamp(0.0, 1.0);
+        match event {
+            canvas::Event::Mouse(MouseEvent::ButtonPressed(Button::Left)) => match cursor.position_over(bounds) {
+                Some(p) => {
+                    *dragging = true;
+                    (canvas::event::Status::Captured, Some(Message::SeekChanged(ratio(p.x))))
+                }
+                None => (canvas::event::Status::Ignored, None),
+            },
+            canvas::Event::Mouse(MouseEvent::CursorMoved { position }) if *dragging => {
+                (canvas::event::Status::Captured, Some(Message::SeekChanged(ratio(position.x))))
+            }
+            canvas::Event::Mouse(MouseEvent::ButtonReleased(Button::Left)) if *dragging => {
+                *dragging = false;
+                (canvas::event::Status::Captured, Some(Message::SeekReleased))
+            }
+            _ => (canvas::event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &iced::Renderer,
+        theme: &iced::Theme,
+        bounds: iced::Rectangle,
+        _cursor: iced::mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+        let palette = theme.extended_palette();
+        let mid = bounds.height / 2.0;
+        let columns = bounds.width.max(1.0) as usize;
+        let played_x = self.position * bounds.width;
+        frame.fill_rectangle(iced::Point::ORIGIN, bounds.size(), palette.background.weak.color);
+        if let (Some(a), Some(b)) = (self.a, self.b) {
+            let danger = palette.danger.base.color;
+            frame.fill_rectangle(
+                iced::Point::new(a * bounds.width, 0.0),
+                iced::Size::new((b - a) * bounds.width, bounds.height),
+                iced::Color { a: 0.2, ..danger },
+            );
+        }
+        // One column per pixel, each covering its share of the peak pairs
+        for x in 0..columns {
+            let first = x * self.peaks.len() / columns;
+            let last = ((x + 1) * self.peaks.len() / columns).max(first + 1).min(self.peaks.len());
+            let Some(slice) = self.peaks.get(first..last) else { continue };
+            let (lo, hi) = slice.iter().fold((0.0f32, 0.0f32), |(lo, hi), &(a, b)| (lo.min(a), hi.max(b)));
+            let top = mid - hi.clamp(-1.0, 1.0) * mid;
+            let bottom = mid - lo.clamp(-1.0, 1.0) * mid;
+            let color = if (x as f32) < played_x { palette.primary.base.color } else { palette.background.strong.color };
+            frame.fill_rectangle(iced::Point::new(x as f32, top), iced::Size::new(1.0, (bottom - top).max(1.0)), color);
+        }
+        for v in [self.a, self.b].into_iter().flatten() {
+            frame.fill_rectangle(iced::Point::new(v * bounds.width - 1.0, 0.0), iced::Size::new(2.0, bounds.height), palette.danger.base.color);
+        }
+        frame.fill_rectangle(iced::Point::new(played_x - 1.0, 0.0), iced::Size::new(2.0, bounds.height), palette.background.base.text);
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(&self, _state: &Self::State, bounds: iced::Rectangle, cursor: iced::mouse::Cursor) -> iced::mouse::Interaction {
+        if cursor.is_over(bounds) { iced::mouse::Interaction::Pointer } else { iced::mouse::Interaction::default() }
+    }
+}
+
+// Horizontal level bars from meter::FLOOR_DB to 0 dBFS: RMS solid, peak
+// lighter behind it, a held-peak marker, and a clip box at the right end.
+struct LevelMeterView<'a> {
+    levels: &'a [meter::ChannelLevel],
+}
+
+impl<Message> canvas::Program<Message> for LevelMeterView<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &iced::Renderer,
+        theme: &iced::Theme,
+        bounds: iced::Rectangle,
+        _cursor: iced::mouse::Cursor,
+    ) -> Vec<canvas::Geometry> {
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+        let palette = theme.extended_palette();
+        let row_h = bounds.height / self.levels.len().max(1) as f32;
+        let bar_h = (row_h - 2.0).max(1.0);
+        let clip_w = 10.0;
+        let width = (bounds.width - clip_w - 4.0).max(0.0);
+        let x = |db: f32| ((db - meter::FLOOR_DB) / -meter::FLOOR_DB).clamp(0.0, 1.0) * width;
+        for (i, level) in self.levels.iter().enumerate() {
+            let y = i as f32 * row_h;
+            frame.fill_rectangle(iced::Point::new(0.0, y), iced::Size::new(width, bar_h), palette.background.weak.color);
+            frame.fill_rectangle(iced::Point::new(0.0, y), iced::Size::new(x(level.peak_db), bar_h), palette.primary.weak.color);
+            frame.fill_rectangle(iced::Point::new(0.0, y), iced::Size::new(x(level.rms_db), bar_h), palette.primary.base.color);
+            if level.hold_db > meter::FLOOR_DB {
+                let color = if level.hold_db > -1.0 { palette.danger.base.color } else { palette.background.base.text };
+                frame.fill_rectangle(iced::Point::new(x(level.hold_db) - 1.0, y), iced::Size::new(2.0, bar_h), color);
+            }
+            let clip = if level.clipped { palette.danger.base.color } else { palette.background.strong.color };
+            frame.fill_rectangle(iced::Point::new(width + 4.0, y), iced::Size::new(clip_w, bar_h), clip);
+        }
+        vec![frame.into_geometry()]
+    }
+}
+
+pub(crate) fn format_time(dur: Duration) -> String {
     let secs = dur.as_secs();
     let m = secs / 60;
     let s = secs % 60;
@@ -857,6 +2573,78 @@ fn format_time(dur: Duration) -> String {
 }
 
 impl AudioPlayer {
+    // Try to open audio output again after it failed; on success the engine
+    // picks up the persisted effect settings. Returns true if audio is now available.
+    fn retry_audio(&mut self) -> bool {
+        if self.audio.is_ok() {
+            return true;
+        }
+        self.last_audio_retry = Some(Instant::now());
+        self.audio = AudioEngine::new(self.output_device.as_deref(), self.output_settings);
+        self.engine_events.connect(&self.audio);
+        let volume = self.volume;
+        if let Ok(engine) = &mut self.audio {
+            engine.set_volume(volume);
+        }
+        match &self.audio {
+            Ok(engine) => {
+                self.push_effect_settings(engine.effects());
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    // Hand every effect's settings to a (new) engine
+    fn push_effect_settings(&self, effects: &DspEffects) {
+        effects.chain.set(self.dsp_chain.clone());
+        effects.preamp.set_gain_db(self.preamp_db);
+        effects.eq.set_gains_db(self.eq_gains_db);
+        effects.compressor.set(self.compressor_settings);
+        effects.crossfeed.set(self.crossfeed_settings);
+        effects.channel_mix.set(self.channel_settings);
+        effects.reverb.set(self.reverb_settings.clone());
+        effects.reverb.set_impulse_response(self.impulse_response.clone());
+    }
+
+    // (Re)start the remote control server with the current settings, or stop it
+    fn restart_remote(&mut self) {
+        // The old server has to release its port first
+        self.remote = None;
+        if !self.remote_settings.enabled {
+            return;
+        }
+        match RemoteServer::start(&self.remote_settings) {
+            Ok(server) => {
+                self.remote = Some(server);
+                self.status = Some(format!("Remote control on http://127.0.0.1:{}/api/", self.remote_settings.port));
+            }
+            Err(e) => self.status = Some(e),
+        }
+    }
+
+    fn save_settings(&self) {
+        save_config(&AppConfig {
+            dark_mode: self.dark_mode,
+            last_folder: self.folder.clone(),
+            eq: Some(self.eq_gains_db),
+            output_device: self.output_device.clone(),
+            output: self.output_settings,
+            channels: self.channel_settings,
+            crossfeed: self.crossfeed_settings,
+            compressor: self.compressor_settings,
+            dsp_chain: self.dsp_chain.clone(),
+            preamp_db: self.preamp_db,
+            reverb: self.reverb_settings.clone(),
+            spectrum_resolution: self.spectrum.resolution(),
+            waveform_seek_bar: self.waveform_enabled,
+            write_replaygain_tags: self.write_replaygain_tags,
+            silence_trim: self.silence_trim,
+            volume: self.volume,
+            remote: self.remote_settings.clone(),
+        });
+    }
+
     fn folder_display(&self) -> String {
         self.folder
             .as_ref()
@@ -868,73 +2656,58 @@ impl AudioPlayer {
 // Helper: determine the current track index, preferring the engine's current_path if available.
 fn current_index(state: &AudioPlayer) -> Option<usize> {
     if let Ok(engine) = &state.audio {
-        if let Some(p) = &engine.current_path {
-            return state.files.iter().position(|f| &f.path == p).or(state.selected);
+        if let Some(p) = engine.current_path() {
+            return state.files.iter().position(|f| f.path == p && f.cue == engine.current_range()).or(state.selected);
         }
     }
     state.selected
 }
 
-// Compute the indices of files that match the current search query (case-insensitive substring)
+// Compute the indices of files that match the current search query (see `query`
+// for the syntax), in the chosen sort order
 fn compute_filtered_indices(state: &AudioPlayer) -> Vec<usize> {
-    if state.search_query.trim().is_empty() {
-        return (0..state.files.len()).collect();
-    }
-    let q = state.search_query.to_lowercase();
-    state
-        .files
-        .iter()
-        .enumerate()
-        .filter_map(|(i, f)| {
-            let name = f.name.to_lowercase();
-            if name.contains(&q) { Some(i) } else { None }
-        })
-        .collect()
-}
-
-fn scan_audio_files(dir: &Path) -> (Vec<AudioFile>, Option<String>) {
-    // Filter by common audio extensions. With rodio + symphonia-all, this should cover most use cases.
-    const EXTS: &[&str] = &[
-        "mp3", "flac", "wav", "ogg", "opus", "aac", "m4a", "alac", "aiff", "aif",
-    ];
-
-    let mut files = Vec::new();
-    let mut errors: Vec<String> = Vec::new();
-
-    match fs::read_dir(dir) {
-        Ok(entries) => {
-            for entry in entries {
-                match entry {
-                    Ok(e) => {
-                        let path = e.path();
-                        if path.is_file() {
-                            if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                                if EXTS.iter().any(|x| x.eq_ignore_ascii_case(ext)) {
-                                    let name = path
-                                        .file_name()
-                                        .and_then(|n| n.to_str())
-                                        .unwrap_or("Unknown")
-                                        .to_string();
-                                    files.push(AudioFile { name, path });
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => errors.push(format!("Error reading entry: {e}")),
-                }
+    let tempo_key = |f: &AudioFile| state.track_info.get(&f.path).and_then(|e| e.tempo_key);
+    let query = TrackQuery::parse(&state.search_query);
+    let mut indices: Vec<usize> = if query.is_empty() {
+        (0..state.files.len()).collect()
+    } else {
+        (0..state.files.len())
+            .filter(|&i| query.matches(&state.files[i].name, tempo_key(&state.files[i]).as_ref()))
+            .collect()
+    };
+    // The scan already sorts by name; tracks without a value go last either way
+    match state.sort {
+        SortColumn::Name => {
+            if state.sort_descending {
+                indices.reverse();
             }
         }
-        Err(e) => errors.push(format!("Failed to read directory: {e}")),
+        SortColumn::Bpm | SortColumn::Key => {
+            let value = |i: usize| {
+                let info = tempo_key(&state.files[i])?;
+                match state.sort {
+                    SortColumn::Bpm => info.bpm,
+                    _ => info.key.map(|k| (k.camelot_number() * 2 + k.minor as u32) as f32),
+                }
+            };
+            indices.sort_by(|&a, &b| match (value(a), value(b)) {
+                (Some(x), Some(y)) if state.sort_descending => y.total_cmp(&x),
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                (x, y) => y.is_some().cmp(&x.is_some()),
+            });
+        }
     }
+    indices
+}
 
-    files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+const BPM_COLUMN_WIDTH: f32 = 60.0;
+const KEY_COLUMN_WIDTH: f32 = 80.0;
 
-    let err = if errors.is_empty() {
-        None
-    } else {
-        Some(errors.join("; "))
-    };
-    (files, err)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortColumn {
+    Name,
+    Bpm,
+    Key,
 }
 
 // --- Tiny config (theme + last folder) ---
@@ -945,6 +2718,74 @@ struct AppConfig {
     last_folder: Option<PathBuf>,
     // Equalizer gains
     eq: Option<[f32; 10]>,
+    // Preferred output device name; None follows the system default
+    output_device: Option<String>,
+    // Output sample rate, buffer size and resampler quality
+    #[serde(default)]
+    output: OutputSettings,
+    // Balance, mono downmix and L/R swap
+    #[serde(default)]
+    channels: ChannelSettings,
+    // Headphone crossfeed
+    #[serde(default)]
+    crossfeed: CrossfeedSettings,
+    // Compressor/limiter
+    #[serde(default)]
+    compressor: CompressorSettings,
+    // Effect order, enabled and bypass flags
+    #[serde(default)]
+    dsp_chain: DspChainSettings,
+    #[serde(default)]
+    preamp_db: f32,
+    // Convolution impulse response and mix
+    #[serde(default)]
+    reverb: ReverbSettings,
+    // Spectrum analyzer FFT size
+    #[serde(default)]
+    spectrum_resolution: SpectrumResolution,
+    // Show a waveform overview instead of the plain seek slider
+    #[serde(default)]
+    waveform_seek_bar: bool,
+    // Let library analysis write REPLAYGAIN_* tags (FLAC only)
+    #[serde(default)]
+    write_replaygain_tags: bool,
+    // Skip silence at track start/end and its threshold
+    #[serde(default)]
+    silence_trim: SilenceTrimSettings,
+    #[serde(default = "default_volume")]
+    volume: f32,
+    // HTTP remote control: enabled, port and token
+    #[serde(default)]
+    remote: RemoteSettings,
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+// Per-user cache directory for derived data (thumbnails, analysis results).
+pub(crate) fn cache_dir(name: &str) -> Option<PathBuf> {
+    use directories::ProjectDirs;
+    let proj = ProjectDirs::from("dev", "RustSamples", "RustAudioPlayer")?;
+    let dir = proj.cache_dir().join(name);
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+// Cache file for data derived from `track`, e.g. `cache_entry("art", track, "64.png")`.
+// Keyed by path, size and mtime so edited files are derived afresh. The key is
+// a 64-bit FNV-1a hash, which (unlike `DefaultHasher`) stays the same across
+// Rust releases, so caches survive toolchain upgrades.
+pub(crate) fn cache_entry(kind: &str, track: &Path, ext: &str) -> Option<PathBuf> {
+    let meta = std::fs::metadata(track).ok()?;
+    let modified = meta.modified().ok().and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos());
+    let (len, modified) = (meta.len().to_le_bytes(), modified.to_le_bytes());
+    let hash = fnv1a(track.as_os_str().as_encoded_bytes().iter().chain(&len).chain(&modified));
+    Some(cache_dir(kind)?.join(format!("{hash:016x}.{ext}")))
+}
+
+fn fnv1a<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
 }
 
 fn config_path() -> Option<PathBuf> {
@@ -958,7 +2799,11 @@ fn config_path() -> Option<PathBuf> {
 fn load_config() -> Option<AppConfig> {
     let path = config_path()?;
     let data = std::fs::read_to_string(path).ok()?;
-    let mut cfg: AppConfig = serde_json::from_str(&data).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&data).ok()?;
+    let mut cfg: AppConfig = serde_json::from_value(json.clone()).ok()?;
+    if json.get("dsp_chain").is_none() {
+        cfg.dsp_chain = migrate_dsp_chain(&json);
+    }
     // Validate last folder exists
     if let Some(ref p) = cfg.last_folder {
         if !p.exists() {
@@ -968,6 +2813,19 @@ fn load_config() -> Option<AppConfig> {
     Some(cfg)
 }
 
+// Configs from before the effect chain kept crossfeed and compressor on/off
+// in their own settings; carry those over
+fn migrate_dsp_chain(json: &serde_json::Value) -> DspChainSettings {
+    let mut chain = DspChainSettings::default();
+    for (key, kind) in [("crossfeed", EffectKind::Crossfeed), ("compressor", EffectKind::Compressor)] {
+        let enabled = json.pointer(&format!("/{key}/enabled")).and_then(|v| v.as_bool()).unwrap_or(false);
+        if let Some(slot) = chain.slot_mut(kind) {
+            slot.enabled = enabled;
+        }
+    }
+    chain
+}
+
 fn save_config(cfg: &AppConfig) {
     if let Some(path) = config_path() {
         if let Ok(json) = serde_json::to_string_pretty(cfg) {
@@ -977,140 +2835,27 @@ fn save_config(cfg: &AppConfig) {
 }
 
 // serde helpers for Option<PathBuf> as plain string
-mod opt_path {
-    use super::*;
-    use serde::{Serializer, Deserializer};
-
-    pub fn serialize<S>(val: &Option<PathBuf>, s: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match val {
-            Some(p) => s.serialize_some(&p.to_string_lossy()),
-            None => s.serialize_none(),
-        }
-    }
-
-    pub fn deserialize<'de, D>(d: D) -> Result<Option<PathBuf>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-    let opt: Option<String> = <Option<String> as serde::Deserialize>::deserialize(d)?;
-        Ok(opt.map(PathBuf::from))
-    }
-}
-
-// ===== Equalizer implementation =====
-#[derive(Clone, Copy)]
-struct BiquadCoeffs { b0: f32, b1: f32, b2: f32, a1: f32, a2: f32 }
-
-#[derive(Clone, Copy, Default)]
-struct BiquadState { z1: f32, z2: f32 }
-
-impl BiquadState {
-    fn process(&mut self, x: f32, c: BiquadCoeffs) -> f32 {
-        // Direct Form I transposed
-        let y = c.b0 * x + self.z1;
-        self.z1 = c.b1 * x - c.a1 * y + self.z2;
-        self.z2 = c.b2 * x - c.a2 * y;
-        y
-    }
-}
-
-fn peaking_eq(sr: f32, f0: f32, q: f32, gain_db: f32) -> BiquadCoeffs {
-    let a = 10f32.powf(gain_db / 40.0);
-    let w0 = 2.0 * std::f32::consts::PI * (f0 / sr);
-    let alpha = w0.sin() / (2.0 * q);
-    let cosw = w0.cos();
-
-    let b0 = 1.0 + alpha * a;
-    let b1 = -2.0 * cosw;
-    let b2 = 1.0 - alpha * a;
-    let a0 = 1.0 + alpha / a;
-    let a1 = -2.0 * cosw;
-    let a2 = 1.0 - alpha / a;
-
-    let inv_a0 = 1.0 / a0;
-    BiquadCoeffs { b0: b0 * inv_a0, b1: b1 * inv_a0, b2: b2 * inv_a0, a1: a1 * inv_a0, a2: a2 * inv_a0 }
-}
 
-struct Equalizer {
-    gains_db: Mutex<[f32; 10]>,
-    version: AtomicU64,
-}
-impl Default for Equalizer { fn default() -> Self { Self { gains_db: Mutex::new([0.0; 10]), version: AtomicU64::new(0) } } }
-impl Equalizer {
-    fn set_gains_db(&self, gains: [f32; 10]) {
-        if let Ok(mut g) = self.gains_db.lock() { *g = gains; }
-        self.version.fetch_add(1, Ordering::Relaxed);
-    }
-    fn snapshot_gains(&self) -> [f32; 10] {
-        self.gains_db.lock().map(|g| *g).unwrap_or([0.0; 10])
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-struct EqSource<S: rodio::Source> {
-    inner: S,
-    // Per-band coefficients at current sample rate
-    coeffs: [BiquadCoeffs; 10],
-    // Stereo states for each band
-    l: [BiquadState; 10],
-    r: [BiquadState; 10],
-    next_left: bool,
-    shared: Arc<Equalizer>,
-    last_version: u64,
-    // Small fade-in to mask discontinuity on (re)start
-    fade_len: u32,
-    fade_idx: u32,
-}
-
-impl<S: rodio::Source> EqSource<S> {
-    fn new(inner: S, shared: Arc<Equalizer>) -> Self {
-        let sr = inner.sample_rate() as f32;
-        let freqs = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
-        let q = 1.0; // broad bands
-        let mut coeffs = [BiquadCoeffs { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0 }; 10];
-        let gains = shared.snapshot_gains();
-        for i in 0..10 { coeffs[i] = peaking_eq(sr, freqs[i], q, gains[i]); }
-        let last_version = shared.version.load(Ordering::Relaxed);
-        let fade_len = ((sr * 0.005).ceil() as u32).max(1);
-        Self { inner, coeffs, l: [BiquadState::default(); 10], r: [BiquadState::default(); 10], next_left: true, shared, last_version, fade_len, fade_idx: 0 }
+    #[test]
+    fn cache_keys_use_the_reference_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
     }
-}
 
-impl<S: rodio::Source<Item = f32>> Iterator for EqSource<S> {
-    type Item = f32;
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut x = self.inner.next()?;
-        // Refresh coeffs if updated
-        let current_version = self.shared.version.load(Ordering::Relaxed);
-        if current_version != self.last_version {
-            let sr = self.inner.sample_rate() as f32;
-            let freqs = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
-            let q = 1.0;
-            let gains = self.shared.snapshot_gains();
-            for i in 0..10 { self.coeffs[i] = peaking_eq(sr, freqs[i], q, gains[i]); }
-            self.last_version = current_version;
-        }
-        if self.next_left {
-            for i in 0..10 { x = self.l[i].process(x, self.coeffs[i]); }
-        } else {
-            for i in 0..10 { x = self.r[i].process(x, self.coeffs[i]); }
-        }
-        // Apply fade-in ramp
-        if self.fade_idx < self.fade_len {
-            let t = self.fade_idx as f32 / self.fade_len as f32;
-            x *= t;
-            self.fade_idx += 1;
-        }
-        self.next_left = !self.next_left;
-        Some(x)
+    #[test]
+    fn legacy_effect_flags_seed_the_dsp_chain() {
+        let json = serde_json::json!({
+            "crossfeed": { "enabled": true, "cutoff_hz": 700.0 },
+            "compressor": { "enabled": false },
+        });
+        let chain = migrate_dsp_chain(&json);
+        assert!(chain.is_enabled(EffectKind::Crossfeed));
+        assert!(!chain.is_enabled(EffectKind::Compressor));
+        assert!(chain.is_enabled(EffectKind::Equalizer));
     }
 }
-
-impl<S: rodio::Source<Item = f32>> rodio::Source for EqSource<S> {
-    fn channels(&self) -> u16 { self.inner.channels() }
-    fn sample_rate(&self) -> u32 { self.inner.sample_rate() }
-    fn current_span_len(&self) -> Option<usize> { self.inner.current_span_len() }
-    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
-}
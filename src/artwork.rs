@@ -0,0 +1,154 @@
+// Cover art lookup: embedded pictures first (ID3 APIC, FLAC PICTURE, MP4 covr
+// are all surfaced by Symphonia as "visuals"), then well-known image files
+// next to the track. Decoded images are downscaled and cached on disk so
+// browsing a folder a second time doesn't re-decode every picture.
+use iced::widget::image::Handle as ImageHandle;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use symphonia::core::formats::FormatOptions as SymFormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions as SymMetadataOptions, MetadataRevision, StandardVisualKey};
+use symphonia::core::probe::Hint as SymHint;
+use symphonia::default::get_probe as sym_get_probe;
+
+// Edge length (in pixels) of the image shown in the now-playing panel
+pub(crate) const NOW_PLAYING_SIZE: u32 = 256;
+// Edge length of the per-track thumbnails in the file list
+pub(crate) const THUMB_SIZE: u32 = 64;
+
+// File stems checked (case-insensitively) when a track has no embedded art
+const FOLDER_ART_STEMS: &[&str] = &["cover", "folder", "front", "album", "albumart"];
+const FOLDER_ART_EXTS: &[&str] = &["jpg", "jpeg", "png", "bmp", "gif", "webp"];
+
+// Load artwork for a track, downscaled to fit in `size` x `size`.
+// This does file IO and image decoding, so call it off the UI thread.
+pub(crate) fn load_artwork(track: &Path, size: u32) -> Option<ImageHandle> {
+    let cache = cache_file(track, size);
+    if let Some(cache) = &cache {
+        // A ".none" marker remembers that we already looked and found nothing,
+        // until something changes in the track's folder (e.g. a cover.jpg appears)
+        if no_art_marker_is_current(&cache.with_extension("none"), track) {
+            return None;
+        }
+        if let Ok(img) = image::open(cache) {
+            let rgba = img.to_rgba8();
+            return Some(ImageHandle::from_rgba(rgba.width(), rgba.height(), rgba.into_raw()));
+        }
+    }
+
+    let decoded = embedded_picture(track)
+        .and_then(|bytes| image::load_from_memory(&bytes).ok())
+        .or_else(|| folder_picture(track).and_then(|p| image::open(p).ok()));
+
+    let Some(img) = decoded else {
+        if let Some(cache) = &cache {
+            let _ = fs::write(cache.with_extension("none"), b"");
+        }
+        return None;
+    };
+
+    let rgba = img.thumbnail(size, size).to_rgba8();
+    if let Some(cache) = &cache {
+        let _ = rgba.save_with_format(cache, image::ImageFormat::Png);
+    }
+    Some(ImageHandle::from_rgba(rgba.width(), rgba.height(), rgba.into_raw()))
+}
+
+// Image file with the (downscaled) artwork, for handing to other programs.
+// Loads it into the cache first if needed, so call it off the UI thread too.
+pub(crate) fn artwork_file(track: &Path, size: u32) -> Option<PathBuf> {
+    load_artwork(track, size)?;
+    cache_file(track, size).filter(|p| p.exists())
+}
+
+// Pick the best embedded picture: the front cover if tagged, else the first one.
+fn embedded_picture(path: &Path) -> Option<Vec<u8>> {
+    let mut hint = SymHint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let file = fs::File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut probed = sym_get_probe()
+        .format(&hint, mss, &SymFormatOptions::default(), &SymMetadataOptions::default())
+        .ok()?;
+
+    fn pick(rev: &MetadataRevision) -> Option<Vec<u8>> {
+        let visuals = rev.visuals();
+        visuals
+            .iter()
+            .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+            .or_else(|| visuals.first())
+            .map(|v| v.data.to_vec())
+    }
+
+    // Metadata found while probing (e.g. ID3v2 in front of an MP3) comes first,
+    // then whatever the container itself carries (FLAC, MP4, Vorbis comments).
+    if let Some(found) = probed.metadata.get().as_ref().and_then(|m| m.current()).and_then(pick) {
+        return Some(found);
+    }
+    let mut format = probed.format;
+    let meta = format.metadata();
+    meta.current().and_then(pick)
+}
+
+// Look for cover.jpg, folder.png, etc. in the track's directory.
+fn folder_picture(track: &Path) -> Option<PathBuf> {
+    let dir = track.parent()?;
+    let mut candidates: Vec<(usize, PathBuf)> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter_map(|p| {
+            let stem = p.file_stem()?.to_str()?.to_lowercase();
+            let ext = p.extension()?.to_str()?;
+            if !FOLDER_ART_EXTS.iter().any(|x| x.eq_ignore_ascii_case(ext)) {
+                return None;
+            }
+            let rank = FOLDER_ART_STEMS.iter().position(|s| *s == stem)?;
+            Some((rank, p))
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+    candidates.into_iter().next().map(|(_, p)| p)
+}
+
+// A marker older than the folder's last change may be missing a new image file.
+fn no_art_marker_is_current(marker: &Path, track: &Path) -> bool {
+    let Ok(marked) = fs::metadata(marker).and_then(|m| m.modified()) else { return false };
+    let folder_changed = track.parent().and_then(|dir| fs::metadata(dir).and_then(|m| m.modified()).ok());
+    folder_changed.is_none_or(|changed| marked >= changed)
+}
+
+// One cached image per track and size
+fn cache_file(track: &Path, size: u32) -> Option<PathBuf> {
+    crate::app::cache_entry("art", track, &format!("{size}.png"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn no_art_marker_expires_when_the_folder_changes() {
+        let dir = std::env::temp_dir().join(format!("art-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let track = dir.join("song.flac");
+        let marker = std::env::temp_dir().join(format!("art-test-{}.none", std::process::id()));
+        fs::write(&track, b"").unwrap();
+        assert!(!no_art_marker_is_current(&marker, &track));
+
+        fs::write(&marker, b"").unwrap();
+        assert!(no_art_marker_is_current(&marker, &track));
+
+        // Written before the folder last changed, e.g. before cover.jpg was added
+        let earlier = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options().write(true).open(&marker).unwrap().set_modified(earlier).unwrap();
+        assert!(!no_art_marker_is_current(&marker, &track));
+
+        let _ = fs::remove_file(&marker);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
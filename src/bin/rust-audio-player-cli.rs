@@ -0,0 +1,12 @@
+// Console build of `rust-audio-player play`. Release builds of the main binary
+// use the Windows GUI subsystem, which has no console to print to or read keys
+// from, so Windows users run the terminal player through this one.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Also accept the subcommand, so both spellings from the README work
+    let args = args.strip_prefix(&["play".to_string()]).unwrap_or(&args);
+    if let Err(e) = rust_audio_player::run_cli(args) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
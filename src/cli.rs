@@ -0,0 +1,343 @@
+// Headless playback: `rust-audio-player play <files|folders|playlists> [--shuffle]
+// [--repeat] [--eq <preset>]`. Drives the same AudioEngine as the GUI without
+// opening a window; the terminal shows the current track and position and
+// takes single-key controls.
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, style, terminal};
+
+use crate::app::format_time;
+use crate::engine::{self, AudioEngine, AudioFile, EngineEvent, OutputSettings};
+
+const USAGE: &str = "Usage: rust-audio-player play <files|folders|playlists>... [--shuffle] [--repeat] [--eq <preset>]";
+const KEYS: &str = "space pause/resume · n/p next/previous · ←/→ seek 5 s · +/- volume · q quit";
+const SEEK_STEP: Duration = Duration::from_secs(5);
+const VOLUME_STEP: f32 = 0.05;
+// How often the position line is redrawn and the end of the track checked
+const TICK: Duration = Duration::from_millis(200);
+// "Previous" restarts the current track once it has played this long
+const RESTART_AFTER: Duration = Duration::from_secs(3);
+
+struct Options {
+    inputs: Vec<PathBuf>,
+    shuffle: bool,
+    repeat: bool,
+    eq: Option<[f32; 10]>,
+}
+
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut options = Options { inputs: Vec::new(), shuffle: false, repeat: false, eq: None };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--shuffle" => options.shuffle = true,
+            "--repeat" => options.repeat = true,
+            "--eq" => {
+                let name = args.next().ok_or("--eq needs a preset name")?;
+                let presets: Vec<&str> = engine::EQ_PRESETS.iter().map(|(n, _)| *n).collect();
+                let gains = engine::eq_preset(name)
+                    .ok_or_else(|| format!("Unknown EQ preset \"{name}\" (available: {})", presets.join(", ")))?;
+                options.eq = Some(gains);
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {flag}\n{USAGE}")),
+            path => options.inputs.push(PathBuf::from(path)),
+        }
+    }
+    if options.inputs.is_empty() {
+        return Err(USAGE.into());
+    }
+    Ok(Some(options))
+}
+
+// Expand folders (like the GUI's folder scan, CUE sheets included) and M3U
+// playlists into one list of tracks, in argument order.
+fn collect_tracks(inputs: &[PathBuf]) -> Result<Vec<AudioFile>, String> {
+    let mut tracks = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let (files, err) = engine::scan_audio_files(input);
+            if let Some(e) = err {
+                eprintln!("{}: {e}", input.display());
+            }
+            tracks.extend(files);
+        } else if is_playlist(input) {
+            tracks.extend(load_playlist(input)?.iter().map(|p| file_entry(p)));
+        } else if input.is_file() {
+            tracks.push(file_entry(input));
+        } else {
+            return Err(format!("No such file or folder: {}", input.display()));
+        }
+    }
+    Ok(tracks)
+}
+
+fn file_entry(path: &Path) -> AudioFile {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("Unknown").to_string();
+    AudioFile { name, path: path.to_path_buf(), cue: None }
+}
+
+fn is_playlist(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("m3u") || e.eq_ignore_ascii_case("m3u8"))
+}
+
+// Paths listed in an M3U playlist; relative entries are relative to the playlist.
+fn load_playlist(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(data
+        .lines()
+        .map(|l| l.trim().trim_start_matches('\u{feff}'))
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| base.join(l))
+        .collect())
+}
+
+// Fisher-Yates with a time-seeded xorshift; good enough for play order.
+fn shuffle<T>(items: &mut [T]) {
+    let mut seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(1) | 1;
+    for i in (1..items.len()).rev() {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        items.swap(i, (seed % (i as u64 + 1)) as usize);
+    }
+}
+
+// Raw terminal mode for single-key input, restored however we exit.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<Self, String> {
+        terminal::enable_raw_mode().map_err(|e| format!("Terminal error: {e}"))?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        println!();
+    }
+}
+
+struct CliPlayer {
+    engine: AudioEngine,
+    tracks: Vec<AudioFile>,
+    current: usize,
+    repeat: bool,
+}
+
+impl CliPlayer {
+    // Start the track at `index`, skipping forward past files that fail to
+    // play. Returns false when nothing is left to play.
+    fn start(&mut self, mut index: usize) -> bool {
+        for _ in 0..self.tracks.len() {
+            let file = &self.tracks[index];
+            match self.engine.play_track(file) {
+                Ok(()) => {
+                    self.current = index;
+                    print_line(&format!("Now playing: {}", file.name));
+                    return true;
+                }
+                Err(e) => print_line(&format!("{}: {e}", file.name)),
+            }
+            match self.next_index(index) {
+                Some(next) => index = next,
+                None => break,
+            }
+        }
+        self.engine.stop();
+        false
+    }
+
+    fn next_index(&self, index: usize) -> Option<usize> {
+        if index + 1 < self.tracks.len() {
+            Some(index + 1)
+        } else {
+            self.repeat.then_some(0)
+        }
+    }
+
+    // Move on when the track ends; consecutive CUE tracks of one file continue
+    // without restarting the sink, as in the GUI.
+    fn advance(&mut self) -> bool {
+        let Some(next) = self.next_index(self.current) else { return false };
+        let file = &self.tracks[next];
+        if self.engine.can_continue_into(file) {
+            self.engine.continue_into(file);
+            self.current = next;
+            print_line(&format!("Now playing: {}", file.name));
+            return true;
+        }
+        self.start(next)
+    }
+
+    fn next(&mut self) -> bool {
+        match self.next_index(self.current) {
+            Some(next) => self.start(next),
+            None => false,
+        }
+    }
+
+    fn previous(&mut self) -> bool {
+        let index = if self.engine.current_position() >= RESTART_AFTER {
+            self.current
+        } else if self.current > 0 {
+            self.current - 1
+        } else if self.repeat {
+            self.tracks.len() - 1
+        } else {
+            0
+        };
+        self.start(index)
+    }
+
+    fn seek_by(&mut self, forward: bool) {
+        let position = self.engine.current_position();
+        let target = if forward { position + SEEK_STEP } else { position.saturating_sub(SEEK_STEP) };
+        if let Err(e) = self.engine.seek_to(target) {
+            print_line(&e);
+        }
+    }
+
+    fn change_volume(&mut self, delta: f32) {
+        let volume = self.engine.volume() + delta;
+        self.engine.set_volume(volume);
+    }
+
+    fn toggle_pause(&mut self) {
+        if self.engine.is_paused() {
+            self.engine.play();
+        } else {
+            self.engine.pause();
+        }
+    }
+
+    // Rewrite the position line in place
+    fn draw_status(&self) {
+        let paused = self.engine.is_paused();
+        let position = format_time(self.engine.current_position());
+        let total = self.engine.total_duration().map(format_time).unwrap_or_else(|| "--:--".into());
+        let line = format!(
+            "{} {position} / {total}  [{}/{}]  vol {:.0}%",
+            if paused { "⏸" } else { "▶" },
+            self.current + 1,
+            self.tracks.len(),
+            self.engine.volume() * 100.0
+        );
+        let mut out = std::io::stdout();
+        let _ = crossterm::execute!(
+            out,
+            cursor::MoveToColumn(0),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::Print(line)
+        );
+        let _ = out.flush();
+    }
+}
+
+// Wait up to one tick for a key press
+fn read_key() -> Result<Option<KeyEvent>, String> {
+    if !event::poll(TICK).map_err(|e| format!("Terminal error: {e}"))? {
+        return Ok(None);
+    }
+    Ok(match event::read() {
+        Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => Some(key),
+        _ => None,
+    })
+}
+
+// Print a whole line above the position line (raw mode needs the explicit \r)
+fn print_line(text: &str) {
+    let mut out = std::io::stdout();
+    let _ = crossterm::execute!(
+        out,
+        cursor::MoveToColumn(0),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        style::Print(format!("{text}\r\n"))
+    );
+}
+
+// Entry point for `rust-audio-player play ...`; `args` excludes the `play` itself.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let Some(options) = parse_args(args)? else {
+        println!("{USAGE}\nKeys: {KEYS}");
+        return Ok(());
+    };
+    let mut tracks = collect_tracks(&options.inputs)?;
+    if tracks.is_empty() {
+        return Err("No playable files found.".into());
+    }
+    if options.shuffle {
+        shuffle(&mut tracks);
+    }
+    let engine = AudioEngine::new(None, OutputSettings::default())?;
+    if let Some(gains) = options.eq {
+        engine.set_eq_gains(gains);
+    }
+    let events = engine.subscribe();
+    let mut player = CliPlayer { engine, tracks, current: 0, repeat: options.repeat };
+
+    let _raw = RawMode::enable()?;
+    print_line(KEYS);
+    if !player.start(0) {
+        return Err("None of the files could be played.".into());
+    }
+    loop {
+        if let Some(key) = read_key()? {
+            let playing = match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                KeyCode::Char(' ') => {
+                    player.toggle_pause();
+                    true
+                }
+                KeyCode::Char('n') => player.next(),
+                KeyCode::Char('p') => player.previous(),
+                KeyCode::Left => {
+                    player.seek_by(false);
+                    true
+                }
+                KeyCode::Right => {
+                    player.seek_by(true);
+                    true
+                }
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    player.change_volume(VOLUME_STEP);
+                    true
+                }
+                KeyCode::Char('-') => {
+                    player.change_volume(-VOLUME_STEP);
+                    true
+                }
+                _ => true,
+            };
+            if !playing {
+                break;
+            }
+        }
+        if let Some(msg) = player.engine.recover_lost_device() {
+            print_line(&msg);
+        }
+        let mut ended = false;
+        for event in events.try_iter() {
+            match event {
+                EngineEvent::TrackEnded => ended = true,
+                EngineEvent::Error(e) => print_line(&e),
+                _ => {}
+            }
+        }
+        // Ignore a track end that arrived after skipping to another track
+        if ended && player.engine.track_ended() && !player.advance() {
+            print_line("Playback finished.");
+            break;
+        }
+        player.draw_status();
+    }
+    player.engine.stop();
+    Ok(())
+}
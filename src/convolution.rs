@@ -0,0 +1,212 @@
+// Impulse response loading and uniformly partitioned FFT convolution
+// (overlap-save with a frequency-domain delay line). Latency is one block.
+//
+// Every block multiplies each partition's spectrum with the matching input
+// spectrum, so the cost grows linearly with the response length. Inputs and
+// responses are real, so only the first half of each spectrum is stored and
+// multiplied; the rest is its mirror image. Responses are also capped at
+// `MAX_IR_SECS`: at 48 kHz that is 375 partitions of 513 bins, or about 190k
+// complex multiply-adds per channel per block. That leaves plenty of headroom
+// on the audio thread and covers typical room and hall recordings; longer
+// tails would need non-uniform partitions to stay affordable.
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::fft::{Complex, Fft};
+
+// Frames per convolution block; also the stage's latency
+pub(crate) const BLOCK: usize = 512;
+// Longer responses are truncated to keep the CPU cost bounded (see above)
+const MAX_IR_SECS: f32 = 4.0;
+
+#[derive(Debug, Clone)]
+pub(crate) struct ImpulseResponse {
+    pub(crate) sample_rate: u32,
+    // One response per channel; a mono file applies to every output channel
+    pub(crate) channels: Vec<Vec<f32>>,
+}
+
+impl ImpulseResponse {
+    pub(crate) fn duration_secs(&self) -> f32 {
+        self.channels.first().map_or(0.0, |c| c.len() as f32 / self.sample_rate.max(1) as f32)
+    }
+
+    // Response for output channel `c`
+    pub(crate) fn channel(&self, c: usize) -> &[f32] {
+        &self.channels[c.min(self.channels.len() - 1)]
+    }
+
+    // Resample with a windowed sinc. Runs once per load/format change, off the audio thread.
+    pub(crate) fn resampled(&self, rate: u32) -> ImpulseResponse {
+        if rate == self.sample_rate || rate == 0 {
+            return self.clone();
+        }
+        use std::f64::consts::PI;
+        const HALF_TAPS: isize = 32;
+        let ratio = rate as f64 / self.sample_rate as f64;
+        let cutoff = ratio.min(1.0) * 0.95;
+        let channels = self
+            .channels
+            .iter()
+            .map(|input| {
+                let out_len = (input.len() as f64 * ratio).ceil() as usize;
+                (0..out_len)
+                    .map(|i| {
+                        let t = i as f64 / ratio;
+                        let centre = t.floor() as isize;
+                        let mut acc = 0.0;
+                        for k in centre - HALF_TAPS + 1..=centre + HALF_TAPS {
+                            let Some(&x) = usize::try_from(k).ok().and_then(|k| input.get(k)) else { continue };
+                            let d = t - k as f64;
+                            let sinc = if d.abs() < 1e-9 { 1.0 } else { (PI * cutoff * d).sin() / (PI * cutoff * d) };
+                            let w = 0.5 + 0.5 * (PI * d / HALF_TAPS as f64).cos();
+                            acc += x as f64 * cutoff * sinc * w;
+                        }
+                        acc as f32
+                    })
+                    .collect()
+            })
+            .collect();
+        ImpulseResponse { sample_rate: rate, channels }
+    }
+
+    // Scale so the loudest channel has unit energy; recorded room responses are
+    // otherwise much louder than the dry signal.
+    pub(crate) fn normalized(mut self) -> ImpulseResponse {
+        let energy = self
+            .channels
+            .iter()
+            .map(|c| c.iter().map(|x| x * x).sum::<f32>())
+            .fold(0.0f32, f32::max);
+        if energy > 1e-9 {
+            let scale = 1.0 / energy.sqrt();
+            self.channels.iter_mut().flatten().for_each(|x| *x *= scale);
+        }
+        self
+    }
+}
+
+// Decode an impulse response (WAV, or anything else Symphonia reads).
+pub(crate) fn load_impulse_response(path: &Path) -> Result<ImpulseResponse, String> {
+    use rodio::Source as _;
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open impulse response: {e}"))?;
+    let decoder = rodio::Decoder::try_from(file).map_err(|e| format!("Failed to decode impulse response: {e}"))?;
+    let sample_rate = decoder.sample_rate();
+    let n = decoder.channels().max(1) as usize;
+    let max_samples = (MAX_IR_SECS * sample_rate as f32) as usize * n;
+    let mut channels = vec![Vec::new(); n];
+    for (i, x) in decoder.take(max_samples).enumerate() {
+        channels[i % n].push(x);
+    }
+    if channels[0].is_empty() {
+        return Err("Impulse response is empty".into());
+    }
+    Ok(ImpulseResponse { sample_rate, channels })
+}
+
+// Convolves one channel with a fixed response, `BLOCK` samples at a time.
+pub(crate) struct Convolver {
+    fft: Arc<Fft>,
+    // Spectra of the response split into BLOCK-sized partitions (bins 0..=BLOCK)
+    partitions: Vec<Vec<Complex>>,
+    // Spectra of the most recent input windows, newest at `fdl_pos` (bins 0..=BLOCK)
+    fdl: Vec<Vec<Complex>>,
+    fdl_pos: usize,
+    // Previous and current input block
+    window: Vec<f32>,
+    scratch: Vec<Complex>,
+    acc: Vec<Complex>,
+}
+
+impl Convolver {
+    // `fft` must be of size 2 * BLOCK
+    pub(crate) fn new(ir: &[f32], fft: Arc<Fft>) -> Self {
+        let n = fft.len();
+        let partitions: Vec<Vec<Complex>> = ir
+            .chunks(BLOCK)
+            .map(|chunk| {
+                let mut spectrum = vec![Complex::ZERO; n];
+                for (dst, &x) in spectrum.iter_mut().zip(chunk) {
+                    dst.re = x;
+                }
+                fft.forward(&mut spectrum);
+                spectrum.truncate(n / 2 + 1);
+                spectrum
+            })
+            .collect();
+        let fdl = vec![vec![Complex::ZERO; n / 2 + 1]; partitions.len().max(1)];
+        Self { fft, partitions, fdl, fdl_pos: 0, window: vec![0.0; n], scratch: vec![Complex::ZERO; n], acc: vec![Complex::ZERO; n] }
+    }
+
+    // Replace `block` (BLOCK samples) with the convolved output. Doesn't allocate.
+    pub(crate) fn process_block(&mut self, block: &mut [f32]) {
+        let n = self.fft.len();
+        self.window.copy_within(BLOCK.., 0);
+        self.window[BLOCK..].copy_from_slice(block);
+        for (dst, &x) in self.scratch.iter_mut().zip(&self.window) {
+            *dst = Complex::new(x, 0.0);
+        }
+        self.fft.forward(&mut self.scratch);
+        let half = n / 2;
+        self.fdl[self.fdl_pos].copy_from_slice(&self.scratch[..=half]);
+
+        self.acc.iter_mut().for_each(|c| *c = Complex::ZERO);
+        let count = self.fdl.len();
+        for (p, h) in self.partitions.iter().enumerate() {
+            let x = &self.fdl[(self.fdl_pos + count - p) % count];
+            for k in 0..=half {
+                self.acc[k] = self.acc[k] + x[k] * h[k];
+            }
+        }
+        // The spectrum of a real signal is conjugate-symmetric
+        for k in half + 1..n {
+            self.acc[k] = self.acc[n - k].conj();
+        }
+        self.fft.inverse(&mut self.acc);
+        // Overlap-save: only the second half is free of wrap-around
+        for (dst, c) in block.iter_mut().zip(&self.acc[BLOCK..]) {
+            *dst = c.re;
+        }
+        self.fdl_pos = (self.fdl_pos + 1) % count;
+    }
+
+    // Forget earlier input, as if the convolver were new. Doesn't allocate.
+    pub(crate) fn reset(&mut self) {
+        self.fdl.iter_mut().flatten().for_each(|c| *c = Complex::ZERO);
+        self.fdl_pos = 0;
+        self.window.iter_mut().for_each(|x| *x = 0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direct(x: &[f32], h: &[f32]) -> Vec<f32> {
+        (0..x.len()).map(|n| h.iter().enumerate().filter(|&(k, _)| k <= n).map(|(k, hk)| hk * x[n - k]).sum()).collect()
+    }
+
+    #[test]
+    fn convolver_matches_direct_convolution() {
+        // Longer than one partition, so the delay line is exercised
+        let h: Vec<f32> = (0..BLOCK + 200).map(|i| (-(i as f32) / 150.0).exp() * if i % 3 == 0 { 1.0 } else { -0.5 }).collect();
+        let x: Vec<f32> = (0..4 * BLOCK).map(|i| ((i * 7919) % 101) as f32 / 50.0 - 1.0).collect();
+        let mut conv = Convolver::new(&h, Arc::new(Fft::new(2 * BLOCK)));
+        let mut y = x.clone();
+        for block in y.chunks_mut(BLOCK) {
+            conv.process_block(block);
+        }
+        for (n, (a, b)) in y.iter().zip(direct(&x, &h)).enumerate() {
+            assert!((a - b).abs() < 1e-3, "sample {n}: {a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn unit_impulse_passes_the_signal_through() {
+        let mut conv = Convolver::new(&[1.0], Arc::new(Fft::new(2 * BLOCK)));
+        let x: Vec<f32> = (0..BLOCK).map(|i| (i as f32 * 0.1).sin()).collect();
+        let mut y = x.clone();
+        conv.process_block(&mut y);
+        assert!(y.iter().zip(&x).all(|(a, b)| (a - b).abs() < 1e-5));
+    }
+}
@@ -0,0 +1,191 @@
+// CUE sheet parsing for single-file album rips. Each TRACK becomes a virtual
+// entry pointing at a time range inside the referenced audio file.
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Portion of a file that makes up one virtual track. `end` is None for the
+/// last track, which runs to the end of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackRange {
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CueTrack {
+    pub(crate) file: PathBuf,
+    pub(crate) number: u32,
+    pub(crate) title: Option<String>,
+    pub(crate) performer: Option<String>,
+    pub(crate) range: TrackRange,
+}
+
+// Parse a CUE sheet. FILE entries are resolved relative to the sheet's directory.
+pub(crate) fn parse_cue_file(path: &Path) -> Result<Vec<CueTrack>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    // Older rippers write CUE sheets in Latin-1/CP1252 rather than UTF-8
+    let text = match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    Ok(parse_cue(&text, dir))
+}
+
+pub(crate) fn parse_cue(text: &str, dir: &Path) -> Vec<CueTrack> {
+    struct Pending {
+        file: PathBuf,
+        number: u32,
+        title: Option<String>,
+        performer: Option<String>,
+        start: Option<Duration>,
+    }
+
+    let mut album_performer: Option<String> = None;
+    let mut current_file: Option<PathBuf> = None;
+    let mut pending: Vec<Pending> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        let (cmd, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match cmd.to_ascii_uppercase().as_str() {
+            "FILE" => current_file = Some(dir.join(unquote(strip_file_type(rest)))),
+            "TRACK" => {
+                let Some(file) = current_file.clone() else { continue };
+                let number = rest.split_whitespace().next().and_then(|n| n.parse().ok()).unwrap_or(0);
+                pending.push(Pending { file, number, title: None, performer: None, start: None });
+            }
+            "TITLE" => {
+                if let Some(t) = pending.last_mut() {
+                    t.title = Some(unquote(rest));
+                }
+            }
+            "PERFORMER" => match pending.last_mut() {
+                Some(t) => t.performer = Some(unquote(rest)),
+                None => album_performer = Some(unquote(rest)),
+            },
+            "INDEX" => {
+                let mut parts = rest.split_whitespace();
+                let (Some(idx), Some(stamp)) = (parts.next(), parts.next()) else { continue };
+                // INDEX 00 marks the pregap; playback starts at INDEX 01
+                if let Some(t) = pending.last_mut().filter(|_| idx.parse::<u32>().ok() == Some(1)) {
+                    t.start = parse_msf(stamp);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    for (i, t) in pending.iter().enumerate() {
+        let Some(start) = t.start else { continue };
+        // A track ends where the next one in the same file begins
+        let end = pending[i + 1..]
+            .iter()
+            .find(|n| n.start.is_some())
+            .filter(|n| n.file == t.file)
+            .and_then(|n| n.start);
+        tracks.push(CueTrack {
+            file: t.file.clone(),
+            number: t.number,
+            title: t.title.clone(),
+            performer: t.performer.clone().or_else(|| album_performer.clone()),
+            range: TrackRange { start, end },
+        });
+    }
+    tracks
+}
+
+// `FILE "name.flac" WAVE` -> `"name.flac"`
+fn strip_file_type(rest: &str) -> &str {
+    match rest.rfind(['"']) {
+        Some(i) => &rest[..=i],
+        None => rest.rsplit_once(char::is_whitespace).map(|(name, _)| name).unwrap_or(rest),
+    }
+}
+
+fn unquote(s: &str) -> String {
+    s.trim().trim_matches('"').to_string()
+}
+
+// mm:ss:ff with 75 frames per second
+fn parse_msf(stamp: &str) -> Option<Duration> {
+    let mut parts = stamp.split(':').map(|p| p.parse::<u64>().ok());
+    let (m, s, f) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(Duration::from_millis((m * 60 + s) * 1000) + Duration::from_nanos(f * 1_000_000_000 / 75))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = r#"PERFORMER "Album Artist"
+TITLE "Album"
+FILE "disc.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "One"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Two"
+    PERFORMER "Guest"
+    INDEX 00 03:58:00
+    INDEX 01 04:00:37
+  TRACK 03 AUDIO
+    TITLE "Three"
+    INDEX 01 08:30:00
+"#;
+
+    #[test]
+    fn msf_stamps_use_75_frames_per_second() {
+        assert_eq!(parse_msf("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_msf("01:02:00"), Some(Duration::from_secs(62)));
+        assert_eq!(parse_msf("00:01:75").map(|d| d.as_millis()), Some(2_000));
+        assert_eq!(parse_msf("00:00:15").map(|d| d.as_millis()), Some(200));
+    }
+
+    #[test]
+    fn malformed_msf_stamps_are_rejected() {
+        assert_eq!(parse_msf(""), None);
+        assert_eq!(parse_msf("01:02"), None);
+        assert_eq!(parse_msf("01:xx:00"), None);
+        assert_eq!(parse_msf("-1:00:00"), None);
+    }
+
+    #[test]
+    fn tracks_span_from_index_01_to_the_next_track() {
+        let tracks = parse_cue(SHEET, Path::new("/music"));
+        assert_eq!(tracks.len(), 3);
+        assert!(tracks.iter().all(|t| t.file == Path::new("/music/disc.flac")));
+
+        // The pregap at INDEX 00 belongs to the previous track
+        let two_start = Duration::from_secs(240) + Duration::from_nanos(37 * 1_000_000_000 / 75);
+        assert_eq!(tracks[0].range, TrackRange { start: Duration::ZERO, end: Some(two_start) });
+        assert_eq!(tracks[1].range, TrackRange { start: two_start, end: Some(Duration::from_secs(510)) });
+        // The last track runs to the end of the file
+        assert_eq!(tracks[2].range.end, None);
+
+        assert_eq!(tracks[0].performer.as_deref(), Some("Album Artist"));
+        assert_eq!(tracks[1].performer.as_deref(), Some("Guest"));
+        assert_eq!(tracks[1].title.as_deref(), Some("Two"));
+    }
+
+    #[test]
+    fn tracks_end_at_their_own_file() {
+        let sheet = "FILE \"a.wav\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 02:00:00\n\
+                     FILE \"b.wav\" WAVE\nTRACK 03 AUDIO\nINDEX 01 00:00:00\n";
+        let tracks = parse_cue(sheet, Path::new("."));
+        assert_eq!(tracks.iter().map(|t| t.number).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(tracks[1].range.end, None);
+        assert_eq!(tracks[2].file, Path::new("./b.wav"));
+    }
+
+    #[test]
+    fn tracks_with_a_bad_index_are_skipped() {
+        let sheet = "FILE \"a.wav\" WAVE\nTRACK 01 AUDIO\nINDEX 01 0:xx:00\nTRACK 02 AUDIO\nINDEX 01 01:00:00\n";
+        let tracks = parse_cue(sheet, Path::new("."));
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].number, 2);
+        assert_eq!(tracks[0].range.start, Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,3306 @@
+//! Playback engine: output device handling, the decoder -> loop -> speed ->
+//! pitch -> effects -> resampler source chain, and the DSP effects with their
+//! settings. The GUI and the headless command-line player are both built on
+//! it; nothing in here depends on iced.
+//!
+//! ```no_run
+//! use rust_audio_player::engine::{AudioEngine, EngineEvent, OutputSettings};
+//!
+//! use std::time::Duration;
+//!
+//! let mut engine = AudioEngine::new(None, OutputSettings::default())?;
+//! let events = engine.subscribe();
+//! engine.open("song.flac".as_ref())?;
+//! engine.set_volume(0.8);
+//! engine.play();
+//! loop {
+//!     match events.recv_timeout(Duration::from_secs(1)) {
+//!         // Skip a stale event from a track that was replaced meanwhile
+//!         Ok(EngineEvent::TrackEnded) if engine.track_ended() => break,
+//!         Ok(EngineEvent::Error(e)) => eprintln!("{e}"),
+//!         Ok(_) | Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+//!         Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+//!     }
+//!     // Reopens the output if the device went away; None while all is well
+//!     if let Some(message) = engine.recover_lost_device() {
+//!         eprintln!("{message}");
+//!     }
+//! }
+//! # Ok::<(), String>(())
+//! ```
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::sync::{mpsc, Arc, Mutex, OnceLock, Weak};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+// Symphonia is used to probe duration for formats where rodio's Decoder
+// cannot determine it up-front (e.g., some MP3/streamable formats).
+// This enables the seekbar to work more reliably.
+use symphonia::core::audio::{SampleBuffer as SymSampleBuffer, SignalSpec as SymSignalSpec};
+use symphonia::core::formats::{FormatOptions as SymFormatOptions, FormatReader as SymFormatReader, Track as SymTrack};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions as SymMetadataOptions;
+use symphonia::core::probe::Hint as SymHint;
+use symphonia::default::get_probe as sym_get_probe;
+use symphonia::core::codecs::DecoderOptions as SymDecoderOptions;
+use symphonia::default::get_codecs as sym_get_codecs;
+
+use crate::convolution::{self, Convolver, ImpulseResponse};
+use crate::cue;
+pub use crate::cue::TrackRange;
+use crate::meter::{LevelMeter, MeterWriter, MAX_CHANNELS};
+use crate::spectrum::AudioTap;
+
+/// A playable entry: a whole file, or a virtual track inside one (CUE sheets).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioFile {
+    /// Display name; file name, or "NN. Performer - Title" for CUE tracks.
+    pub name: String,
+    pub path: PathBuf,
+    /// Set for virtual tracks from a CUE sheet.
+    pub cue: Option<TrackRange>,
+}
+
+/// Something that happened in the engine. Events are delivered in order on
+/// every channel returned by [`AudioEngine::subscribe`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EngineEvent {
+    /// A new track became current (`open`, `play_track`, or the next CUE
+    /// track taking over gaplessly). Seeking doesn't count.
+    TrackStarted,
+    /// The current track played to its end. For CUE tracks and trimmed files
+    /// that's the end of their span; the audio carries on until the next
+    /// `play_track`/`stop`, so consecutive CUE tracks can continue gaplessly.
+    TrackEnded,
+    /// Playback position within the current track, sent about every 100 ms
+    /// of audio while it plays and never while paused or stopped. Measured
+    /// where the audio is decoded, so it runs slightly ahead of
+    /// [`AudioEngine::current_position`].
+    PositionChanged(Duration),
+    /// Playback failed on its own, e.g. the output device went away, or had
+    /// to fall back, e.g. the device refused the track's sample rate.
+    Error(String),
+    /// The output stream was (re)opened on this device; None is the system
+    /// default.
+    DeviceChanged(Option<String>),
+}
+
+// Events a subscriber can fall behind by (about 100 s of position updates);
+// past that, new events are dropped for it until it catches up
+const EVENT_QUEUE: usize = 1024;
+
+// Fan-out of engine events to every subscriber; closed receivers are dropped.
+// Never used from the audio callback.
+#[derive(Clone, Default)]
+struct EventBus(Arc<Mutex<Vec<mpsc::SyncSender<EngineEvent>>>>);
+
+impl EventBus {
+    fn subscribe(&self) -> mpsc::Receiver<EngineEvent> {
+        let (tx, rx) = mpsc::sync_channel(EVENT_QUEUE);
+        if let Ok(mut senders) = self.0.lock() {
+            senders.push(tx);
+        }
+        rx
+    }
+
+    fn emit(&self, event: EngineEvent) {
+        if let Ok(mut senders) = self.0.lock() {
+            senders.retain(|tx| !matches!(tx.try_send(event.clone()), Err(mpsc::TrySendError::Disconnected(_))));
+        }
+    }
+}
+
+// Span being played inside the file (CUE track or trimmed file), watched from
+// the audio thread so TrackEnded fires on time even though the source goes on,
+// and positions are reported relative to the track. `ended` keeps it to one
+// TrackEnded per track. The audio thread only stores atomics and wakes the
+// event thread, which sends the events.
+struct TrackSpan {
+    start_us: AtomicU64,
+    end_us: AtomicU64,
+    ended: AtomicBool,
+    // Waiting to be sent: the latest position (u64::MAX = none) and TrackEnded
+    position_us: AtomicU64,
+    end_pending: AtomicBool,
+    notifier: OnceLock<std::thread::Thread>,
+}
+
+impl TrackSpan {
+    fn new(events: EventBus) -> Arc<Self> {
+        let span = Arc::new(Self {
+            start_us: AtomicU64::new(0),
+            end_us: AtomicU64::new(u64::MAX),
+            ended: AtomicBool::new(false),
+            position_us: AtomicU64::new(u64::MAX),
+            end_pending: AtomicBool::new(false),
+            notifier: OnceLock::new(),
+        });
+        let weak = Arc::downgrade(&span);
+        let notifier = std::thread::spawn(move || run_event_thread(weak, events));
+        let _ = span.notifier.set(notifier.thread().clone());
+        span
+    }
+
+    fn arm(&self, start: Duration, end: Option<Duration>) {
+        self.start_us.store(start.as_micros() as u64, Ordering::Relaxed);
+        self.end_us.store(end.map_or(u64::MAX, |d| d.as_micros() as u64), Ordering::Relaxed);
+        self.ended.store(false, Ordering::Relaxed);
+        // Whatever the previous track left unsent is stale now
+        self.position_us.store(u64::MAX, Ordering::Relaxed);
+        self.end_pending.store(false, Ordering::Relaxed);
+    }
+
+    fn position(&self, file_us: u64) {
+        let us = file_us.saturating_sub(self.start_us.load(Ordering::Relaxed));
+        self.position_us.store(us, Ordering::Relaxed);
+        self.notify();
+    }
+
+    fn fire(&self) {
+        if !self.ended.load(Ordering::Relaxed) && !self.ended.swap(true, Ordering::Relaxed) {
+            self.end_pending.store(true, Ordering::Release);
+            self.notify();
+        }
+    }
+
+    fn notify(&self) {
+        if let Some(thread) = self.notifier.get() {
+            thread.unpark();
+        }
+    }
+}
+
+impl Drop for TrackSpan {
+    // Let the event thread see the span is gone and exit
+    fn drop(&mut self) {
+        self.notify();
+    }
+}
+
+// Sends what the audio thread recorded in the span, for as long as it exists.
+fn run_event_thread(span: Weak<TrackSpan>, events: EventBus) {
+    loop {
+        let Some(span) = span.upgrade() else { return };
+        let us = span.position_us.swap(u64::MAX, Ordering::Relaxed);
+        if us != u64::MAX {
+            events.emit(EngineEvent::PositionChanged(Duration::from_micros(us)));
+        }
+        if span.end_pending.swap(false, Ordering::Acquire) {
+            events.emit(EngineEvent::TrackEnded);
+        }
+        drop(span);
+        std::thread::park();
+    }
+}
+
+/// Plays one track at a time through the effect chain on an output device.
+///
+/// Positions and durations are relative to the current track: for CUE tracks
+/// and trimmed files that's their span, not the whole file.
+pub struct AudioEngine {
+    stream: rodio::stream::OutputStream,
+    // Preferred output device (None = system default) and the one actually open
+    device_name: Option<String>,
+    active_device: Option<String>,
+    // Set from the stream's error callback when the device goes away or errors
+    stream_failed: Arc<AtomicBool>,
+    last_recovery: Option<Instant>,
+    // Requested output format; `stream_rate` is the rate we asked the stream for
+    output: OutputSettings,
+    stream_rate: Option<u32>,
+    // Native rate of the current track, used when following the source rate
+    source_rate: Option<u32>,
+    sink: Option<rodio::Sink>,
+    now_playing: Option<String>,
+    current_path: Option<PathBuf>,
+    duration: Option<Duration>,
+    clock: TrackClock,
+    // CUE virtual track being played; positions are relative to its start
+    range: Option<TrackRange>,
+    // Audible part of a whole-file track when silence trimming is on; works
+    // like `range` but never applies to CUE tracks
+    trim: Option<TrackRange>,
+    title: Option<String>,
+    // A-B loop points, applied inside the source chain
+    ab_loop: Arc<AbLoop>,
+    // Playback speed, read live by the time-stretch stage
+    speed: Arc<PlaybackSpeed>,
+    // Transposition, independent of speed
+    pitch: Arc<PitchShift>,
+    // Effect chain and each effect's settings, shared with UI for live updates
+    effects: DspEffects,
+    // Post-effects signal for the spectrum analyzer
+    tap: Arc<AudioTap>,
+    // Post-effects levels for the meters
+    meter: Arc<LevelMeter>,
+    // Sink volume, 0.0-1.0, kept across tracks
+    volume: f32,
+    events: EventBus,
+    track_span: Arc<TrackSpan>,
+}
+
+impl AudioEngine {
+    /// Open `device` (None or a missing device: the system default) with the
+    /// given output format. Nothing plays until a track is opened.
+    pub fn new(device: Option<&str>, output: OutputSettings) -> Result<Self, String> {
+        let stream_failed = Arc::new(AtomicBool::new(false));
+        let stream_rate = output.sample_rate.fixed();
+        let events = EventBus::default();
+        let (stream, active_device) = open_output(device, stream_failed.clone(), events.clone(), &output, stream_rate)?;
+        Ok(Self {
+            stream,
+            device_name: device.map(str::to_string),
+            active_device,
+            stream_failed,
+            last_recovery: None,
+            output,
+            stream_rate,
+            source_rate: None,
+            sink: None,
+            now_playing: None,
+            current_path: None,
+            duration: None,
+            clock: TrackClock::default(),
+            range: None,
+            trim: None,
+            title: None,
+            ab_loop: Arc::new(AbLoop::default()),
+            speed: Arc::new(PlaybackSpeed::default()),
+            pitch: Arc::new(PitchShift::default()),
+            effects: DspEffects::default(),
+            tap: Arc::new(AudioTap::default()),
+            meter: Arc::new(LevelMeter::default()),
+            volume: 1.0,
+            track_span: TrackSpan::new(events.clone()),
+            events,
+        })
+    }
+
+    /// Receive [`EngineEvent`]s from now on. Every subscriber gets every event;
+    /// dropping the receiver unsubscribes. A subscriber that stops reading
+    /// misses events once about a thousand are queued for it.
+    pub fn subscribe(&self) -> mpsc::Receiver<EngineEvent> {
+        self.events.subscribe()
+    }
+
+    /// Stop playback and forget the current track.
+    pub fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        self.now_playing = None;
+        self.current_path = None;
+        self.duration = None;
+        self.clock = TrackClock::default();
+        self.range = None;
+        self.trim = None;
+        self.title = None;
+        self.ab_loop.set(None, None);
+        self.track_span.arm(Duration::ZERO, None);
+    }
+
+    /// Load a whole file, paused at its start; [`play`](Self::play) starts it.
+    pub fn open(&mut self, path: &Path) -> Result<(), String> {
+        self.ab_loop.set(None, None);
+        self.range = None;
+        self.trim = None;
+        self.title = None;
+        self.play_from(path, Duration::ZERO, true)?;
+        self.events.emit(EngineEvent::TrackStarted);
+        Ok(())
+    }
+
+    /// Start playing a track (a whole file or a CUE track) from its beginning.
+    pub fn play_track(&mut self, file: &AudioFile) -> Result<(), String> {
+        // Loop points belong to the previous track
+        self.ab_loop.set(None, None);
+        self.range = file.cue;
+        // Replaying the same file keeps its trim; anything else waits for detection
+        if file.cue.is_some() || self.current_path.as_ref() != Some(&file.path) {
+            self.trim = None;
+        }
+        self.title = file.cue.map(|_| file.name.clone());
+        self.play_from(&file.path, Duration::ZERO, false)?;
+        self.events.emit(EngineEvent::TrackStarted);
+        Ok(())
+    }
+
+    /// Make `file`, the next CUE track of the same file, current without
+    /// interrupting the audio, so a single-file album plays gaplessly. Call it
+    /// on [`EngineEvent::TrackEnded`] when [`can_continue_into`](Self::can_continue_into)
+    /// says so; otherwise use [`play_track`](Self::play_track).
+    pub fn continue_into(&mut self, file: &AudioFile) {
+        let (Some(old), Some(new)) = (self.range, file.cue) else { return };
+        let shift = new.start.saturating_sub(old.start);
+        let position = self.current_position().saturating_sub(shift);
+        self.clock.rebase(position, self.rewound_us());
+        self.range = Some(new);
+        self.title = Some(file.name.clone());
+        self.now_playing = self.title.clone();
+        self.track_span.arm(new.start, new.end);
+        self.events.emit(EngineEvent::TrackStarted);
+    }
+
+    /// True if `file` directly follows the current CUE track in the same file
+    /// and the audio is still running, so [`continue_into`](Self::continue_into) can take over.
+    pub fn can_continue_into(&self, file: &AudioFile) -> bool {
+        self.sink.as_ref().is_some_and(|s| !s.empty())
+            && Some(&file.path) == self.current_path.as_ref()
+            && file.cue.is_some_and(|r| self.range.and_then(|c| c.end) == Some(r.start))
+    }
+
+    // Part of the file being played: the CUE track, else the trimmed span.
+    pub(crate) fn span(&self) -> Option<TrackRange> {
+        self.range.or(self.trim)
+    }
+
+    // Apply newly detected (or cleared) silence trimming to the current file.
+    // Positions are re-based without touching the sink; if we're still inside
+    // the leading silence, playback restarts at the first audible sample.
+    pub(crate) fn set_trim(&mut self, trim: Option<TrackRange>) -> Result<(), String> {
+        if self.range.is_some() || self.trim == trim {
+            return Ok(());
+        }
+        let old_start = self.trim.map_or(Duration::ZERO, |r| r.start);
+        let new_start = trim.map_or(Duration::ZERO, |r| r.start);
+        let absolute = old_start + self.current_position();
+        self.trim = trim;
+        self.track_span.arm(new_start, trim.and_then(|r| r.end));
+        let Some(path) = self.current_path.clone() else { return Ok(()) };
+        if absolute < new_start {
+            let was_paused = self.sink.as_ref().is_some_and(|s| s.is_paused());
+            return self.play_from(&path, Duration::ZERO, was_paused);
+        }
+        self.clock.rebase(absolute.saturating_sub(new_start), self.rewound_us());
+        Ok(())
+    }
+
+    /// True once the loaded track has played to its end. Events are queued, so
+    /// a [`EngineEvent::TrackEnded`] can arrive after another track was started;
+    /// check this before advancing to skip such stale ones.
+    pub fn track_ended(&self) -> bool {
+        self.sink.is_some() && self.track_span.ended.load(Ordering::Relaxed)
+    }
+
+    fn play_from(&mut self, path: &Path, position: Duration, resume_paused: bool) -> Result<(), String> {
+        use rodio::Source as _;
+
+        if let Some(sink) = self.sink.take() { sink.stop(); }
+
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("Failed to open file: {e}"))?;
+        // Decoder::try_from(File) wraps in BufReader and sets byte_len for accurate seeking
+        let decoder = rodio::Decoder::try_from(file)
+            .map_err(|e| format!("Failed to decode audio: {e}"))?;
+
+        // Prefer rodio's duration, but if it's not available, try probing with symphonia.
+        // Avoid re-probing if we already know duration for the same track.
+        let same_track = self.current_path.as_ref().is_some_and(|p| p == path);
+        if !same_track || self.duration.is_none() {
+            self.duration = decoder.total_duration().or_else(|| probe_duration_with_symphonia(path));
+        }
+
+        // Following the source rate: reopen the stream when the track's rate differs
+        self.source_rate = Some(decoder.sample_rate());
+        if self.output.sample_rate == SampleRateChoice::FollowSource && self.stream_rate != self.source_rate {
+            let device = self.device_name.clone();
+            if let Err(e) = self.open_stream(device.as_deref()) {
+                let rate = decoder.sample_rate();
+                self.events.emit(EngineEvent::Error(format!("Couldn't reopen the output at {rate} Hz, resampling instead: {e}")));
+            }
+        }
+
+    // Apply effects by wrapping the source. CUE tracks start at their INDEX 01 offset,
+    // trimmed tracks at their first audible sample.
+    let start = self.span().map(|r| r.start).unwrap_or(Duration::ZERO);
+    let source = decoder.skip_duration(start + position);
+    self.track_span.arm(start, self.span().and_then(|r| r.end));
+    let source = LoopSource::new(source, self.ab_loop.clone(), self.track_span.clone(), start + position);
+    let source = SpeedSource::new(source, self.speed.clone());
+    let source = PitchSource::new(source, self.pitch.clone());
+    let source = DspSource::new(source, self.effects.clone(), self.tap.clone(), self.meter.clone());
+    // Convert to the device rate ourselves so the resampler quality is ours to pick
+    let source = ResampleSource::new(source, self.stream.config().sample_rate(), self.output.resampler);
+
+        // Create a sink we can control and append the (possibly skipped) source
+        let sink = rodio::Sink::connect_new(&self.stream.mixer());
+        sink.set_volume(self.volume);
+    sink.append(source);
+        // Runs once the source is exhausted
+        let track_span = self.track_span.clone();
+        sink.append(rodio::source::EmptyCallback::new(Box::new(move || track_span.fire())));
+        self.sink = Some(sink);
+        self.now_playing = Some(self.title.clone().unwrap_or_else(|| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown")
+                .to_string()
+        }));
+        self.current_path = Some(path.to_path_buf());
+        self.clock.restart(position, self.rewound_us());
+
+        if resume_paused {
+            if let Some(s) = &self.sink { s.pause(); }
+        }
+
+        Ok(())
+    }
+
+    /// Pause; the position is kept.
+    pub fn pause(&mut self) {
+        if let Some(s) = &self.sink {
+            if !s.is_paused() {
+                s.pause();
+                self.clock.pause(self.speed.factor(), self.rewound_us());
+            }
+        }
+    }
+
+    /// Start or resume the loaded track.
+    pub fn play(&mut self) {
+        if let Some(s) = &self.sink {
+            if s.is_paused() {
+                s.play();
+                self.clock.resume(self.rewound_us());
+            }
+        }
+    }
+
+    /// Jump to `position` within the current track (clamped to its length),
+    /// keeping the paused/playing state.
+    pub fn seek_to(&mut self, position: Duration) -> Result<(), String> {
+        let clamped = if let Some(d) = self.total_duration() { position.min(d) } else { position };
+        if let Some(path) = self.current_path.clone() {
+            let was_paused = self.sink.as_ref().is_some_and(|s| s.is_paused());
+            // If position is close to current, do nothing
+            if (self.current_position().as_secs_f32() - clamped.as_secs_f32()).abs() < 0.01 { return Ok(()); }
+            self.play_from(&path, clamped, was_paused)
+        } else { Ok(()) }
+    }
+
+    /// True while audio is coming out: a track is loaded, not paused and not finished.
+    pub fn is_playing(&self) -> bool {
+        if let Some(s) = &self.sink {
+            !s.is_paused() && !s.empty()
+        } else {
+            false
+        }
+    }
+
+    /// True if a track is loaded and paused.
+    pub fn is_paused(&self) -> bool {
+        self.sink.as_ref().is_some_and(|s| s.is_paused())
+    }
+
+    /// File of the loaded track.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current_path.as_deref()
+    }
+
+    /// Span of the loaded CUE track within its file.
+    pub fn current_range(&self) -> Option<TrackRange> {
+        self.range
+    }
+
+    /// Title of the loaded track: the CUE track's name, else the file name.
+    pub fn now_playing(&self) -> Option<&str> {
+        self.now_playing.as_deref()
+    }
+
+    /// Output volume, 0.0 (silent) to 1.0 (unchanged).
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Set the output volume (clamped to 0.0-1.0); it carries over to later tracks.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        if let Some(sink) = &self.sink {
+            sink.set_volume(self.volume);
+        }
+    }
+
+    /// Equalizer gains in dB for the bands in [`EQ_FREQS`].
+    pub fn eq_gains(&self) -> [f32; 10] {
+        self.effects.eq.snapshot_gains()
+    }
+
+    /// Set the equalizer gains in dB for the bands in [`EQ_FREQS`]; applies live.
+    pub fn set_eq_gains(&self, gains_db: [f32; 10]) {
+        self.effects.eq.set_gains_db(gains_db);
+    }
+
+    /// Device playback goes to; None means the system default.
+    pub fn active_device(&self) -> Option<&str> {
+        self.active_device.as_deref()
+    }
+
+    // Live settings of the effect chain and other stages, shared with the audio thread
+    pub(crate) fn effects(&self) -> &DspEffects {
+        &self.effects
+    }
+
+    pub(crate) fn speed(&self) -> &PlaybackSpeed {
+        &self.speed
+    }
+
+    pub(crate) fn pitch(&self) -> &PitchShift {
+        &self.pitch
+    }
+
+    pub(crate) fn tap(&self) -> &AudioTap {
+        &self.tap
+    }
+
+    pub(crate) fn meter(&self) -> &LevelMeter {
+        &self.meter
+    }
+
+    /// Length of the current track, if known.
+    pub fn total_duration(&self) -> Option<Duration> {
+        match self.span() {
+            Some(r) => Some(r.end.or(self.duration)?.saturating_sub(r.start)),
+            None => self.duration,
+        }
+    }
+
+    /// Playback position within the current track.
+    pub fn current_position(&self) -> Duration {
+        self.clock.position(self.speed.factor(), self.rewound_us())
+    }
+
+    fn rewound_us(&self) -> u64 {
+        self.ab_loop.rewound_us.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_speed(&mut self, factor: f32) {
+        // Re-base the clock so time played so far keeps the old rate
+        self.clock.rebase(self.current_position(), self.rewound_us());
+        self.speed.set_factor(factor);
+    }
+
+    pub(crate) fn set_speed_changes_pitch(&mut self, changes_pitch: bool) -> Result<(), String> {
+        self.speed.preserve_pitch.store(!changes_pitch, Ordering::Relaxed);
+        // The stretch mode is fixed per source, so rebuild the chain at the current position
+        match self.current_path.clone() {
+            Some(path) => {
+                let was_paused = self.sink.as_ref().is_some_and(|s| s.is_paused());
+                let position = self.current_position();
+                self.play_from(&path, position, was_paused)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Make `name` the preferred output device and move playback over to it.
+    pub(crate) fn switch_device(&mut self, name: Option<String>) -> Result<(), String> {
+        self.reopen_output(name.as_deref())?;
+        self.device_name = name;
+        Ok(())
+    }
+
+    // Open `name` (falling back to the default device) and resume the current
+    // track there at the same position.
+    fn reopen_output(&mut self, name: Option<&str>) -> Result<(), String> {
+        let was_paused = self.sink.as_ref().is_some_and(|s| s.is_paused());
+        let resume = self.current_path.clone().map(|p| (p, self.current_position()));
+        self.open_stream(name)?;
+        match resume {
+            Some((path, position)) => self.play_from(&path, position, was_paused),
+            None => Ok(()),
+        }
+    }
+
+    // Replace the output stream, dropping whatever was playing on the old one.
+    fn open_stream(&mut self, name: Option<&str>) -> Result<(), String> {
+        let rate = match self.output.sample_rate {
+            SampleRateChoice::FollowSource => self.source_rate,
+            choice => choice.fixed(),
+        };
+        let (mut stream, active) = open_output(name, self.stream_failed.clone(), self.events.clone(), &self.output, rate)?;
+        stream.log_on_drop(false);
+        if let Some(sink) = self.sink.take() { sink.stop(); }
+        self.stream.log_on_drop(false);
+        self.stream = stream;
+        self.stream_rate = rate;
+        self.active_device = active;
+        self.stream_failed.store(false, Ordering::Relaxed);
+        self.events.emit(EngineEvent::DeviceChanged(self.active_device.clone()));
+        Ok(())
+    }
+
+    // Apply new output settings by reopening the stream; playback continues
+    // from the same position.
+    pub(crate) fn set_output_settings(&mut self, output: OutputSettings) -> Result<(), String> {
+        self.output = output;
+        let device = self.device_name.clone();
+        self.reopen_output(device.as_deref())
+    }
+
+    // What the device is actually running at, e.g. "48000 Hz · 2 ch · f32 · 512 frames"
+    pub(crate) fn format_summary(&self) -> String {
+        let config = self.stream.config();
+        let buffer = match config.buffer_size() {
+            rodio::cpal::BufferSize::Fixed(n) => format!("{n} frames"),
+            rodio::cpal::BufferSize::Default => "default buffer".into(),
+        };
+        format!("{} Hz · {} ch · {} · {buffer}", config.sample_rate(), config.channel_count(), config.sample_format())
+    }
+
+    // The output stream reported an error and hasn't been reopened yet
+    pub(crate) fn output_failed(&self) -> bool {
+        self.stream_failed.load(Ordering::Relaxed)
+    }
+
+    /// If the output stream failed (device unplugged, backend error), reopen it
+    /// on the chosen device, falling back to the system default, and carry on
+    /// with the same track and position. Call it after an [`EngineEvent::Error`]
+    /// and then every second or so until the device is back; retries are rate
+    /// limited. Returns a message describing what happened, if anything did.
+    pub fn recover_lost_device(&mut self) -> Option<String> {
+        if !self.stream_failed.load(Ordering::Relaxed) {
+            return None;
+        }
+        // Don't hammer the audio backend while no device is available
+        if self.last_recovery.is_some_and(|t| t.elapsed() < AUDIO_RETRY_INTERVAL) {
+            return None;
+        }
+        self.last_recovery = Some(Instant::now());
+        let lost = self.active_device.clone().unwrap_or_else(|| DEFAULT_DEVICE_LABEL.to_string());
+        let wanted = self.device_name.clone();
+        Some(match self.reopen_output(wanted.as_deref()) {
+            Ok(()) if self.active_device == wanted => "Audio output restarted.".into(),
+            Ok(()) => format!("Output device \"{lost}\" unavailable; switched to the default device."),
+            Err(e) => {
+                // Keep the flag so we try again later
+                self.stream_failed.store(true, Ordering::Relaxed);
+                format!("Output device \"{lost}\" unavailable: {e}")
+            }
+        })
+    }
+
+    // Loop points relative to the current track (CUE and trimmed tracks start at their offset).
+    pub(crate) fn loop_points(&self) -> (Option<Duration>, Option<Duration>) {
+        let start = self.span().map(|r| r.start).unwrap_or(Duration::ZERO);
+        let (a, b) = self.ab_loop.points();
+        (a.map(|a| a.saturating_sub(start)), b.map(|b| b.saturating_sub(start)))
+    }
+
+    pub(crate) fn set_loop_points(&mut self, a: Option<Duration>, b: Option<Duration>) {
+        let start = self.span().map(|r| r.start).unwrap_or(Duration::ZERO);
+        self.ab_loop.set(a.map(|a| a + start), b.map(|b| b + start));
+    }
+}
+
+// Position within the loaded track. The sink doesn't report one, so it's
+// derived from the wall time since the clock was last (re)started.
+#[derive(Debug, Default)]
+struct TrackClock {
+    start: Option<Instant>,
+    paused_at: Option<Duration>,
+    offset: Duration,
+    // Value of `AbLoop::rewound_us` when the clock was last (re)started
+    rewound_base: u64,
+}
+
+impl TrackClock {
+    fn position(&self, speed: f32, rewound_us: u64) -> Duration {
+        if let Some(paused) = self.paused_at {
+            paused
+        } else if let Some(start) = self.start {
+            // Track time runs at the playback speed; time jumped back by the
+            // A-B loop since the clock started doesn't count
+            let rewound = rewound_us.saturating_sub(self.rewound_base);
+            let played = start.elapsed().mul_f32(speed);
+            (self.offset + played).saturating_sub(Duration::from_micros(rewound))
+        } else {
+            self.offset
+        }
+    }
+
+    // Start running from `position`
+    fn restart(&mut self, position: Duration, rewound_us: u64) {
+        self.offset = position;
+        self.paused_at = None;
+        self.start = Some(Instant::now());
+        self.rewound_base = rewound_us;
+    }
+
+    fn pause(&mut self, speed: f32, rewound_us: u64) {
+        self.paused_at = Some(self.position(speed, rewound_us));
+        self.start = None;
+    }
+
+    fn resume(&mut self, rewound_us: u64) {
+        let position = self.paused_at.take().unwrap_or(self.offset);
+        self.restart(position, rewound_us);
+    }
+
+    // Make the clock read `position` now, running or paused as before
+    fn rebase(&mut self, position: Duration, rewound_us: u64) {
+        if self.paused_at.is_some() {
+            self.paused_at = Some(position);
+        } else if self.start.is_some() {
+            self.restart(position, rewound_us);
+        } else {
+            self.offset = position;
+        }
+    }
+}
+
+pub(crate) const DEFAULT_DEVICE_LABEL: &str = "System default";
+// How often to retry opening audio output while it is unavailable
+pub(crate) const AUDIO_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Names of all output devices on the default host, for [`AudioEngine::new`].
+pub fn list_output_devices() -> Vec<String> {
+    use rodio::cpal::traits::{DeviceTrait, HostTrait};
+    rodio::cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+// Open an output stream on the named device, or the default device if it is
+// missing or fails to open. Returns the stream and the device actually used.
+fn open_output(
+    name: Option<&str>,
+    failed: Arc<AtomicBool>,
+    events: EventBus,
+    output: &OutputSettings,
+    rate: Option<u32>,
+) -> Result<(rodio::OutputStream, Option<String>), String> {
+    use rodio::cpal::traits::{DeviceTrait, HostTrait};
+    // Unsupported rates/buffer sizes fall back to a configuration the device accepts
+    let configure = |mut b: rodio::OutputStreamBuilder| {
+        if let Some(rate) = rate {
+            b = b.with_sample_rate(rate);
+        }
+        if let Some(frames) = output.buffer_size.frames() {
+            b = b.with_buffer_size(rodio::cpal::BufferSize::Fixed(frames));
+        }
+        b
+    };
+    // Any stream error means playback has stopped; the UI tick reopens the stream
+    let on_error = move |e: rodio::cpal::StreamError| {
+        if !failed.swap(true, Ordering::Relaxed) {
+            events.emit(EngineEvent::Error(format!("Audio output error: {e}")));
+        }
+    };
+    if let Some(name) = name {
+        let device = rodio::cpal::default_host()
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|d| d.name().ok().as_deref() == Some(name)));
+        if let Some(device) = device {
+            let opened = rodio::OutputStreamBuilder::from_device(device)
+                .and_then(|b| configure(b).with_error_callback(on_error.clone()).open_stream_or_fallback());
+            if let Ok(stream) = opened {
+                return Ok((stream, Some(name.to_string())));
+            }
+        }
+    }
+    let stream = rodio::OutputStreamBuilder::from_default_device()
+        .and_then(|b| configure(b).with_error_callback(on_error).open_stream_or_fallback())
+        .map_err(|e| format!("Audio output error: {e}"))?;
+    Ok((stream, None))
+}
+
+fn probe_duration_with_symphonia(path: &Path) -> Option<Duration> {
+    let (mut format, track) = open_with_symphonia(path)?;
+    let params = &track.codec_params;
+    if let (Some(sr), Some(n_frames)) = (params.sample_rate, params.n_frames) {
+        let secs = n_frames as f64 / sr as f64;
+        return Some(Duration::from_secs_f64(secs));
+    }
+
+    // As a last resort, decode and count frames to compute duration.
+    let mut total_frames: u64 = 0;
+    let mut sr_opt = params.sample_rate;
+    decode_with_symphonia(format.as_mut(), &track, |samples, channels, rate| {
+        total_frames += (samples.len() / channels) as u64;
+        if sr_opt.is_none() { sr_opt = Some(rate); }
+        true
+    })?;
+
+    let sr = sr_opt?;
+    if total_frames > 0 {
+        return Some(Duration::from_secs_f64(total_frames as f64 / sr as f64));
+    }
+
+    None
+}
+
+// Open a file with Symphonia and pick the default track, or the first track
+// with a sample rate.
+pub(crate) fn open_with_symphonia(path: &Path) -> Option<(Box<dyn SymFormatReader>, SymTrack)> {
+    let mut hint = SymHint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let file = std::fs::File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let probed = sym_get_probe()
+        .format(&hint, mss, &SymFormatOptions::default(), &SymMetadataOptions::default())
+        .ok()?;
+    let format = probed.format;
+    let track = format
+        .default_track()
+        .cloned()
+        .or_else(|| format.tracks().iter().find(|t| t.codec_params.sample_rate.is_some()).cloned())?;
+    Some((format, track))
+}
+
+// Decode every packet of `track`, passing interleaved f32 samples, the channel
+// count and the sample rate to `on_samples`. Stops early when it returns false.
+// Packets that fail to decode are skipped.
+pub(crate) fn decode_with_symphonia(
+    format: &mut dyn SymFormatReader,
+    track: &SymTrack,
+    mut on_samples: impl FnMut(&[f32], usize, u32) -> bool,
+) -> Option<()> {
+    let mut decoder = sym_get_codecs().make(&track.codec_params, &SymDecoderOptions::default()).ok()?;
+    let mut buf: Option<(SymSignalSpec, SymSampleBuffer<f32>)> = None;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track.id { continue; }
+        let Ok(audio_buf) = decoder.decode(&packet) else { continue };
+        let spec = *audio_buf.spec();
+        let channels = spec.channels.count().max(1);
+        // Reuse the sample buffer unless the format changed or a packet is larger
+        let reuse = buf.as_ref().is_some_and(|(s, b)| *s == spec && b.capacity() >= audio_buf.capacity() * channels);
+        if !reuse {
+            buf = Some((spec, SymSampleBuffer::new(audio_buf.capacity() as u64, spec)));
+        }
+        let Some((_, samples)) = buf.as_mut() else { continue };
+        samples.copy_interleaved_ref(audio_buf);
+        if !on_samples(samples.samples(), channels, spec.rate) {
+            break;
+        }
+    }
+    Some(())
+}
+
+/// Playable files in `dir` (not recursive), sorted by name. Files referenced
+/// by a CUE sheet are replaced by its tracks. Also returns any errors met on
+/// the way, joined into one message.
+pub fn scan_audio_files(dir: &Path) -> (Vec<AudioFile>, Option<String>) {
+    // Filter by common audio extensions. With rodio + symphonia-all, this should cover most use cases.
+    const EXTS: &[&str] = &[
+        "mp3", "flac", "wav", "ogg", "opus", "aac", "m4a", "alac", "aiff", "aif",
+    ];
+
+    let mut files = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+
+    match fs::read_dir(dir) {
+        Ok(entries) => {
+            for entry in entries {
+                match entry {
+                    Ok(e) => {
+                        let path = e.path();
+                        if path.is_file() {
+                            if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
+                                if EXTS.iter().any(|x| x.eq_ignore_ascii_case(ext)) {
+                                    let name = path
+                                        .file_name()
+                                        .and_then(|n| n.to_str())
+                                        .unwrap_or("Unknown")
+                                        .to_string();
+                                    files.push(AudioFile { name, path, cue: None });
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => errors.push(format!("Error reading entry: {e}")),
+                }
+            }
+        }
+        Err(e) => errors.push(format!("Failed to read directory: {e}")),
+    }
+
+    files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+
+    // Replace single-file album rips with one virtual entry per CUE track
+    let cue_sheets: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("cue")))
+                .collect()
+        })
+        .unwrap_or_default();
+    for sheet in cue_sheets {
+        match cue::parse_cue_file(&sheet) {
+            Ok(tracks) => expand_cue_tracks(&mut files, tracks),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let err = if errors.is_empty() {
+        None
+    } else {
+        Some(errors.join("; "))
+    };
+    (files, err)
+}
+
+// Swap each file referenced by a CUE sheet for its virtual tracks, keeping list order.
+fn expand_cue_tracks(files: &mut Vec<AudioFile>, tracks: Vec<cue::CueTrack>) {
+    let mut sheet_files: Vec<PathBuf> = tracks.iter().map(|t| t.file.clone()).collect();
+    sheet_files.dedup();
+    for track_file in sheet_files {
+        // CUE sheets often name the original .wav while the rip is .flac; fall back to the stem
+        let Some(pos) = files.iter().position(|f| f.cue.is_none() && f.path == track_file).or_else(|| {
+            let stem = track_file.file_stem()?;
+            files.iter().position(|f| f.cue.is_none() && f.path.file_stem() == Some(stem))
+        }) else {
+            continue;
+        };
+        let audio_path = files[pos].path.clone();
+        let entries: Vec<AudioFile> = tracks
+            .iter()
+            .filter(|t| t.file == track_file)
+            .map(|t| {
+                let title = t.title.clone().unwrap_or_else(|| format!("Track {:02}", t.number));
+                let name = match &t.performer {
+                    Some(p) => format!("{:02}. {p} - {title}", t.number),
+                    None => format!("{:02}. {title}", t.number),
+                };
+                AudioFile { name, path: audio_path.clone(), cue: Some(t.range) }
+            })
+            .collect();
+        files.splice(pos..=pos, entries);
+    }
+}
+
+// ===== Equalizer implementation =====
+#[derive(Clone, Copy)]
+struct BiquadCoeffs { b0: f32, b1: f32, b2: f32, a1: f32, a2: f32 }
+
+#[derive(Clone, Copy, Default)]
+struct BiquadState { z1: f32, z2: f32 }
+
+impl BiquadState {
+    fn process(&mut self, x: f32, c: BiquadCoeffs) -> f32 {
+        // Direct Form I transposed
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+fn peaking_eq(sr: f32, f0: f32, q: f32, gain_db: f32) -> BiquadCoeffs {
+    let a = 10f32.powf(gain_db / 40.0);
+    let w0 = 2.0 * std::f32::consts::PI * (f0 / sr);
+    let alpha = w0.sin() / (2.0 * q);
+    let cosw = w0.cos();
+
+    let b0 = 1.0 + alpha * a;
+    let b1 = -2.0 * cosw;
+    let b2 = 1.0 - alpha * a;
+    let a0 = 1.0 + alpha / a;
+    let a1 = -2.0 * cosw;
+    let a2 = 1.0 - alpha / a;
+
+    let inv_a0 = 1.0 / a0;
+    BiquadCoeffs { b0: b0 * inv_a0, b1: b1 * inv_a0, b2: b2 * inv_a0, a1: a1 * inv_a0, a2: a2 * inv_a0 }
+}
+
+pub(crate) struct Equalizer {
+    gains_db: Mutex<[f32; 10]>,
+    version: AtomicU64,
+}
+impl Default for Equalizer { fn default() -> Self { Self { gains_db: Mutex::new([0.0; 10]), version: AtomicU64::new(0) } } }
+impl Equalizer {
+    pub(crate) fn set_gains_db(&self, gains: [f32; 10]) {
+        if let Ok(mut g) = self.gains_db.lock() { *g = gains; }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    fn snapshot_gains(&self) -> [f32; 10] {
+        self.gains_db.lock().map(|g| *g).unwrap_or([0.0; 10])
+    }
+}
+
+/// Centre frequencies (Hz) of the ten equalizer bands.
+pub const EQ_FREQS: [f32; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+
+/// Named equalizer settings: band gains in dB for [`EQ_FREQS`].
+pub const EQ_PRESETS: [(&str, [f32; 10]); 7] = [
+    ("flat", [0.0; 10]),
+    ("bass", [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("treble", [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 5.0, 6.0]),
+    ("vocal", [-2.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.0, 1.0, 0.0, -1.0]),
+    ("rock", [4.0, 3.0, 1.0, -1.0, -2.0, -1.0, 1.0, 3.0, 4.0, 4.0]),
+    ("loudness", [5.0, 4.0, 2.0, 0.0, -1.0, 0.0, 0.0, 2.0, 4.0, 5.0]),
+    ("classical", [3.0, 2.0, 1.0, 0.0, 0.0, 0.0, -1.0, -1.0, 1.0, 2.0]),
+];
+
+/// Gains of the preset called `name` (case-insensitive).
+pub fn eq_preset(name: &str) -> Option<[f32; 10]> {
+    EQ_PRESETS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, gains)| gains)
+}
+
+struct EqProcessor {
+    shared: Arc<Equalizer>,
+    last_version: u64,
+    sample_rate: f32,
+    // Per-band coefficients at current sample rate
+    coeffs: [BiquadCoeffs; 10],
+    // Filter state for each band, per channel
+    states: Vec<[BiquadState; 10]>,
+}
+
+impl EqProcessor {
+    fn new(shared: Arc<Equalizer>) -> Self {
+        let last_version = shared.version.load(Ordering::Relaxed);
+        let coeffs = [BiquadCoeffs { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0 }; 10];
+        Self { shared, last_version, sample_rate: 44_100.0, coeffs, states: Vec::with_capacity(MAX_CHANNELS) }
+    }
+
+    fn rebuild(&mut self) {
+        let q = 1.0; // broad bands
+        let gains = self.shared.snapshot_gains();
+        for i in 0..10 { self.coeffs[i] = peaking_eq(self.sample_rate, EQ_FREQS[i], q, gains[i]); }
+    }
+}
+
+impl DspProcessor for EqProcessor {
+    fn configure(&mut self, channels: usize, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.states.clear();
+        self.states.resize(channels, [BiquadState::default(); 10]);
+        self.rebuild();
+    }
+    fn update(&mut self) {
+        // Refresh coeffs if updated
+        let current_version = self.shared.version.load(Ordering::Relaxed);
+        if current_version != self.last_version {
+            self.rebuild();
+            self.last_version = current_version;
+        }
+    }
+    fn process(&mut self, frame: &mut [f32]) {
+        for (x, state) in frame.iter_mut().zip(self.states.iter_mut()) {
+            for (band, c) in state.iter_mut().zip(self.coeffs) { *x = band.process(*x, c); }
+        }
+    }
+    fn reset(&mut self) {
+        self.states.iter_mut().for_each(|s| *s = [BiquadState::default(); 10]);
+    }
+}
+
+// ===== A-B loop implementation =====
+// Loop points are absolute file positions in microseconds (u64::MAX = unset).
+// `rewound_us` accumulates how far the loop has jumped back so the engine's
+// wall-clock position can be corrected.
+struct AbLoop {
+    a_us: AtomicU64,
+    b_us: AtomicU64,
+    rewound_us: AtomicU64,
+}
+impl Default for AbLoop {
+    fn default() -> Self { Self { a_us: AtomicU64::new(u64::MAX), b_us: AtomicU64::new(u64::MAX), rewound_us: AtomicU64::new(0) } }
+}
+impl AbLoop {
+    fn set(&self, a: Option<Duration>, b: Option<Duration>) {
+        self.a_us.store(a.map(|d| d.as_micros() as u64).unwrap_or(u64::MAX), Ordering::Relaxed);
+        self.b_us.store(b.map(|d| d.as_micros() as u64).unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+    fn points(&self) -> (Option<Duration>, Option<Duration>) {
+        let load = |v: &AtomicU64| Some(v.load(Ordering::Relaxed)).filter(|&us| us != u64::MAX).map(Duration::from_micros);
+        (load(&self.a_us), load(&self.b_us))
+    }
+}
+
+// Counts frames as they are pulled from the decoder and seeks back to A as soon
+// as B is reached, so loops are sample-accurate regardless of the UI tick rate.
+// A few milliseconds of fade on both sides of the jump avoid clicks.
+struct LoopSource<S: rodio::Source> {
+    inner: S,
+    shared: Arc<AbLoop>,
+    track_span: Arc<TrackSpan>,
+    // Absolute position in frames, and the channel of the next sample
+    frame: u64,
+    // Position events go out whenever the frame enters a new interval
+    position_interval: u64,
+    channel: u16,
+    fade_in: u64,
+}
+
+const LOOP_FADE_SECS: f64 = 0.003;
+const POSITION_INTERVAL_MS: u64 = 100;
+
+impl<S: rodio::Source> LoopSource<S> {
+    fn new(inner: S, shared: Arc<AbLoop>, track_span: Arc<TrackSpan>, start: Duration) -> Self {
+        let frame = (start.as_secs_f64() * inner.sample_rate() as f64) as u64;
+        let position_interval = (inner.sample_rate() as u64 * POSITION_INTERVAL_MS / 1000).max(1);
+        Self { inner, shared, track_span, frame, position_interval, channel: 0, fade_in: 0 }
+    }
+
+    fn to_frames(&self, us: u64) -> u64 {
+        us * self.inner.sample_rate() as u64 / 1_000_000
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> Iterator for LoopSource<S> {
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        let a_us = self.shared.a_us.load(Ordering::Relaxed);
+        let b_us = self.shared.b_us.load(Ordering::Relaxed);
+        let fade_len = ((self.inner.sample_rate() as f64 * LOOP_FADE_SECS) as u64).max(1);
+        let mut gain = 1.0;
+        if b_us != u64::MAX && a_us < b_us {
+            let (a, b) = (self.to_frames(a_us), self.to_frames(b_us));
+            // Only jump at the start of a frame so channels stay aligned
+            if self.frame >= b && self.frame > a && self.channel == 0 {
+                if self.inner.try_seek(Duration::from_micros(a_us)).is_ok() {
+                    let pos_us = self.frame * 1_000_000 / self.inner.sample_rate().max(1) as u64;
+                    self.shared.rewound_us.fetch_add(pos_us.saturating_sub(a_us), Ordering::Relaxed);
+                    self.frame = a;
+                    self.fade_in = fade_len;
+                }
+            } else if self.frame > a && b > self.frame && b - self.frame < fade_len {
+                gain = (b - self.frame) as f32 / fade_len as f32;
+            }
+        }
+        if self.fade_in > 0 {
+            gain *= 1.0 - self.fade_in as f32 / fade_len as f32;
+        }
+        let x = self.inner.next()?;
+        self.channel += 1;
+        if self.channel >= self.inner.channels().max(1) {
+            self.channel = 0;
+            self.frame += 1;
+            self.fade_in = self.fade_in.saturating_sub(1);
+            let end_us = self.track_span.end_us.load(Ordering::Relaxed);
+            if end_us != u64::MAX && self.frame >= self.to_frames(end_us) {
+                self.track_span.fire();
+            } else if self.frame.is_multiple_of(self.position_interval) {
+                let rate = self.inner.sample_rate().max(1) as u64;
+                self.track_span.position(self.frame * 1_000_000 / rate);
+            }
+        }
+        Some(x * gain)
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> rodio::Source for LoopSource<S> {
+    fn channels(&self) -> u16 { self.inner.channels() }
+    fn sample_rate(&self) -> u32 { self.inner.sample_rate() }
+    fn current_span_len(&self) -> Option<usize> { self.inner.current_span_len() }
+    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        self.frame = self.to_frames(pos.as_micros() as u64);
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+// ===== Playback speed (time stretching) =====
+pub(crate) const MIN_SPEED: f32 = 0.5;
+pub(crate) const MAX_SPEED: f32 = 2.0;
+
+// Speed factor (f32 bits) and stretch mode shared with the playing source.
+pub(crate) struct PlaybackSpeed {
+    factor_bits: AtomicU32,
+    pub(crate) preserve_pitch: AtomicBool,
+}
+impl Default for PlaybackSpeed {
+    fn default() -> Self { Self { factor_bits: AtomicU32::new(1.0f32.to_bits()), preserve_pitch: AtomicBool::new(true) } }
+}
+impl PlaybackSpeed {
+    pub(crate) fn factor(&self) -> f32 {
+        f32::from_bits(self.factor_bits.load(Ordering::Relaxed))
+    }
+    fn set_factor(&self, factor: f32) {
+        self.factor_bits.store(factor.clamp(MIN_SPEED, MAX_SPEED).to_bits(), Ordering::Relaxed);
+    }
+}
+
+// WSOLA window length; the synthesis hop is half of it (Hann windows at 50% overlap sum to 1)
+const WSOLA_WINDOW_SECS: f64 = 0.040;
+// How far around the ideal analysis position we search for the best-matching frame
+const WSOLA_SEARCH_SECS: f64 = 0.008;
+
+// Changes playback speed live. With `preserve_pitch` it uses WSOLA (overlap-add
+// of windowed input frames, each picked near its ideal position where it best
+// continues the previous frame); otherwise it resamples by linear interpolation,
+// which shifts pitch along with tempo. Until the speed first leaves 1.0 samples
+// pass straight through.
+struct SpeedSource<S: rodio::Source> {
+    inner: S,
+    shared: Arc<PlaybackSpeed>,
+    preserve_pitch: bool,
+    channels: usize,
+    // Frames pulled from `inner`, and the channel of the next pass-through sample
+    consumed: i64,
+    pass_channel: usize,
+    engaged: bool,
+    inner_done: bool,
+    finished: bool,
+    // Interleaved input history starting at frame `buf_start`
+    buf: VecDeque<f32>,
+    buf_start: i64,
+    out: VecDeque<f32>,
+    // WSOLA state
+    window: Vec<f32>,
+    hop: usize,
+    search: i64,
+    tail: Vec<f32>,
+    prev_pos: i64,
+    analysis_pos: f64,
+    // Linear resampler read position (frames)
+    read_pos: f64,
+}
+
+impl<S: rodio::Source<Item = f32>> SpeedSource<S> {
+    fn new(inner: S, shared: Arc<PlaybackSpeed>) -> Self {
+        let sr = inner.sample_rate() as f64;
+        let channels = inner.channels().max(1) as usize;
+        let hop = ((sr * WSOLA_WINDOW_SECS / 2.0) as usize).max(16);
+        let n = hop * 2;
+        let window = (0..n)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / n as f32).cos())
+            .collect();
+        let preserve_pitch = shared.preserve_pitch.load(Ordering::Relaxed);
+        Self {
+            inner,
+            shared,
+            preserve_pitch,
+            channels,
+            consumed: 0,
+            pass_channel: 0,
+            engaged: false,
+            inner_done: false,
+            finished: false,
+            buf: VecDeque::new(),
+            buf_start: 0,
+            out: VecDeque::new(),
+            window,
+            hop,
+            search: (sr * WSOLA_SEARCH_SECS) as i64,
+            tail: Vec::new(),
+            prev_pos: 0,
+            analysis_pos: 0.0,
+            read_pos: 0.0,
+        }
+    }
+
+    fn engage(&mut self) {
+        self.engaged = true;
+        self.buf.clear();
+        self.buf_start = self.consumed;
+        self.tail.clear();
+        self.read_pos = self.consumed as f64;
+    }
+
+    fn buf_end(&self) -> i64 {
+        self.buf_start + (self.buf.len() / self.channels) as i64
+    }
+
+    // Pull whole frames from the inner source until frame `end` is buffered.
+    fn fill(&mut self, end: i64) {
+        while !self.inner_done && self.buf_end() < end {
+            for c in 0..self.channels {
+                match self.inner.next() {
+                    Some(x) => self.buf.push_back(x),
+                    None => {
+                        // Drop a trailing partial frame
+                        self.buf.truncate(self.buf.len() - c);
+                        self.inner_done = true;
+                        return;
+                    }
+                }
+            }
+            self.consumed += 1;
+        }
+    }
+
+    fn sample(&self, frame: i64, channel: usize) -> f32 {
+        if frame < self.buf_start || frame >= self.buf_end() {
+            return 0.0;
+        }
+        self.buf[(frame - self.buf_start) as usize * self.channels + channel]
+    }
+
+    fn mono(&self, frame: i64) -> f32 {
+        (0..self.channels).map(|c| self.sample(frame, c)).sum()
+    }
+
+    fn discard_before(&mut self, frame: i64) {
+        let drop = (frame - self.buf_start).clamp(0, (self.buf.len() / self.channels) as i64);
+        self.buf.drain(..drop as usize * self.channels);
+        self.buf_start += drop;
+    }
+
+    // Produce one synthesis hop of output.
+    fn wsola_step(&mut self, speed: f64) {
+        let (hop, ch) = (self.hop as i64, self.channels);
+        if self.tail.is_empty() {
+            // Seed as if a frame had started one hop ago, so output continues at full level
+            self.fill(self.buf_start + hop);
+            self.prev_pos = self.buf_start - hop;
+            self.analysis_pos = self.prev_pos as f64;
+            self.tail = vec![0.0; self.hop * ch];
+            for i in 0..self.hop {
+                for c in 0..ch {
+                    self.tail[i * ch + c] = self.window[self.hop + i] * self.sample(self.buf_start + i as i64, c);
+                }
+            }
+        }
+
+        let ideal = self.analysis_pos + hop as f64 * speed;
+        let ideal_i = ideal.round() as i64;
+        let lo = (ideal_i - self.search).max(self.buf_start);
+        let hi = (ideal_i + self.search).max(lo);
+        let natural = self.prev_pos + hop;
+        self.fill(hi.max(natural) + 2 * hop);
+        let end = self.buf_end();
+        if self.inner_done && lo >= end {
+            self.out.extend(self.tail.drain(..));
+            self.finished = true;
+            return;
+        }
+
+        // Pick the candidate that best continues the previous frame (normalised
+        // cross-correlation over the overlap, sampled every 4th frame for speed)
+        let mut best = ideal_i.clamp(lo, hi);
+        if natural + hop <= end {
+            let mut best_score = f32::MIN;
+            for cand in lo..=hi.min(end - hop) {
+                let (mut dot, mut energy) = (0.0f32, 1e-9f32);
+                for i in (0..hop).step_by(4) {
+                    let x = self.mono(cand + i);
+                    dot += x * self.mono(natural + i);
+                    energy += x * x;
+                }
+                let score = dot / energy.sqrt();
+                if score > best_score {
+                    best_score = score;
+                    best = cand;
+                }
+            }
+        }
+
+        for i in 0..self.hop {
+            for c in 0..ch {
+                let x = self.window[i] * self.sample(best + i as i64, c);
+                self.out.push_back(self.tail[i * ch + c] + x);
+                self.tail[i * ch + c] = self.window[self.hop + i] * self.sample(best + hop + i as i64, c);
+            }
+        }
+        self.prev_pos = best;
+        self.analysis_pos = ideal;
+        self.discard_before((self.prev_pos + hop).min(self.analysis_pos as i64 - self.search));
+    }
+
+    // Produce a block of output by reading the input at `speed` frames per frame.
+    fn resample_step(&mut self, speed: f64) {
+        for _ in 0..256 {
+            let i0 = self.read_pos.floor() as i64;
+            self.fill(i0 + 2);
+            if i0 + 1 >= self.buf_end() && self.inner_done {
+                self.finished = true;
+                return;
+            }
+            let t = (self.read_pos - i0 as f64) as f32;
+            for c in 0..self.channels {
+                let x = self.sample(i0, c) * (1.0 - t) + self.sample(i0 + 1, c) * t;
+                self.out.push_back(x);
+            }
+            self.read_pos += speed;
+            self.discard_before(i0);
+        }
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> Iterator for SpeedSource<S> {
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(x) = self.out.pop_front() {
+                return Some(x);
+            }
+            if self.finished {
+                return None;
+            }
+            let speed = self.shared.factor() as f64;
+            // Stay transparent at 1.0x; switch over only at a frame boundary
+            if !self.engaged && ((speed - 1.0).abs() < 1e-3 || self.pass_channel != 0) {
+                let x = self.inner.next()?;
+                self.pass_channel += 1;
+                if self.pass_channel == self.channels {
+                    self.pass_channel = 0;
+                    self.consumed += 1;
+                }
+                return Some(x);
+            }
+            if !self.engaged {
+                self.engage();
+            }
+            if self.preserve_pitch {
+                self.wsola_step(speed);
+            } else {
+                self.resample_step(speed);
+            }
+        }
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> rodio::Source for SpeedSource<S> {
+    fn channels(&self) -> u16 { self.inner.channels() }
+    fn sample_rate(&self) -> u32 { self.inner.sample_rate() }
+    fn current_span_len(&self) -> Option<usize> { if self.engaged { None } else { self.inner.current_span_len() } }
+    fn total_duration(&self) -> Option<Duration> { if self.engaged { None } else { self.inner.total_duration() } }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        self.out.clear();
+        self.pass_channel = 0;
+        self.inner_done = false;
+        self.finished = false;
+        if self.engaged {
+            self.engage();
+        }
+        Ok(())
+    }
+}
+
+// ===== Pitch shift =====
+// Transposition in semitones plus cents; `version` tells the playing source to
+// pick up new settings, like `Equalizer`.
+pub(crate) struct PitchShift {
+    settings: Mutex<(i32, f32)>,
+    version: AtomicU64,
+}
+impl Default for PitchShift { fn default() -> Self { Self { settings: Mutex::new((0, 0.0)), version: AtomicU64::new(0) } } }
+impl PitchShift {
+    pub(crate) fn set(&self, semitones: i32, cents: f32) {
+        if let Ok(mut s) = self.settings.lock() { *s = (semitones.clamp(-12, 12), cents.clamp(-50.0, 50.0)); }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn snapshot(&self) -> (i32, f32) {
+        self.settings.lock().map(|s| *s).unwrap_or((0, 0.0))
+    }
+    // Frequency ratio, kept within one octave either way
+    fn ratio(&self) -> f64 {
+        let (semitones, cents) = self.snapshot();
+        let total = (semitones as f64 * 100.0 + cents as f64).clamp(-1200.0, 1200.0);
+        2f64.powf(total / 1200.0)
+    }
+}
+
+// Shifts pitch without changing tempo: the inner WSOLA stage stretches time by
+// the pitch ratio, then reading that back `ratio` times faster restores the
+// original duration at the new pitch.
+struct PitchSource<S: rodio::Source> {
+    inner: SpeedSource<S>,
+    stretch: Arc<PlaybackSpeed>,
+    shared: Arc<PitchShift>,
+    last_version: u64,
+    ratio: f64,
+    engaged: bool,
+    channels: usize,
+    // Linear interpolation between two input frames
+    cur: Vec<f32>,
+    next: Vec<f32>,
+    frac: f64,
+    out_channel: usize,
+    at_end: bool,
+}
+
+impl<S: rodio::Source<Item = f32>> PitchSource<S> {
+    fn new(inner: S, shared: Arc<PitchShift>) -> Self {
+        let stretch = Arc::new(PlaybackSpeed::default());
+        let channels = inner.channels().max(1) as usize;
+        let ratio = shared.ratio();
+        stretch.set_factor((1.0 / ratio) as f32);
+        let last_version = shared.version.load(Ordering::Relaxed);
+        Self {
+            inner: SpeedSource::new(inner, stretch.clone()),
+            stretch,
+            shared,
+            last_version,
+            ratio,
+            engaged: false,
+            channels,
+            cur: vec![0.0; channels],
+            next: vec![0.0; channels],
+            frac: 0.0,
+            out_channel: 0,
+            at_end: false,
+        }
+    }
+
+    fn read_frame(&mut self) -> bool {
+        std::mem::swap(&mut self.cur, &mut self.next);
+        for c in 0..self.channels {
+            match self.inner.next() {
+                Some(x) => self.next[c] = x,
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> Iterator for PitchSource<S> {
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        // Pick up new settings only between frames
+        if self.out_channel == 0 {
+            let current_version = self.shared.version.load(Ordering::Relaxed);
+            if current_version != self.last_version {
+                self.ratio = self.shared.ratio();
+                self.stretch.set_factor((1.0 / self.ratio) as f32);
+                self.last_version = current_version;
+            }
+        }
+        if !self.engaged {
+            if (self.ratio - 1.0).abs() < 1e-6 || self.out_channel != 0 {
+                let x = self.inner.next()?;
+                self.out_channel = (self.out_channel + 1) % self.channels;
+                return Some(x);
+            }
+            self.engaged = true;
+            // Prime the interpolator with two frames
+            if !self.read_frame() || !self.read_frame() {
+                return None;
+            }
+        }
+        if self.at_end {
+            return None;
+        }
+        let t = self.frac as f32;
+        let x = self.cur[self.out_channel] * (1.0 - t) + self.next[self.out_channel] * t;
+        self.out_channel += 1;
+        if self.out_channel == self.channels {
+            self.out_channel = 0;
+            self.frac += self.ratio;
+            while self.frac >= 1.0 {
+                self.frac -= 1.0;
+                if !self.read_frame() {
+                    self.at_end = true;
+                    break;
+                }
+            }
+        }
+        Some(x)
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> rodio::Source for PitchSource<S> {
+    fn channels(&self) -> u16 { self.inner.channels() }
+    fn sample_rate(&self) -> u32 { self.inner.sample_rate() }
+    fn current_span_len(&self) -> Option<usize> { if self.engaged { None } else { self.inner.current_span_len() } }
+    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        self.out_channel = 0;
+        self.at_end = false;
+        if self.engaged {
+            self.frac = 0.0;
+            if !self.read_frame() || !self.read_frame() {
+                self.at_end = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ===== Output format and resampling =====
+/// Output sample rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum SampleRateChoice {
+    /// Whatever the device is configured for.
+    #[default]
+    DeviceDefault,
+    /// Reopen the stream at each track's native rate (bit-transparent when supported).
+    FollowSource,
+    /// This rate in Hz, if the device accepts it.
+    Fixed(u32),
+}
+
+impl SampleRateChoice {
+    pub(crate) const ALL: [SampleRateChoice; 8] = [
+        SampleRateChoice::DeviceDefault,
+        SampleRateChoice::FollowSource,
+        SampleRateChoice::Fixed(44_100),
+        SampleRateChoice::Fixed(48_000),
+        SampleRateChoice::Fixed(88_200),
+        SampleRateChoice::Fixed(96_000),
+        SampleRateChoice::Fixed(176_400),
+        SampleRateChoice::Fixed(192_000),
+    ];
+
+    fn fixed(self) -> Option<u32> {
+        match self {
+            SampleRateChoice::Fixed(rate) => Some(rate),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SampleRateChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SampleRateChoice::DeviceDefault => write!(f, "Device default"),
+            SampleRateChoice::FollowSource => write!(f, "Follow source"),
+            SampleRateChoice::Fixed(rate) => write!(f, "{rate} Hz"),
+        }
+    }
+}
+
+/// Output buffer size in frames; smaller means lower latency but more risk of dropouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum BufferSizeChoice {
+    /// The backend's default.
+    #[default]
+    Default,
+    Frames(u32),
+}
+
+impl BufferSizeChoice {
+    pub(crate) const ALL: [BufferSizeChoice; 7] = [
+        BufferSizeChoice::Default,
+        BufferSizeChoice::Frames(128),
+        BufferSizeChoice::Frames(256),
+        BufferSizeChoice::Frames(512),
+        BufferSizeChoice::Frames(1024),
+        BufferSizeChoice::Frames(2048),
+        BufferSizeChoice::Frames(4096),
+    ];
+
+    fn frames(self) -> Option<u32> {
+        match self {
+            BufferSizeChoice::Frames(n) => Some(n),
+            BufferSizeChoice::Default => None,
+        }
+    }
+}
+
+impl std::fmt::Display for BufferSizeChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BufferSizeChoice::Default => write!(f, "Default"),
+            BufferSizeChoice::Frames(n) => write!(f, "{n} frames"),
+        }
+    }
+}
+
+/// Interpolation used to convert tracks to the device's sample rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum ResamplerQuality {
+    /// Linear interpolation.
+    Fast,
+    /// Cubic (Catmull-Rom) interpolation.
+    #[default]
+    Balanced,
+    /// Blackman-windowed sinc, 32 taps.
+    High,
+}
+
+impl ResamplerQuality {
+    pub(crate) const ALL: [ResamplerQuality; 3] = [ResamplerQuality::Fast, ResamplerQuality::Balanced, ResamplerQuality::High];
+
+    fn taps(self) -> usize {
+        match self {
+            ResamplerQuality::Fast => 2,
+            ResamplerQuality::Balanced => 4,
+            ResamplerQuality::High => 32,
+        }
+    }
+}
+
+impl std::fmt::Display for ResamplerQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResamplerQuality::Fast => write!(f, "Fast (linear)"),
+            ResamplerQuality::Balanced => write!(f, "Balanced (cubic)"),
+            ResamplerQuality::High => write!(f, "High (sinc)"),
+        }
+    }
+}
+
+/// Requested output format. Values the device rejects fall back to one it supports.
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct OutputSettings {
+    pub sample_rate: SampleRateChoice,
+    pub buffer_size: BufferSizeChoice,
+    pub resampler: ResamplerQuality,
+}
+
+// Interpolation kernel sampled at this many fractional positions per tap
+const RESAMPLE_PHASES: usize = 512;
+
+// Converts the chain's sample rate to the output stream's rate. Transparent when
+// the rates already match.
+struct ResampleSource<S: rodio::Source> {
+    inner: S,
+    channels: usize,
+    out_rate: u32,
+    passthrough: bool,
+    taps: usize,
+    // `RESAMPLE_PHASES + 1` rows of `taps` weights
+    kernel: Vec<f32>,
+    // Input frames ending just after the interpolation point, interleaved
+    window: VecDeque<f32>,
+    // Input frames advanced per output frame, and position between the two centre frames
+    step: f64,
+    frac: f64,
+    frame: Vec<f32>,
+    out_channel: usize,
+    // Zero frames fed after the input ran out; done once the tail has played
+    flushed: usize,
+}
+
+impl<S: rodio::Source<Item = f32>> ResampleSource<S> {
+    fn new(inner: S, out_rate: u32, quality: ResamplerQuality) -> Self {
+        let in_rate = inner.sample_rate().max(1);
+        let channels = inner.channels().max(1) as usize;
+        let passthrough = in_rate == out_rate;
+        let taps = quality.taps();
+        let kernel = if passthrough { Vec::new() } else { resample_kernel(quality, in_rate, out_rate) };
+        let mut me = Self {
+            inner,
+            channels,
+            out_rate,
+            passthrough,
+            taps,
+            kernel,
+            window: VecDeque::with_capacity(taps * channels),
+            step: in_rate as f64 / out_rate.max(1) as f64,
+            frac: 0.0,
+            frame: vec![0.0; channels],
+            out_channel: 0,
+            flushed: 0,
+        };
+        if !passthrough {
+            me.prime();
+        }
+        me
+    }
+
+    // Silence before the first frame so it lands on the interpolation point
+    fn prime(&mut self) {
+        self.window.clear();
+        self.window.resize((self.taps / 2 - 1) * self.channels, 0.0);
+        self.frac = 0.0;
+        self.flushed = 0;
+        self.out_channel = 0;
+        while self.window.len() < self.taps * self.channels {
+            self.push_frame();
+        }
+    }
+
+    fn push_frame(&mut self) {
+        for c in 0..self.channels {
+            match self.inner.next() {
+                Some(x) => self.window.push_back(x),
+                None => {
+                    // Pad the frame with silence and count it towards the flush
+                    self.window.extend(std::iter::repeat_n(0.0, self.channels - c));
+                    self.flushed += 1;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn compute_frame(&mut self) {
+        let phase = ((self.frac * RESAMPLE_PHASES as f64).round() as usize).min(RESAMPLE_PHASES);
+        let weights = &self.kernel[phase * self.taps..(phase + 1) * self.taps];
+        for c in 0..self.channels {
+            let mut acc = 0.0;
+            for (k, w) in weights.iter().enumerate() {
+                acc += self.window[k * self.channels + c] * w;
+            }
+            self.frame[c] = acc;
+        }
+    }
+}
+
+// Weight of each tap for every fractional position. Tap `k` sits at
+// `k - (taps / 2 - 1)` input frames from the frame before the interpolation point.
+fn resample_kernel(quality: ResamplerQuality, in_rate: u32, out_rate: u32) -> Vec<f32> {
+    use std::f64::consts::PI;
+    let taps = quality.taps();
+    let centre = (taps / 2 - 1) as f64;
+    // Lower the cutoff when downsampling so content above the new Nyquist doesn't alias
+    let cutoff = (out_rate as f64 / in_rate as f64).min(1.0) * 0.95;
+    let mut kernel = Vec::with_capacity((RESAMPLE_PHASES + 1) * taps);
+    for p in 0..=RESAMPLE_PHASES {
+        let frac = p as f64 / RESAMPLE_PHASES as f64;
+        let start = kernel.len();
+        for k in 0..taps {
+            let d = k as f64 - centre - frac;
+            let w = match quality {
+                ResamplerQuality::Fast => (1.0 - d.abs()).max(0.0),
+                ResamplerQuality::Balanced => {
+                    let x = d.abs();
+                    if x < 1.0 {
+                        1.5 * x * x * x - 2.5 * x * x + 1.0
+                    } else if x < 2.0 {
+                        -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+                    } else {
+                        0.0
+                    }
+                }
+                ResamplerQuality::High => {
+                    let half = taps as f64 / 2.0;
+                    let sinc = if d.abs() < 1e-9 { 1.0 } else { (PI * cutoff * d).sin() / (PI * cutoff * d) };
+                    let t = (d / half).clamp(-1.0, 1.0);
+                    let blackman = 0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos();
+                    cutoff * sinc * blackman
+                }
+            };
+            kernel.push(w as f32);
+        }
+        // Normalise so DC passes at unity gain for every phase
+        let sum: f32 = kernel[start..].iter().sum();
+        if sum.abs() > 1e-6 {
+            kernel[start..].iter_mut().for_each(|w| *w /= sum);
+        }
+    }
+    kernel
+}
+
+impl<S: rodio::Source<Item = f32>> Iterator for ResampleSource<S> {
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.passthrough {
+            return self.inner.next();
+        }
+        if self.out_channel == 0 {
+            // The last real frame has passed the interpolation point
+            if self.flushed > self.taps / 2 {
+                return None;
+            }
+            self.compute_frame();
+        }
+        let x = self.frame[self.out_channel];
+        self.out_channel += 1;
+        if self.out_channel == self.channels {
+            self.out_channel = 0;
+            self.frac += self.step;
+            while self.frac >= 1.0 {
+                self.frac -= 1.0;
+                self.window.drain(..self.channels);
+                self.push_frame();
+            }
+        }
+        Some(x)
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> rodio::Source for ResampleSource<S> {
+    fn channels(&self) -> u16 { self.inner.channels() }
+    fn sample_rate(&self) -> u32 { if self.passthrough { self.inner.sample_rate() } else { self.out_rate } }
+    fn current_span_len(&self) -> Option<usize> { if self.passthrough { self.inner.current_span_len() } else { None } }
+    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        if !self.passthrough {
+            self.prime();
+        }
+        Ok(())
+    }
+}
+
+// ===== Balance, mono downmix and channel swap =====
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct ChannelSettings {
+    // -1.0 = left only, 0.0 = centre, 1.0 = right only
+    pub(crate) balance: f32,
+    pub(crate) mono: bool,
+    pub(crate) swap: bool,
+}
+
+pub(crate) struct ChannelMix {
+    settings: Mutex<ChannelSettings>,
+    version: AtomicU64,
+}
+impl Default for ChannelMix { fn default() -> Self { Self { settings: Mutex::new(ChannelSettings::default()), version: AtomicU64::new(0) } } }
+impl ChannelMix {
+    pub(crate) fn set(&self, settings: ChannelSettings) {
+        if let Ok(mut s) = self.settings.lock() { *s = settings; }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    fn snapshot(&self) -> ChannelSettings {
+        self.settings.lock().map(|s| *s).unwrap_or_default()
+    }
+}
+
+// Side of each channel in the usual WAV/FLAC order (FL FR FC LFE BL BR SL SR);
+// -1 left, 1 right, 0 centre. Anything past eight channels counts as centre.
+fn channel_side(channel: usize, channels: usize) -> i8 {
+    if channels < 2 {
+        return 0;
+    }
+    match channel {
+        0 | 4 | 6 => -1,
+        1 | 5 | 7 => 1,
+        _ => 0,
+    }
+}
+
+// Time for gain changes to settle, so dragging the balance slider doesn't zipper
+const BALANCE_SMOOTH_SECS: f32 = 0.02;
+
+struct ChannelMixProcessor {
+    shared: Arc<ChannelMix>,
+    last_version: u64,
+    settings: ChannelSettings,
+    // Per-channel gain from the balance setting: current and target
+    gains: Vec<f32>,
+    targets: Vec<f32>,
+    smooth: f32,
+}
+
+impl ChannelMixProcessor {
+    fn new(shared: Arc<ChannelMix>) -> Self {
+        let last_version = shared.version.load(Ordering::Relaxed);
+        let settings = shared.snapshot();
+        Self {
+            shared,
+            last_version,
+            settings,
+            gains: Vec::with_capacity(MAX_CHANNELS),
+            targets: Vec::with_capacity(MAX_CHANNELS),
+            smooth: 1.0,
+        }
+    }
+
+    // Balance only attenuates the opposite side, so centre stays at unity gain.
+    // Refills `targets` in place, so it doesn't allocate on the audio thread.
+    fn set_targets(&mut self, channels: usize) {
+        let balance = self.settings.balance.clamp(-1.0, 1.0);
+        self.targets.clear();
+        self.targets.extend((0..channels).map(|c| match channel_side(c, channels) {
+            -1 => (1.0 - balance.max(0.0)).max(0.0),
+            1 => (1.0 + balance.min(0.0)).max(0.0),
+            _ => 1.0,
+        }));
+    }
+}
+
+impl DspProcessor for ChannelMixProcessor {
+    fn configure(&mut self, channels: usize, sample_rate: u32) {
+        self.smooth = 1.0 / (sample_rate as f32 * BALANCE_SMOOTH_SECS).max(1.0);
+        self.set_targets(channels);
+        self.gains.clear();
+        self.gains.extend_from_slice(&self.targets);
+    }
+    fn update(&mut self) {
+        let current_version = self.shared.version.load(Ordering::Relaxed);
+        if current_version != self.last_version {
+            self.settings = self.shared.snapshot();
+            self.set_targets(self.targets.len());
+            self.last_version = current_version;
+        }
+    }
+    fn reset(&mut self) {
+        self.gains.copy_from_slice(&self.targets);
+    }
+    fn process(&mut self, frame: &mut [f32]) {
+        let n = frame.len();
+        if self.settings.mono && n > 1 {
+            let avg = frame.iter().sum::<f32>() / n as f32;
+            frame.iter_mut().for_each(|x| *x = avg);
+        }
+        if self.settings.swap && n > 1 {
+            // Swap each left/right pair that exists in this layout
+            for (l, r) in [(0, 1), (4, 5), (6, 7)] {
+                if r < n {
+                    frame.swap(l, r);
+                }
+            }
+        }
+        for (c, x) in frame.iter_mut().enumerate() {
+            self.gains[c] += (self.targets[c] - self.gains[c]) * self.smooth;
+            if (self.gains[c] - self.targets[c]).abs() < 1e-4 {
+                self.gains[c] = self.targets[c];
+            }
+            *x *= self.gains[c];
+        }
+    }
+}
+
+// ===== Crossfeed (Bauer stereophonic-to-binaural) =====
+// Feeds a low-passed, attenuated copy of each channel into the other ear, the
+// way speakers would, and shelves the direct signal to keep the overall tone.
+pub(crate) const CROSSFEED_CUTOFF_RANGE: std::ops::RangeInclusive<f32> = 300.0..=2000.0;
+pub(crate) const CROSSFEED_FEED_RANGE: std::ops::RangeInclusive<f32> = 1.0..=15.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) enum CrossfeedPreset {
+    #[default]
+    Default,
+    ChuMoy,
+    JanMeier,
+    Custom,
+}
+
+impl CrossfeedPreset {
+    pub(crate) const ALL: [CrossfeedPreset; 4] = [CrossfeedPreset::Default, CrossfeedPreset::ChuMoy, CrossfeedPreset::JanMeier, CrossfeedPreset::Custom];
+
+    // (cutoff Hz, feed level dB); None for custom settings
+    pub(crate) fn params(self) -> Option<(f32, f32)> {
+        match self {
+            CrossfeedPreset::Default => Some((700.0, 4.5)),
+            CrossfeedPreset::ChuMoy => Some((700.0, 6.0)),
+            CrossfeedPreset::JanMeier => Some((650.0, 9.5)),
+            CrossfeedPreset::Custom => None,
+        }
+    }
+}
+
+impl std::fmt::Display for CrossfeedPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrossfeedPreset::Default => write!(f, "Default (700 Hz, 4.5 dB)"),
+            CrossfeedPreset::ChuMoy => write!(f, "Chu Moy (700 Hz, 6 dB)"),
+            CrossfeedPreset::JanMeier => write!(f, "Jan Meier (650 Hz, 9.5 dB)"),
+            CrossfeedPreset::Custom => write!(f, "Custom"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct CrossfeedSettings {
+    pub(crate) preset: CrossfeedPreset,
+    pub(crate) cutoff_hz: f32,
+    pub(crate) feed_db: f32,
+}
+
+impl Default for CrossfeedSettings {
+    fn default() -> Self {
+        Self { preset: CrossfeedPreset::Default, cutoff_hz: 700.0, feed_db: 4.5 }
+    }
+}
+
+pub(crate) struct Crossfeed {
+    settings: Mutex<CrossfeedSettings>,
+    version: AtomicU64,
+}
+impl Default for Crossfeed { fn default() -> Self { Self { settings: Mutex::new(CrossfeedSettings::default()), version: AtomicU64::new(0) } } }
+impl Crossfeed {
+    pub(crate) fn set(&self, settings: CrossfeedSettings) {
+        if let Ok(mut s) = self.settings.lock() { *s = settings; }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    fn snapshot(&self) -> CrossfeedSettings {
+        self.settings.lock().map(|s| *s).unwrap_or_default()
+    }
+}
+
+// One-pole filter coefficients, as in libbs2b
+#[derive(Clone, Copy, Default)]
+struct CrossfeedCoeffs { a0_lo: f32, b1_lo: f32, a0_hi: f32, a1_hi: f32, b1_hi: f32 }
+
+fn crossfeed_coeffs(sr: f32, cutoff_hz: f32, feed_db: f32) -> CrossfeedCoeffs {
+    use std::f32::consts::PI;
+    let gb_lo = feed_db * -5.0 / 6.0 - 3.0;
+    let gb_hi = feed_db / 6.0 - 3.0;
+    let g_lo = 10f32.powf(gb_lo / 20.0);
+    let g_hi = 1.0 - 10f32.powf(gb_hi / 20.0);
+    let fc_hi = cutoff_hz * 2f32.powf((gb_lo - 20.0 * g_hi.log10()) / 12.0);
+
+    let x = (-2.0 * PI * cutoff_hz / sr).exp();
+    let (a0_lo, b1_lo) = (g_lo * (1.0 - x), x);
+    let x = (-2.0 * PI * fc_hi / sr).exp();
+    CrossfeedCoeffs { a0_lo, b1_lo, a0_hi: 1.0 - g_hi * (1.0 - x), a1_hi: -x, b1_hi: x }
+}
+
+// Applied to the front left/right pair; other channels pass through untouched.
+struct CrossfeedProcessor {
+    shared: Arc<Crossfeed>,
+    last_version: u64,
+    sample_rate: f32,
+    coeffs: CrossfeedCoeffs,
+    // Filter state for [left, right]
+    lo: [f32; 2],
+    hi: [f32; 2],
+    prev: [f32; 2],
+}
+
+impl CrossfeedProcessor {
+    fn new(shared: Arc<Crossfeed>) -> Self {
+        let last_version = shared.version.load(Ordering::Relaxed);
+        Self { shared, last_version, sample_rate: 44_100.0, coeffs: CrossfeedCoeffs::default(), lo: [0.0; 2], hi: [0.0; 2], prev: [0.0; 2] }
+    }
+
+    fn rebuild(&mut self) {
+        let settings = self.shared.snapshot();
+        self.coeffs = crossfeed_coeffs(self.sample_rate, settings.cutoff_hz, settings.feed_db);
+    }
+}
+
+impl DspProcessor for CrossfeedProcessor {
+    fn configure(&mut self, _channels: usize, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.reset();
+        self.rebuild();
+    }
+    fn reset(&mut self) {
+        self.lo = [0.0; 2];
+        self.hi = [0.0; 2];
+        self.prev = [0.0; 2];
+    }
+    fn update(&mut self) {
+        let current_version = self.shared.version.load(Ordering::Relaxed);
+        if current_version != self.last_version {
+            self.rebuild();
+            self.last_version = current_version;
+        }
+    }
+    fn process(&mut self, frame: &mut [f32]) {
+        if frame.len() < 2 {
+            return;
+        }
+        let c = self.coeffs;
+        let input = [frame[0], frame[1]];
+        for (ch, x) in input.into_iter().enumerate() {
+            self.lo[ch] = c.a0_lo * x + c.b1_lo * self.lo[ch];
+            self.hi[ch] = c.a0_hi * x + c.a1_hi * self.prev[ch] + c.b1_hi * self.hi[ch];
+        }
+        self.prev = input;
+        frame[0] = self.hi[0] + self.lo[1];
+        frame[1] = self.hi[1] + self.lo[0];
+    }
+}
+
+// ===== Compressor / limiter =====
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct CompressorSettings {
+    threshold_db: f32,
+    // Input dB over threshold per output dB; 20:1 and up acts as a limiter
+    ratio: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    makeup_db: f32,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self { threshold_db: -18.0, ratio: 3.0, attack_ms: 10.0, release_ms: 200.0, makeup_db: 0.0 }
+    }
+}
+
+impl CompressorSettings {
+    // Heavy compression with make-up gain so dialogue and quiet passages stay
+    // audible at low volume without loud scenes jumping out
+    pub(crate) fn night_mode() -> Self {
+        Self { threshold_db: -32.0, ratio: 6.0, attack_ms: 5.0, release_ms: 300.0, makeup_db: 12.0 }
+    }
+}
+
+// Slider-adjustable compressor parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CompressorParam {
+    Threshold,
+    Ratio,
+    Attack,
+    Release,
+    Makeup,
+}
+
+impl CompressorParam {
+    pub(crate) const ALL: [CompressorParam; 5] =
+        [CompressorParam::Threshold, CompressorParam::Ratio, CompressorParam::Attack, CompressorParam::Release, CompressorParam::Makeup];
+
+    pub(crate) fn field(self, s: &mut CompressorSettings) -> &mut f32 {
+        match self {
+            CompressorParam::Threshold => &mut s.threshold_db,
+            CompressorParam::Ratio => &mut s.ratio,
+            CompressorParam::Attack => &mut s.attack_ms,
+            CompressorParam::Release => &mut s.release_ms,
+            CompressorParam::Makeup => &mut s.makeup_db,
+        }
+    }
+
+    pub(crate) fn value(self, mut s: CompressorSettings) -> f32 {
+        *self.field(&mut s)
+    }
+
+    pub(crate) fn range(self) -> (std::ops::RangeInclusive<f32>, f32) {
+        match self {
+            CompressorParam::Threshold => (-60.0..=0.0, 0.5),
+            CompressorParam::Ratio => (1.0..=20.0, 0.5),
+            CompressorParam::Attack => (0.1..=200.0, 0.1),
+            CompressorParam::Release => (10.0..=2000.0, 10.0),
+            CompressorParam::Makeup => (0.0..=24.0, 0.5),
+        }
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            CompressorParam::Threshold => "Threshold",
+            CompressorParam::Ratio => "Ratio",
+            CompressorParam::Attack => "Attack",
+            CompressorParam::Release => "Release",
+            CompressorParam::Makeup => "Make-up gain",
+        }
+    }
+
+    pub(crate) fn format(self, value: f32) -> String {
+        match self {
+            CompressorParam::Threshold => format!("{value:.1} dB"),
+            CompressorParam::Ratio if value >= COMPRESSOR_LIMIT_RATIO => "∞:1 (limit)".to_string(),
+            CompressorParam::Ratio => format!("{value:.1}:1"),
+            CompressorParam::Attack => format!("{value:.1} ms"),
+            CompressorParam::Release => format!("{value:.0} ms"),
+            CompressorParam::Makeup => format!("+{value:.1} dB"),
+        }
+    }
+}
+
+// Settings plus the current gain reduction for display, published by the audio thread
+pub(crate) struct Compressor {
+    settings: Mutex<CompressorSettings>,
+    version: AtomicU64,
+    reduction_bits: AtomicU32,
+}
+impl Default for Compressor {
+    fn default() -> Self {
+        Self { settings: Mutex::new(CompressorSettings::default()), version: AtomicU64::new(0), reduction_bits: AtomicU32::new(0) }
+    }
+}
+impl Compressor {
+    pub(crate) fn set(&self, settings: CompressorSettings) {
+        if let Ok(mut s) = self.settings.lock() { *s = settings; }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    fn snapshot(&self) -> CompressorSettings {
+        self.settings.lock().map(|s| *s).unwrap_or_default()
+    }
+    pub(crate) fn gain_reduction_db(&self) -> f32 {
+        f32::from_bits(self.reduction_bits.load(Ordering::Relaxed))
+    }
+}
+
+// Soft knee width around the threshold
+const COMPRESSOR_KNEE_DB: f32 = 6.0;
+// Ratio from which the curve is flat above the threshold
+const COMPRESSOR_LIMIT_RATIO: f32 = 20.0;
+// Output peaks are held below this, so make-up gain can't clip
+const COMPRESSOR_CEILING_DB: f32 = -1.0;
+
+// Feed-forward peak compressor, linked across channels so the stereo image holds.
+struct CompressorProcessor {
+    shared: Arc<Compressor>,
+    last_version: u64,
+    settings: CompressorSettings,
+    sample_rate: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    // Smoothed gain reduction in dB (positive = quieter)
+    reduction_db: f32,
+    // Extra reduction holding peaks under the ceiling; instant attack, normal release
+    ceiling_db: f32,
+    frames_since_publish: u32,
+}
+
+impl CompressorProcessor {
+    fn new(shared: Arc<Compressor>) -> Self {
+        let last_version = shared.version.load(Ordering::Relaxed);
+        let settings = shared.snapshot();
+        Self {
+            shared,
+            last_version,
+            settings,
+            sample_rate: 44_100.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            reduction_db: 0.0,
+            ceiling_db: 0.0,
+            frames_since_publish: 0,
+        }
+    }
+
+    fn apply(&mut self, settings: CompressorSettings) {
+        let sr = self.sample_rate;
+        let coeff = |ms: f32| (-1.0 / (ms.max(0.01) * 0.001 * sr)).exp();
+        self.attack_coeff = coeff(settings.attack_ms);
+        self.release_coeff = coeff(settings.release_ms);
+        self.settings = settings;
+    }
+
+    // Static curve: dB of gain reduction for a given input level
+    fn target_reduction(&self, level_db: f32) -> f32 {
+        let s = &self.settings;
+        let slope = if s.ratio >= COMPRESSOR_LIMIT_RATIO { 1.0 } else { 1.0 - 1.0 / s.ratio.max(1.0) };
+        let over = level_db - s.threshold_db;
+        if over <= -COMPRESSOR_KNEE_DB / 2.0 {
+            0.0
+        } else if over < COMPRESSOR_KNEE_DB / 2.0 {
+            let x = over + COMPRESSOR_KNEE_DB / 2.0;
+            slope * x * x / (2.0 * COMPRESSOR_KNEE_DB)
+        } else {
+            slope * over
+        }
+    }
+}
+
+impl DspProcessor for CompressorProcessor {
+    fn configure(&mut self, _channels: usize, sample_rate: u32) {
+        self.sample_rate = sample_rate.max(1) as f32;
+        self.reset();
+        self.apply(self.settings);
+    }
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+        self.ceiling_db = 0.0;
+        self.shared.reduction_bits.store(0f32.to_bits(), Ordering::Relaxed);
+    }
+    fn update(&mut self) {
+        let current_version = self.shared.version.load(Ordering::Relaxed);
+        if current_version != self.last_version {
+            let settings = self.shared.snapshot();
+            self.apply(settings);
+            self.last_version = current_version;
+        }
+    }
+    fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        let level_db = 20.0 * peak.max(1e-6).log10();
+        let target = self.target_reduction(level_db);
+        let coeff = if target > self.reduction_db { self.attack_coeff } else { self.release_coeff };
+        self.reduction_db = target + coeff * (self.reduction_db - target);
+        let gain_db = self.settings.makeup_db - self.reduction_db;
+        let over = (level_db + gain_db - COMPRESSOR_CEILING_DB).max(0.0);
+        self.ceiling_db = if over >= self.ceiling_db { over } else { over + self.release_coeff * (self.ceiling_db - over) };
+        let gain = 10f32.powf((gain_db - self.ceiling_db) / 20.0);
+        frame.iter_mut().for_each(|x| *x *= gain);
+
+        // Publish for the UI meter roughly every 10 ms at 48 kHz
+        self.frames_since_publish += 1;
+        if self.frames_since_publish >= 512 {
+            self.frames_since_publish = 0;
+            let reduction = self.reduction_db + self.ceiling_db;
+            self.shared.reduction_bits.store(reduction.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+// ===== DSP chain =====
+// Effects run on whole interleaved frames in the order the user picked. Each
+// processor reads its settings from shared state (with a version counter, like
+// `Equalizer`) so changes apply without reopening the file.
+trait DspProcessor: Send {
+    // Called before the first frame and again if the channel count or sample
+    // rate changes. The repeat calls run on the audio thread, so per-channel
+    // buffers are sized for `MAX_CHANNELS` in `new` and only resized here.
+    fn configure(&mut self, channels: usize, sample_rate: u32);
+    // Pick up changed settings; called once per frame while enabled, also while bypassed
+    fn update(&mut self) {}
+    fn process(&mut self, frame: &mut [f32]);
+    // Forget the signal history when the effect is switched back on. Runs on
+    // the audio thread, so it mustn't allocate.
+    fn reset(&mut self) {}
+}
+
+// Every effect the chain knows about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub(crate) enum EffectKind {
+    Gain,
+    Equalizer,
+    Compressor,
+    Crossfeed,
+    Balance,
+    Convolution,
+}
+
+impl EffectKind {
+    const ALL: [EffectKind; 6] =
+        [EffectKind::Gain, EffectKind::Equalizer, EffectKind::Compressor, EffectKind::Crossfeed, EffectKind::Balance, EffectKind::Convolution];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            EffectKind::Gain => "Gain",
+            EffectKind::Equalizer => "Equalizer",
+            EffectKind::Compressor => "Compressor",
+            EffectKind::Crossfeed => "Crossfeed",
+            EffectKind::Balance => "Balance / mono / swap",
+            EffectKind::Convolution => "Convolution (IR)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct EffectSlot {
+    pub(crate) kind: EffectKind,
+    // Disabled effects are left out of the chain entirely
+    pub(crate) enabled: bool,
+    // Bypassed effects keep their settings but let audio through unchanged
+    #[serde(default)]
+    pub(crate) bypass: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct DspChainSettings {
+    pub(crate) slots: Vec<EffectSlot>,
+    pub(crate) bypass_all: bool,
+}
+
+impl Default for DspChainSettings {
+    fn default() -> Self {
+        let enabled = |kind| !matches!(kind, EffectKind::Compressor | EffectKind::Crossfeed | EffectKind::Convolution);
+        Self {
+            slots: EffectKind::ALL.iter().map(|&kind| EffectSlot { kind, enabled: enabled(kind), bypass: false }).collect(),
+            bypass_all: false,
+        }
+    }
+}
+
+impl DspChainSettings {
+    // Keep each effect exactly once; effects missing from an older config go at the end, off
+    pub(crate) fn normalized(mut self) -> Self {
+        let mut seen = HashSet::new();
+        self.slots.retain(|s| seen.insert(s.kind));
+        for kind in EffectKind::ALL {
+            if !seen.contains(&kind) {
+                self.slots.push(EffectSlot { kind, enabled: false, bypass: false });
+            }
+        }
+        self
+    }
+
+    pub(crate) fn slot_mut(&mut self, kind: EffectKind) -> Option<&mut EffectSlot> {
+        self.slots.iter_mut().find(|s| s.kind == kind)
+    }
+
+    pub(crate) fn is_enabled(&self, kind: EffectKind) -> bool {
+        self.slots.iter().any(|s| s.kind == kind && s.enabled)
+    }
+}
+
+// The chain settings in a form the audio thread can copy without allocating
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChainLayout {
+    slots: [EffectSlot; EffectKind::ALL.len()],
+    bypass_all: bool,
+}
+
+impl From<DspChainSettings> for ChainLayout {
+    fn from(settings: DspChainSettings) -> Self {
+        let settings = settings.normalized();
+        Self { slots: std::array::from_fn(|i| settings.slots[i]), bypass_all: settings.bypass_all }
+    }
+}
+
+pub(crate) struct DspChain {
+    layout: Mutex<ChainLayout>,
+    version: AtomicU64,
+}
+impl Default for DspChain { fn default() -> Self { Self { layout: Mutex::new(DspChainSettings::default().into()), version: AtomicU64::new(0) } } }
+impl DspChain {
+    pub(crate) fn set(&self, settings: DspChainSettings) {
+        if let Ok(mut l) = self.layout.lock() { *l = settings.into(); }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    // None while the UI is writing it; try again on the next frame
+    fn try_layout(&self) -> Option<ChainLayout> {
+        self.layout.try_lock().ok().map(|l| *l)
+    }
+}
+
+// Pre-amp gain in dB, read live by the gain stage
+pub(crate) struct Preamp {
+    gain_db_bits: AtomicU32,
+}
+impl Default for Preamp { fn default() -> Self { Self { gain_db_bits: AtomicU32::new(0f32.to_bits()) } } }
+impl Preamp {
+    pub(crate) fn set_gain_db(&self, gain_db: f32) {
+        self.gain_db_bits.store(gain_db.to_bits(), Ordering::Relaxed);
+    }
+    fn gain_db(&self) -> f32 {
+        f32::from_bits(self.gain_db_bits.load(Ordering::Relaxed))
+    }
+}
+
+pub(crate) const PREAMP_RANGE: std::ops::RangeInclusive<f32> = -12.0..=12.0;
+
+struct GainProcessor {
+    shared: Arc<Preamp>,
+    gain_db: f32,
+    gain: f32,
+}
+
+impl GainProcessor {
+    fn new(shared: Arc<Preamp>) -> Self {
+        let gain_db = shared.gain_db();
+        Self { shared, gain_db, gain: 10f32.powf(gain_db / 20.0) }
+    }
+}
+
+impl DspProcessor for GainProcessor {
+    fn configure(&mut self, _channels: usize, _sample_rate: u32) {}
+    fn update(&mut self) {
+        let gain_db = self.shared.gain_db();
+        if gain_db != self.gain_db {
+            self.gain_db = gain_db;
+            self.gain = 10f32.powf(gain_db / 20.0);
+        }
+    }
+    fn process(&mut self, frame: &mut [f32]) {
+        frame.iter_mut().for_each(|x| *x *= self.gain);
+    }
+}
+
+// Registry: shared settings for every effect, and the factory that builds processors
+#[derive(Clone, Default)]
+pub(crate) struct DspEffects {
+    pub(crate) chain: Arc<DspChain>,
+    pub(crate) preamp: Arc<Preamp>,
+    pub(crate) eq: Arc<Equalizer>,
+    pub(crate) compressor: Arc<Compressor>,
+    pub(crate) crossfeed: Arc<Crossfeed>,
+    pub(crate) channel_mix: Arc<ChannelMix>,
+    pub(crate) reverb: Arc<Reverb>,
+}
+
+impl DspEffects {
+    fn create(&self, kind: EffectKind) -> Box<dyn DspProcessor> {
+        match kind {
+            EffectKind::Gain => Box::new(GainProcessor::new(self.preamp.clone())),
+            EffectKind::Equalizer => Box::new(EqProcessor::new(self.eq.clone())),
+            EffectKind::Compressor => Box::new(CompressorProcessor::new(self.compressor.clone())),
+            EffectKind::Crossfeed => Box::new(CrossfeedProcessor::new(self.crossfeed.clone())),
+            EffectKind::Balance => Box::new(ChannelMixProcessor::new(self.channel_mix.clone())),
+            EffectKind::Convolution => Box::new(ConvolutionProcessor::new(self.reverb.clone())),
+        }
+    }
+}
+
+struct ChainStage {
+    kind: EffectKind,
+    enabled: bool,
+    bypass: bool,
+    processor: Box<dyn DspProcessor>,
+}
+
+// Runs the configured effects over the decoded stream.
+struct DspSource<S: rodio::Source> {
+    inner: S,
+    effects: DspEffects,
+    // Receives a mono mixdown of the processed signal for the analyzer
+    tap: Arc<AudioTap>,
+    // Peak/RMS levels per channel for the meters
+    meter: Arc<LevelMeter>,
+    meter_writer: MeterWriter,
+    last_version: u64,
+    stages: Vec<ChainStage>,
+    bypass_all: bool,
+    channels: usize,
+    sample_rate: u32,
+    frame: Vec<f32>,
+    out_channel: usize,
+    // Small fade-in to mask discontinuity on (re)start
+    fade_len: u32,
+    fade_idx: u32,
+}
+
+impl<S: rodio::Source<Item = f32>> DspSource<S> {
+    fn new(inner: S, effects: DspEffects, tap: Arc<AudioTap>, meter: Arc<LevelMeter>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate();
+        tap.set_sample_rate(sample_rate);
+        let meter_writer = MeterWriter::new(&meter, channels, sample_rate);
+        let mut me = Self {
+            inner,
+            effects,
+            tap,
+            meter,
+            meter_writer,
+            // Never a real version, so a failed first read is retried
+            last_version: u64::MAX,
+            stages: Vec::new(),
+            bypass_all: false,
+            channels,
+            sample_rate,
+            frame: Vec::with_capacity(channels.max(MAX_CHANNELS)),
+            out_channel: 0,
+            fade_len: ((sample_rate as f32 * 0.005).ceil() as u32).max(1),
+            fade_idx: 0,
+        };
+        me.frame.resize(channels, 0.0);
+        // Built here, on the thread that starts playback, not in the audio callback
+        me.stages = EffectKind::ALL
+            .iter()
+            .map(|&kind| {
+                let mut processor = me.effects.create(kind);
+                processor.configure(channels, sample_rate);
+                ChainStage { kind, enabled: false, bypass: false, processor }
+            })
+            .collect();
+        me.rebuild();
+        me
+    }
+
+    // Reorder the stages and set their flags from the chain settings. Every
+    // effect has its processor from the start, so this runs on the audio
+    // thread without allocating; effects that stay on keep their filter state.
+    fn rebuild(&mut self) {
+        let version = self.effects.chain.version.load(Ordering::Relaxed);
+        let Some(layout) = self.effects.chain.try_layout() else { return };
+        self.last_version = version;
+        for stage in &mut self.stages {
+            let Some(slot) = layout.slots.iter().find(|s| s.kind == stage.kind) else { continue };
+            if slot.enabled && !stage.enabled {
+                stage.processor.reset();
+            }
+            stage.enabled = slot.enabled;
+            stage.bypass = slot.bypass;
+        }
+        self.stages.sort_unstable_by_key(|stage| layout.slots.iter().position(|s| s.kind == stage.kind));
+        self.bypass_all = layout.bypass_all;
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> Iterator for DspSource<S> {
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.out_channel == 0 {
+            if self.effects.chain.version.load(Ordering::Relaxed) != self.last_version {
+                self.rebuild();
+            }
+            // Follow format changes mid-stream (e.g. chained files with different
+            // layouts). Buffers here and in the stages are sized for up to
+            // `MAX_CHANNELS` up front, so this only re-slices them; a wider
+            // layout would grow them once. `MeterWriter` is plain arrays.
+            let (channels, sample_rate) = (self.inner.channels().max(1) as usize, self.inner.sample_rate());
+            if channels != self.channels || sample_rate != self.sample_rate {
+                self.channels = channels;
+                self.sample_rate = sample_rate;
+                self.frame.clear();
+                self.frame.resize(channels, 0.0);
+                self.tap.set_sample_rate(sample_rate);
+                self.meter_writer = MeterWriter::new(&self.meter, channels, sample_rate);
+                for stage in &mut self.stages {
+                    stage.processor.configure(channels, sample_rate);
+                }
+            }
+            // Read a whole frame; a trailing partial frame is padded with silence
+            self.frame[0] = self.inner.next()?;
+            for c in 1..self.channels {
+                self.frame[c] = self.inner.next().unwrap_or(0.0);
+            }
+            for stage in self.stages.iter_mut().filter(|s| s.enabled) {
+                stage.processor.update();
+                if !stage.bypass && !self.bypass_all {
+                    stage.processor.process(&mut self.frame);
+                }
+            }
+            // Apply fade-in ramp
+            if self.fade_idx < self.fade_len {
+                let t = self.fade_idx as f32 / self.fade_len as f32;
+                self.frame.iter_mut().for_each(|x| *x *= t);
+                self.fade_idx += 1;
+            }
+            self.tap.push(self.frame.iter().sum::<f32>() / self.channels as f32);
+            self.meter_writer.process(&self.meter, &self.frame);
+        }
+        let x = self.frame[self.out_channel];
+        self.out_channel = (self.out_channel + 1) % self.channels;
+        Some(x)
+    }
+}
+
+impl<S: rodio::Source<Item = f32>> rodio::Source for DspSource<S> {
+    fn channels(&self) -> u16 { self.inner.channels() }
+    fn sample_rate(&self) -> u32 { self.inner.sample_rate() }
+    fn current_span_len(&self) -> Option<usize> { self.inner.current_span_len() }
+    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
+}
+
+// ===== Convolution (impulse response) =====
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct ReverbSettings {
+    #[serde(with = "opt_path")]
+    pub(crate) ir_path: Option<PathBuf>,
+    // 0.0 = dry only, 1.0 = convolved only
+    pub(crate) mix: f32,
+    // Scale the response to unit energy
+    pub(crate) normalize: bool,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        Self { ir_path: None, mix: 0.3, normalize: true }
+    }
+}
+
+// Settings (versioned like `Equalizer`) plus the decoded response, which has
+// its own version because swapping it means rebuilding the convolvers.
+pub(crate) struct Reverb {
+    settings: Mutex<ReverbSettings>,
+    version: AtomicU64,
+    ir: Mutex<Option<Arc<ImpulseResponse>>>,
+    ir_version: AtomicU64,
+    // Convolution stages waiting for (or holding) convolvers from the worker
+    slots: Mutex<Vec<Weak<ConvolverSlot>>>,
+    // Convolvers replaced on the audio thread, for the worker to free. The
+    // worker keeps spare capacity so handing a set over doesn't allocate.
+    retired: Mutex<Vec<Vec<Convolver>>>,
+    worker: OnceLock<std::thread::Thread>,
+}
+impl Default for Reverb {
+    fn default() -> Self {
+        Self {
+            settings: Mutex::new(ReverbSettings::default()),
+            version: AtomicU64::new(0),
+            ir: Mutex::new(None),
+            ir_version: AtomicU64::new(0),
+            slots: Mutex::new(Vec::new()),
+            retired: Mutex::new(Vec::with_capacity(RETIRED_SPARE)),
+            worker: OnceLock::new(),
+        }
+    }
+}
+impl Drop for Reverb {
+    fn drop(&mut self) {
+        // Let the worker notice it's no longer needed
+        if let Some(worker) = self.worker.get() {
+            worker.unpark();
+        }
+    }
+}
+impl Reverb {
+    pub(crate) fn set(&self, settings: ReverbSettings) {
+        if let Ok(mut s) = self.settings.lock() { *s = settings; }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    fn snapshot(&self) -> ReverbSettings {
+        self.settings.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+    pub(crate) fn set_impulse_response(&self, ir: Option<Arc<ImpulseResponse>>) {
+        if let Ok(mut slot) = self.ir.lock() { *slot = ir; }
+        self.ir_version.fetch_add(1, Ordering::Relaxed);
+    }
+    fn impulse_response(&self) -> Option<Arc<ImpulseResponse>> {
+        self.ir.lock().ok().and_then(|ir| ir.clone())
+    }
+    // Queue a replaced set for the worker without waiting or allocating; hands
+    // it back if the queue is busy or full so the caller can retry later.
+    fn retire(&self, convolvers: Vec<Convolver>) -> Result<(), Vec<Convolver>> {
+        if convolvers.capacity() == 0 {
+            return Ok(());
+        }
+        match self.retired.try_lock() {
+            Ok(mut retired) if retired.len() < retired.capacity() => retired.push(convolvers),
+            _ => return Err(convolvers),
+        }
+        if let Some(worker) = self.worker.get() {
+            worker.unpark();
+        }
+        Ok(())
+    }
+}
+
+// Retired sets the queue holds before the audio thread has to wait for the worker
+const RETIRED_SPARE: usize = 8;
+
+// Mailbox between one convolution stage and the worker. The audio thread
+// only stores atomics and uses `try_lock`, so it never waits or allocates.
+#[derive(Default)]
+struct ConvolverSlot {
+    // Latest request and the format it's for, packed as channels << 32 | rate
+    request: AtomicU64,
+    format: AtomicU64,
+    // Last request the worker answered; only the worker touches it
+    answered: AtomicU64,
+    // Convolvers for a request
+    ready: Mutex<Option<(u64, Vec<Convolver>)>>,
+}
+
+// One long-lived thread per `Reverb` resamples/normalises the response and
+// transforms its partitions for every stage that asks.
+fn run_convolver_worker(reverb: Weak<Reverb>) {
+    let fft = Arc::new(crate::fft::Fft::new(2 * convolution::BLOCK));
+    loop {
+        let Some(reverb) = reverb.upgrade() else { return };
+        // Swap in a fresh queue and free the retired sets outside the lock
+        let mut retired = Vec::with_capacity(RETIRED_SPARE);
+        if let Ok(mut queue) = reverb.retired.lock() {
+            std::mem::swap(&mut *queue, &mut retired);
+        }
+        drop(retired);
+        let slots: Vec<Arc<ConvolverSlot>> = match reverb.slots.lock() {
+            Ok(mut slots) => {
+                slots.retain(|s| s.strong_count() > 0);
+                slots.iter().filter_map(Weak::upgrade).collect()
+            }
+            Err(_) => return,
+        };
+        for slot in slots {
+            let request = slot.request.load(Ordering::Acquire);
+            if request == slot.answered.load(Ordering::Relaxed) {
+                continue;
+            }
+            let format = slot.format.load(Ordering::Relaxed);
+            let (channels, rate) = ((format >> 32) as usize, format as u32);
+            let convolvers = match reverb.impulse_response() {
+                Some(ir) => {
+                    let mut ir = ir.resampled(rate);
+                    if reverb.snapshot().normalize {
+                        ir = ir.normalized();
+                    }
+                    (0..channels).map(|c| Convolver::new(ir.channel(c), fft.clone())).collect()
+                }
+                None => Vec::new(),
+            };
+            if let Ok(mut ready) = slot.ready.lock() {
+                *ready = Some((request, convolvers));
+            }
+            slot.answered.store(request, Ordering::Relaxed);
+        }
+        drop(reverb);
+        // Woken by new requests, retired convolvers and the Reverb going away
+        std::thread::park();
+    }
+}
+
+// Block-based stage: always delays by `convolution::BLOCK` frames, so the
+// timing doesn't jump when a response finishes loading.
+struct ConvolutionProcessor {
+    shared: Arc<Reverb>,
+    last_version: u64,
+    last_ir_version: u64,
+    mix: f32,
+    normalize: bool,
+    channels: usize,
+    sample_rate: u32,
+    convolvers: Vec<Convolver>,
+    // A replaced set the retired queue had no room for yet; retried every block
+    retiring: Option<Vec<Convolver>>,
+    // False from a request until its convolvers arrive; the dry signal passes meanwhile
+    convolvers_current: bool,
+    // Set by `configure`; the request waits for `update` so a disabled stage builds nothing
+    needs_convolvers: bool,
+    slot: Arc<ConvolverSlot>,
+    worker: std::thread::Thread,
+    generation: u64,
+    // Per channel: samples being collected, and output for the previous block.
+    // At least `channels` long; spare buffers are kept for later formats.
+    input: Vec<Vec<f32>>,
+    output: Vec<Vec<f32>>,
+    wet: Vec<f32>,
+    pos: usize,
+}
+
+impl ConvolutionProcessor {
+    fn new(shared: Arc<Reverb>) -> Self {
+        let settings = shared.snapshot();
+        let slot = Arc::new(ConvolverSlot::default());
+        if let Ok(mut slots) = shared.slots.lock() {
+            slots.push(Arc::downgrade(&slot));
+        }
+        let worker = shared
+            .worker
+            .get_or_init(|| {
+                let reverb = Arc::downgrade(&shared);
+                std::thread::spawn(move || run_convolver_worker(reverb)).thread().clone()
+            })
+            .clone();
+        Self {
+            last_version: shared.version.load(Ordering::Relaxed),
+            last_ir_version: shared.ir_version.load(Ordering::Relaxed),
+            shared,
+            mix: settings.mix,
+            normalize: settings.normalize,
+            channels: 0,
+            sample_rate: 0,
+            convolvers: Vec::new(),
+            retiring: None,
+            convolvers_current: false,
+            needs_convolvers: false,
+            slot,
+            worker,
+            generation: 0,
+            input: vec![vec![0.0; convolution::BLOCK]; MAX_CHANNELS],
+            output: vec![vec![0.0; convolution::BLOCK]; MAX_CHANNELS],
+            wet: vec![0.0; convolution::BLOCK],
+            pos: 0,
+        }
+    }
+
+    // Ask the worker for convolvers matching the current response and format;
+    // the dry signal passes until they're ready.
+    fn request_convolvers(&mut self) {
+        self.generation += 1;
+        self.convolvers_current = false;
+        self.slot.format.store((self.channels as u64) << 32 | self.sample_rate as u64, Ordering::Relaxed);
+        self.slot.request.store(self.generation, Ordering::Release);
+        self.worker.unpark();
+    }
+
+    // Swap in convolvers prepared by the worker, never waiting for the lock,
+    // and hand the old ones back to be freed there. A new set is only taken
+    // once the previous one has been handed over.
+    fn pick_up_convolvers(&mut self) {
+        if let Some(old) = self.retiring.take() {
+            self.retiring = self.shared.retire(old).err();
+            if self.retiring.is_some() {
+                return;
+            }
+        }
+        let Ok(mut ready) = self.slot.ready.try_lock() else { return };
+        let Some((_, convolvers)) = ready.take_if(|(g, _)| *g == self.generation) else { return };
+        drop(ready);
+        let old = std::mem::replace(&mut self.convolvers, convolvers);
+        self.convolvers_current = true;
+        self.retiring = self.shared.retire(old).err();
+    }
+
+    fn finish_block(&mut self) {
+        if !self.convolvers_current || self.retiring.is_some() {
+            self.pick_up_convolvers();
+        }
+        let (dry_gain, wet_gain) = (1.0 - self.mix, self.mix);
+        for c in 0..self.channels {
+            match self.convolvers.get_mut(c).filter(|_| self.convolvers_current) {
+                Some(conv) => {
+                    self.wet.copy_from_slice(&self.input[c]);
+                    conv.process_block(&mut self.wet);
+                    for ((out, dry), wet) in self.output[c].iter_mut().zip(&self.input[c]).zip(&self.wet) {
+                        *out = dry * dry_gain + wet * wet_gain;
+                    }
+                }
+                None => self.output[c].copy_from_slice(&self.input[c]),
+            }
+        }
+        self.pos = 0;
+    }
+}
+
+impl DspProcessor for ConvolutionProcessor {
+    fn configure(&mut self, channels: usize, sample_rate: u32) {
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+        for buffers in [&mut self.input, &mut self.output] {
+            if buffers.len() < channels {
+                buffers.resize(channels, vec![0.0; convolution::BLOCK]);
+            }
+        }
+        self.input.iter_mut().chain(&mut self.output).flatten().for_each(|x| *x = 0.0);
+        self.pos = 0;
+        self.convolvers_current = false;
+        self.needs_convolvers = true;
+    }
+    fn update(&mut self) {
+        if std::mem::take(&mut self.needs_convolvers) {
+            self.request_convolvers();
+        }
+        let current_version = self.shared.version.load(Ordering::Relaxed);
+        let current_ir_version = self.shared.ir_version.load(Ordering::Relaxed);
+        if current_version == self.last_version && current_ir_version == self.last_ir_version {
+            return;
+        }
+        let settings = self.shared.snapshot();
+        self.mix = settings.mix.clamp(0.0, 1.0);
+        let rebuild = current_ir_version != self.last_ir_version || settings.normalize != self.normalize;
+        self.normalize = settings.normalize;
+        self.last_version = current_version;
+        self.last_ir_version = current_ir_version;
+        if rebuild {
+            self.request_convolvers();
+        }
+    }
+    fn process(&mut self, frame: &mut [f32]) {
+        for (c, x) in frame.iter_mut().enumerate().take(self.channels) {
+            self.input[c][self.pos] = *x;
+            *x = self.output[c][self.pos];
+        }
+        self.pos += 1;
+        if self.pos == convolution::BLOCK {
+            self.finish_block();
+        }
+    }
+    fn reset(&mut self) {
+        self.input.iter_mut().chain(&mut self.output).flatten().for_each(|x| *x = 0.0);
+        self.pos = 0;
+        self.convolvers.iter_mut().for_each(Convolver::reset);
+    }
+}
+
+impl Drop for ConvolutionProcessor {
+    // Stages are dropped on the audio thread when the chain is rebuilt, so
+    // their convolvers go to the worker too. The worker only holds the queue
+    // long enough to swap it, so waiting for the lock here is brief.
+    fn drop(&mut self) {
+        let unclaimed = self.slot.ready.try_lock().ok().and_then(|mut r| r.take()).map(|(_, c)| c);
+        let sets = [Some(std::mem::take(&mut self.convolvers)), self.retiring.take(), unclaimed];
+        if let Ok(mut retired) = self.shared.retired.lock() {
+            retired.extend(sets.into_iter().flatten().filter(|c| c.capacity() > 0));
+        }
+        self.worker.unpark();
+    }
+}
+
+pub(crate) mod opt_path {
+    use super::*;
+    use serde::{Serializer, Deserializer};
+
+    pub fn serialize<S>(val: &Option<PathBuf>, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match val {
+            Some(p) => s.serialize_some(&p.to_string_lossy()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<Option<PathBuf>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+    let opt: Option<String> = <Option<String> as serde::Deserialize>::deserialize(d)?;
+        Ok(opt.map(PathBuf::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(d: Duration) -> f64 {
+        d.as_secs_f64()
+    }
+
+    // A clock that has been running for `played` since it started at `from`
+    fn running_clock(from: Duration, played: Duration) -> TrackClock {
+        let mut clock = TrackClock::default();
+        clock.restart(from, 0);
+        clock.start = Instant::now().checked_sub(played);
+        clock
+    }
+
+    #[test]
+    fn gapless_handoff_rebases_position() {
+        // 200 s into a CUE file; the next track starts 180 s after the current one
+        let mut clock = running_clock(Duration::ZERO, Duration::from_secs(200));
+        let shift = Duration::from_secs(180);
+        let position = clock.position(1.0, 0).saturating_sub(shift);
+        clock.rebase(position, 0);
+        assert!((secs(clock.position(1.0, 0)) - 20.0).abs() < 0.5);
+        // and keeps running from there
+        clock.start = clock.start.and_then(|s| s.checked_sub(Duration::from_secs(5)));
+        assert!((secs(clock.position(1.0, 0)) - 25.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn rebase_keeps_a_paused_clock_paused() {
+        let mut clock = running_clock(Duration::ZERO, Duration::from_secs(90));
+        clock.pause(1.0, 0);
+        clock.rebase(Duration::from_secs(30), 0);
+        assert_eq!(clock.position(1.0, 0), Duration::from_secs(30));
+        assert!(clock.start.is_none());
+        clock.resume(0);
+        assert!((secs(clock.position(1.0, 0)) - 30.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn rebase_forgets_earlier_loop_rewinds() {
+        // The A-B loop jumped back 10 s before the rebase
+        let mut clock = running_clock(Duration::ZERO, Duration::from_secs(60));
+        assert!((secs(clock.position(1.0, 10_000_000)) - 50.0).abs() < 0.5);
+        clock.rebase(Duration::from_secs(5), 10_000_000);
+        assert!((secs(clock.position(1.0, 10_000_000)) - 5.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn convolution_stage_gets_convolvers_from_the_worker() {
+        let reverb = Arc::new(Reverb::default());
+        reverb.set(ReverbSettings { mix: 1.0, normalize: false, ..ReverbSettings::default() });
+        reverb.set_impulse_response(Some(Arc::new(ImpulseResponse { sample_rate: 48_000, channels: vec![vec![0.5]] })));
+        let mut stage = ConvolutionProcessor::new(reverb.clone());
+        stage.configure(2, 48_000);
+        stage.update();
+        // Dry until the worker delivers, then the response halves the signal
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut out = [1.0f32, 1.0];
+        while out[0] != 0.5 && Instant::now() < deadline {
+            for _ in 0..convolution::BLOCK {
+                out = [1.0, 1.0];
+                stage.process(&mut out);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!((out[0] - 0.5).abs() < 1e-4 && (out[1] - 0.5).abs() < 1e-4, "{out:?}");
+
+        // A new response is picked up the same way
+        reverb.set_impulse_response(Some(Arc::new(ImpulseResponse { sample_rate: 48_000, channels: vec![vec![0.25]] })));
+        stage.update();
+        while (out[0] - 0.25).abs() > 1e-4 && Instant::now() < deadline {
+            for _ in 0..convolution::BLOCK {
+                out = [1.0, 1.0];
+                stage.process(&mut out);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!((out[0] - 0.25).abs() < 1e-4, "{out:?}");
+    }
+
+    #[test]
+    fn format_changes_reuse_the_stage_buffers() {
+        let mut mix = ChannelMixProcessor::new(Arc::new(ChannelMix::default()));
+        let mut reverb = ConvolutionProcessor::new(Arc::new(Reverb::default()));
+        mix.configure(2, 44_100);
+        reverb.configure(2, 44_100);
+        let (targets, gains, input) = (mix.targets.as_ptr(), mix.gains.as_ptr(), reverb.input.as_ptr());
+        for (channels, rate) in [(6, 96_000), (1, 48_000), (8, 44_100)] {
+            mix.configure(channels, rate);
+            reverb.configure(channels, rate);
+            assert_eq!(mix.targets.len(), channels);
+            assert!(reverb.input.len() >= channels && reverb.output.len() >= channels);
+        }
+        assert_eq!((mix.targets.as_ptr(), mix.gains.as_ptr(), reverb.input.as_ptr()), (targets, gains, input));
+    }
+
+    #[test]
+    fn replaced_convolvers_wait_for_the_worker_instead_of_being_dropped() {
+        let reverb = Arc::new(Reverb::default());
+        reverb.set(ReverbSettings { mix: 1.0, normalize: false, ..ReverbSettings::default() });
+        reverb.set_impulse_response(Some(Arc::new(ImpulseResponse { sample_rate: 48_000, channels: vec![vec![0.5]] })));
+        let mut stage = ConvolutionProcessor::new(reverb.clone());
+        stage.configure(2, 48_000);
+        stage.update();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let wait_until_ready = |stage: &ConvolutionProcessor| {
+            while stage.slot.ready.lock().unwrap().is_none() {
+                assert!(Instant::now() < deadline, "worker never answered");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+        wait_until_ready(&stage);
+        let mut out = [1.0f32, 1.0];
+        for _ in 0..convolution::BLOCK {
+            stage.process(&mut out);
+        }
+        assert!(stage.convolvers_current && stage.retiring.is_none());
+
+        // With the queue busy, the replaced set is kept rather than freed here
+        reverb.set_impulse_response(Some(Arc::new(ImpulseResponse { sample_rate: 48_000, channels: vec![vec![0.25]] })));
+        stage.update();
+        wait_until_ready(&stage);
+        let queue = reverb.retired.lock().unwrap();
+        for _ in 0..convolution::BLOCK {
+            stage.process(&mut out);
+        }
+        assert!(stage.convolvers_current);
+        assert_eq!(stage.retiring.as_ref().map(Vec::len), Some(2));
+        drop(queue);
+
+        // and handed over on the next block
+        for _ in 0..convolution::BLOCK {
+            stage.process(&mut out);
+        }
+        assert!(stage.retiring.is_none());
+    }
+
+    #[test]
+    fn chain_changes_reorder_and_toggle_the_prebuilt_stages() {
+        let effects = DspEffects::default();
+        let samples = rodio::buffer::SamplesBuffer::new(2, 48_000, vec![0.25f32; 4_096]);
+        let mut source = DspSource::new(samples, effects.clone(), Arc::new(AudioTap::default()), Arc::new(LevelMeter::default()));
+        assert_eq!(source.stages.len(), EffectKind::ALL.len());
+        source.next();
+
+        let mut settings = DspChainSettings::default();
+        settings.slots.reverse();
+        if let Some(slot) = settings.slot_mut(EffectKind::Crossfeed) {
+            slot.enabled = true;
+        }
+        effects.chain.set(settings.clone());
+        source.next();
+        source.next();
+        let order: Vec<_> = source.stages.iter().map(|s| s.kind).collect();
+        assert_eq!(order, settings.slots.iter().map(|s| s.kind).collect::<Vec<_>>());
+        let enabled = |source: &DspSource<_>, kind| source.stages.iter().any(|s| s.kind == kind && s.enabled);
+        assert!(enabled(&source, EffectKind::Crossfeed));
+        assert!(!enabled(&source, EffectKind::Compressor));
+    }
+
+    fn compressor(settings: CompressorSettings) -> CompressorProcessor {
+        let shared = Arc::new(Compressor::default());
+        shared.set(settings);
+        let mut processor = CompressorProcessor::new(shared);
+        processor.configure(1, 48_000);
+        processor
+    }
+
+    #[test]
+    fn make_up_gain_stays_under_the_ceiling() {
+        let settings = CompressorSettings { threshold_db: -20.0, ratio: 2.0, attack_ms: 50.0, release_ms: 200.0, makeup_db: 24.0 };
+        let mut processor = compressor(settings);
+        let ceiling = 10f32.powf(COMPRESSOR_CEILING_DB / 20.0);
+        for i in 0..48_000 {
+            let mut frame = [(i as f32 * 0.05).sin() * 0.9];
+            processor.process(&mut frame);
+            assert!(frame[0].abs() <= ceiling + 1e-4, "{} at {i}", frame[0]);
+        }
+    }
+
+    #[test]
+    fn top_ratio_limits_at_the_threshold() {
+        let settings = CompressorSettings { threshold_db: -12.0, ratio: COMPRESSOR_LIMIT_RATIO, attack_ms: 1.0, release_ms: 100.0, makeup_db: 0.0 };
+        let mut processor = compressor(settings);
+        let mut frame = [0.0f32];
+        for _ in 0..48_000 {
+            frame = [0.5];
+            processor.process(&mut frame);
+        }
+        // A steady -6 dB input comes out at the -12 dB threshold
+        let out_db = 20.0 * frame[0].log10();
+        assert!((out_db + 12.0).abs() < 0.1, "{out_db}");
+    }
+
+    #[test]
+    fn span_events_are_sent_from_the_event_thread() {
+        let events = EventBus::default();
+        let rx = events.subscribe();
+        let span = TrackSpan::new(events);
+        span.arm(Duration::from_secs(10), Some(Duration::from_secs(20)));
+        span.position(12_000_000);
+        let wait = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(wait), Ok(EngineEvent::PositionChanged(Duration::from_secs(2))));
+        span.fire();
+        span.fire();
+        assert_eq!(rx.recv_timeout(wait), Ok(EngineEvent::TrackEnded));
+        // One TrackEnded per track; the thread ends with the span
+        drop(span);
+        assert_eq!(rx.recv_timeout(wait), Err(mpsc::RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn stalled_subscribers_miss_events_instead_of_queuing_forever() {
+        let events = EventBus::default();
+        let rx = events.subscribe();
+        for i in 0..EVENT_QUEUE as u64 + 10 {
+            events.emit(EngineEvent::PositionChanged(Duration::from_millis(i)));
+        }
+        assert_eq!(rx.try_iter().count(), EVENT_QUEUE);
+        // Still subscribed once it catches up
+        events.emit(EngineEvent::TrackEnded);
+        assert_eq!(rx.try_recv(), Ok(EngineEvent::TrackEnded));
+    }
+
+    #[test]
+    fn position_follows_playback_speed() {
+        let clock = running_clock(Duration::from_secs(10), Duration::from_secs(20));
+        assert!((secs(clock.position(1.5, 0)) - 40.0).abs() < 0.5);
+    }
+}
@@ -0,0 +1,139 @@
+// Small in-place radix-2 FFT for the DSP stages (convolution, analysis).
+// Sizes must be powers of two; twiddles and the bit-reversal table are
+// computed once per size so transforms don't allocate.
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Complex {
+    pub(crate) re: f32,
+    pub(crate) im: f32,
+}
+
+impl Complex {
+    pub(crate) const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+
+    pub(crate) fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    pub(crate) fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub(crate) fn conj(self) -> Self {
+        Self { re: self.re, im: -self.im }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex { re: self.re + o.re, im: self.im + o.im }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex { re: self.re - o.re, im: self.im - o.im }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex { re: self.re * o.re - self.im * o.im, im: self.re * o.im + self.im * o.re }
+    }
+}
+
+pub(crate) struct Fft {
+    n: usize,
+    // e^(-2πik/n) for k in 0..n/2
+    twiddles: Vec<Complex>,
+    bit_reverse: Vec<u32>,
+}
+
+impl Fft {
+    pub(crate) fn new(n: usize) -> Self {
+        assert!(n.is_power_of_two() && n >= 2, "FFT size must be a power of two");
+        let twiddles = (0..n / 2)
+            .map(|k| {
+                let angle = -2.0 * std::f64::consts::PI * k as f64 / n as f64;
+                Complex::new(angle.cos() as f32, angle.sin() as f32)
+            })
+            .collect();
+        let bits = n.trailing_zeros();
+        let bit_reverse = (0..n as u32).map(|i| i.reverse_bits() >> (32 - bits)).collect();
+        Self { n, twiddles, bit_reverse }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.n
+    }
+
+    pub(crate) fn forward(&self, buf: &mut [Complex]) {
+        self.transform(buf);
+    }
+
+    // Inverse transform, scaled by 1/n so forward + inverse round-trips
+    pub(crate) fn inverse(&self, buf: &mut [Complex]) {
+        buf.iter_mut().for_each(|c| *c = c.conj());
+        self.transform(buf);
+        let scale = 1.0 / self.n as f32;
+        buf.iter_mut().for_each(|c| *c = Complex::new(c.re * scale, -c.im * scale));
+    }
+
+    fn transform(&self, buf: &mut [Complex]) {
+        assert_eq!(buf.len(), self.n);
+        for (i, &j) in self.bit_reverse.iter().enumerate() {
+            let j = j as usize;
+            if i < j {
+                buf.swap(i, j);
+            }
+        }
+        let mut size = 2;
+        while size <= self.n {
+            let half = size / 2;
+            let stride = self.n / size;
+            for start in (0..self.n).step_by(size) {
+                for k in 0..half {
+                    let t = buf[start + k + half] * self.twiddles[k * stride];
+                    let u = buf[start + k];
+                    buf[start + k] = u + t;
+                    buf[start + k + half] = u - t;
+                }
+            }
+            size *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_then_inverse_round_trips() {
+        let fft = Fft::new(64);
+        let input: Vec<Complex> = (0..64).map(|i| Complex::new((i as f32 * 0.37).sin(), (i as f32 * 0.11).cos())).collect();
+        let mut buf = input.clone();
+        fft.forward(&mut buf);
+        fft.inverse(&mut buf);
+        for (a, b) in buf.iter().zip(&input) {
+            assert!((a.re - b.re).abs() < 1e-5 && (a.im - b.im).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn forward_finds_a_pure_tone() {
+        let n = 32;
+        let fft = Fft::new(n);
+        let mut buf: Vec<Complex> = (0..n).map(|i| Complex::new((2.0 * std::f32::consts::PI * 5.0 * i as f32 / n as f32).cos(), 0.0)).collect();
+        fft.forward(&mut buf);
+        // A cosine puts half its energy in bin 5 and half in bin n - 5
+        for (k, c) in buf.iter().enumerate() {
+            let expected = if k == 5 || k == n - 5 { n as f32 / 2.0 } else { 0.0 };
+            assert!((c.norm_sqr().sqrt() - expected).abs() < 1e-3, "bin {k}");
+        }
+    }
+}
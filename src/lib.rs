@@ -1,3 +1,22 @@
 pub mod app;
+mod analysis;
+mod artwork;
+mod cli;
+mod convolution;
+mod cue;
+pub mod engine;
+mod fft;
+mod library;
+mod loudness;
+mod lyrics;
+mod meter;
+mod mpris;
+mod query;
+mod remote;
+mod silence;
+mod spectrum;
+mod tempo_key;
+mod waveform;
 
 pub use app::run as run_app;
+pub use cli::run as run_cli;
@@ -0,0 +1,88 @@
+// Library cache: per-file analysis results (loudness, tempo, key) kept in one JSON
+// file in the cache directory. Entries remember the file's size and mtime and
+// are treated as missing once the file changes.
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::loudness::{AlbumLoudness, TrackLoudness};
+use crate::tempo_key::TempoKey;
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) struct LibraryCache {
+    // Keyed by the file's path
+    entries: HashMap<String, LibraryEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct LibraryEntry {
+    size: u64,
+    modified: Option<u64>,
+    pub(crate) loudness: Option<TrackLoudness>,
+    // Album (= folder) loudness the track belongs to
+    pub(crate) album: Option<AlbumLoudness>,
+    pub(crate) tempo_key: Option<TempoKey>,
+}
+
+// Size and modification time (seconds since the epoch) of a file
+fn stamp(path: &Path) -> Option<(u64, Option<u64>)> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs());
+    Some((meta.len(), modified))
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+impl LibraryCache {
+    pub(crate) fn load() -> Self {
+        cache_path()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save(&self) {
+        let Some(path) = cache_path() else { return };
+        if let Ok(data) = serde_json::to_string(self) {
+            // Write then rename so a crash never leaves a truncated cache
+            let tmp = path.with_extension("json.tmp");
+            if fs::write(&tmp, data).is_ok() {
+                let _ = fs::rename(&tmp, &path);
+            }
+        }
+    }
+
+    // Entry for a file, if present and the file hasn't changed since
+    pub(crate) fn get(&self, path: &Path) -> Option<&LibraryEntry> {
+        let entry = self.entries.get(&key(path))?;
+        let (size, modified) = stamp(path)?;
+        (entry.size == size && entry.modified == modified).then_some(entry)
+    }
+
+    // Entry for a file, starting afresh if it's missing or stale
+    pub(crate) fn entry_mut(&mut self, path: &Path) -> &mut LibraryEntry {
+        let (size, modified) = stamp(path).unwrap_or_default();
+        let entry = self.entries.entry(key(path)).or_default();
+        if entry.size != size || entry.modified != modified {
+            *entry = LibraryEntry { size, modified, ..LibraryEntry::default() };
+        }
+        entry
+    }
+
+    // Re-stamp an entry after we changed the file ourselves (e.g. wrote tags)
+    // without touching the audio.
+    pub(crate) fn refresh_stamp(&mut self, path: &Path) {
+        if let (Some(entry), Some((size, modified))) = (self.entries.get_mut(&key(path)), stamp(path)) {
+            entry.size = size;
+            entry.modified = modified;
+        }
+    }
+}
+
+fn cache_path() -> Option<PathBuf> {
+    Some(crate::app::cache_dir("library")?.join("library.json"))
+}
@@ -0,0 +1,518 @@
+// Loudness measurement per EBU R128 (ITU-R BS.1770-4, with loudness range
+// from EBU Tech 3342), album aggregation and ReplayGain tag writing for FLAC.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// ReplayGain 2.0 reference level
+pub(crate) const REFERENCE_LUFS: f64 = -18.0;
+// Measurements are built from 100 ms steps: momentary blocks are 4 steps
+// (400 ms, 75% overlap) and short-term windows 30 steps (3 s)
+const STEP_SECS: f64 = 0.1;
+const BLOCK_STEPS: usize = 4;
+const SHORT_TERM_STEPS: usize = 30;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+const RANGE_RELATIVE_GATE_LU: f64 = -20.0;
+// Block loudness histogram kept per track for album aggregation
+const HISTOGRAM_STEP_LU: f64 = 0.1;
+const HISTOGRAM_BINS: usize = 1000;
+// FLAC metadata block types and limits
+const PADDING: u8 = 1;
+const VORBIS_COMMENT: u8 = 4;
+const MAX_BLOCK_LEN: usize = (1 << 24) - 1;
+// Padding left after a full rewrite, so later tag updates fit in place
+const REWRITE_PADDING: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct TrackLoudness {
+    // None when the whole track is below the absolute gate (silence)
+    pub(crate) integrated_lufs: Option<f64>,
+    pub(crate) range_lu: f64,
+    pub(crate) true_peak_dbtp: f64,
+    // (bin, count) of gated 400 ms block loudness, bins of HISTOGRAM_STEP_LU from -70 LUFS
+    histogram: Vec<(u16, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct AlbumLoudness {
+    pub(crate) integrated_lufs: Option<f64>,
+    pub(crate) true_peak_dbtp: f64,
+}
+
+impl TrackLoudness {
+    pub(crate) fn summary(&self) -> String {
+        let integrated = self.integrated_lufs.map_or("-inf".to_string(), |l| format!("{l:.1}"));
+        format!("{integrated} LUFS · LRA {:.1} LU · {:.1} dBTP", self.range_lu, self.true_peak_dbtp)
+    }
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+// Two-stage gating over (energy, weight) pairs: absolute gate, then a gate
+// relative to the mean of what passed.
+fn gated_loudness(blocks: impl Iterator<Item = (f64, f64)> + Clone, relative_lu: f64) -> Option<f64> {
+    let mean_above = |gate: f64| {
+        let (sum, count) = blocks
+            .clone()
+            .filter(|&(e, _)| e > 0.0 && loudness(e) > gate)
+            .fold((0.0, 0.0), |(s, n), (e, w)| (s + e * w, n + w));
+        (count > 0.0).then(|| sum / count)
+    };
+    let relative_gate = loudness(mean_above(ABSOLUTE_GATE_LUFS)?) + relative_lu;
+    mean_above(relative_gate).map(loudness)
+}
+
+fn histogram_bin(lufs: f64) -> Option<u16> {
+    let bin = ((lufs - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP_LU).floor();
+    (bin >= 0.0).then(|| (bin as usize).min(HISTOGRAM_BINS - 1) as u16)
+}
+
+fn histogram_energy(bin: u16) -> f64 {
+    energy(ABSOLUTE_GATE_LUFS + (bin as f64 + 0.5) * HISTOGRAM_STEP_LU)
+}
+
+// Album loudness: gating over the blocks of every track together.
+pub(crate) fn album_loudness(tracks: &[&TrackLoudness]) -> AlbumLoudness {
+    let mut merged: BTreeMap<u16, u32> = BTreeMap::new();
+    for track in tracks {
+        for &(bin, count) in &track.histogram {
+            *merged.entry(bin).or_default() += count;
+        }
+    }
+    let integrated_lufs = gated_loudness(merged.iter().map(|(&bin, &count)| (histogram_energy(bin), count as f64)), RELATIVE_GATE_LU);
+    let true_peak_dbtp = tracks.iter().map(|t| t.true_peak_dbtp).fold(f64::NEG_INFINITY, f64::max);
+    AlbumLoudness { integrated_lufs, true_peak_dbtp }
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self { b0: b[0] / a[0], b1: b[1] / a[0], b2: b[2] / a[0], a1: a[1] / a[0], a2: a[2] / a[0], z1: 0.0, z2: 0.0 }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+// BS.1770 K-weighting (high shelf + high pass), derived for any sample rate
+fn k_weighting(rate: f64) -> [Biquad; 2] {
+    use std::f64::consts::PI;
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = Biquad::new(
+        [vh + vb * k / q + k * k, 2.0 * (k * k - vh), vh - vb * k / q + k * k],
+        [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let high_pass = Biquad::new([1.0, -2.0, 1.0], [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k]);
+    [shelf, high_pass]
+}
+
+// Channel weights by layout: surrounds count +1.5 dB, LFE is ignored
+fn channel_weights(channels: usize) -> Vec<f64> {
+    match channels {
+        5 => vec![1.0, 1.0, 1.0, 1.41, 1.41],
+        6 => vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41],
+        n => vec![1.0; n],
+    }
+}
+
+// Peak of the signal oversampled 4x (2x from 96 kHz), per BS.1770 Annex 2.
+struct TruePeak {
+    factor: usize,
+    // `factor` phases of TRUE_PEAK_TAPS coefficients each
+    coeffs: Vec<f64>,
+    // Last TRUE_PEAK_TAPS samples per channel, newest first
+    history: Vec<[f64; TRUE_PEAK_TAPS]>,
+    peak: f64,
+}
+
+const TRUE_PEAK_TAPS: usize = 12;
+
+impl TruePeak {
+    fn new(channels: usize, rate: u32) -> Self {
+        let factor = match rate {
+            r if r < 96_000 => 4,
+            r if r < 192_000 => 2,
+            _ => 1,
+        };
+        // Hann-windowed sinc interpolator split into polyphase components
+        let len = factor * TRUE_PEAK_TAPS;
+        let centre = (len - 1) as f64 / 2.0;
+        let prototype: Vec<f64> = (0..len)
+            .map(|n| {
+                let t = (n as f64 - centre) / factor as f64;
+                let sinc = if t.abs() < 1e-9 { 1.0 } else { (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t) };
+                let w = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * (n as f64 + 0.5) / len as f64).cos();
+                sinc * w
+            })
+            .collect();
+        let coeffs = (0..factor).flat_map(|p| (0..TRUE_PEAK_TAPS).map(move |k| (p, k))).map(|(p, k)| prototype[p + k * factor]).collect();
+        Self { factor, coeffs, history: vec![[0.0; TRUE_PEAK_TAPS]; channels], peak: 0.0 }
+    }
+
+    fn push(&mut self, channel: usize, x: f64) {
+        let history = &mut self.history[channel];
+        history.copy_within(..TRUE_PEAK_TAPS - 1, 1);
+        history[0] = x;
+        if self.factor == 1 {
+            self.peak = self.peak.max(x.abs());
+            return;
+        }
+        for phase in self.coeffs.chunks_exact(TRUE_PEAK_TAPS) {
+            let y: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+            self.peak = self.peak.max(y.abs());
+        }
+    }
+}
+
+pub(crate) struct LoudnessMeter {
+    pub(crate) channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    step_len: usize,
+    step_pos: usize,
+    step_sum: f64,
+    // Weighted mean square of each complete 100 ms step
+    steps: Vec<f64>,
+    true_peak: TruePeak,
+}
+
+impl LoudnessMeter {
+    pub(crate) fn new(channels: usize, rate: u32) -> Self {
+        Self {
+            channels,
+            weights: channel_weights(channels),
+            filters: vec![k_weighting(rate as f64); channels],
+            step_len: ((rate as f64 * STEP_SECS).round() as usize).max(1),
+            step_pos: 0,
+            step_sum: 0.0,
+            steps: Vec::new(),
+            true_peak: TruePeak::new(channels, rate),
+        }
+    }
+
+    // Interleaved samples
+    pub(crate) fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (c, &x) in frame.iter().enumerate() {
+                let x = x as f64;
+                self.true_peak.push(c, x);
+                let [shelf, high_pass] = &mut self.filters[c];
+                let y = high_pass.process(shelf.process(x));
+                self.step_sum += self.weights[c] * y * y;
+            }
+            self.step_pos += 1;
+            if self.step_pos == self.step_len {
+                self.steps.push(self.step_sum / self.step_len as f64);
+                self.step_pos = 0;
+                self.step_sum = 0.0;
+            }
+        }
+    }
+
+    // Mean energy of each window of `len` steps, advancing one step at a time
+    fn windows(&self, len: usize) -> Vec<f64> {
+        self.steps.windows(len).map(|w| w.iter().sum::<f64>() / len as f64).collect()
+    }
+
+    pub(crate) fn finish(self) -> TrackLoudness {
+        let blocks = self.windows(BLOCK_STEPS);
+        let integrated_lufs = gated_loudness(blocks.iter().map(|&e| (e, 1.0)), RELATIVE_GATE_LU);
+
+        let mut histogram: BTreeMap<u16, u32> = BTreeMap::new();
+        for &e in &blocks {
+            if let Some(bin) = (e > 0.0).then(|| histogram_bin(loudness(e))).flatten() {
+                *histogram.entry(bin).or_default() += 1;
+            }
+        }
+
+        // Loudness range: spread of the gated short-term loudness, 10th to 95th percentile
+        let short_term = self.windows(SHORT_TERM_STEPS);
+        let above_absolute: Vec<f64> = short_term.into_iter().filter(|&e| e > 0.0 && loudness(e) > ABSOLUTE_GATE_LUFS).collect();
+        let range_lu = if above_absolute.is_empty() {
+            0.0
+        } else {
+            let gate = loudness(above_absolute.iter().sum::<f64>() / above_absolute.len() as f64) + RANGE_RELATIVE_GATE_LU;
+            let mut levels: Vec<f64> = above_absolute.into_iter().map(loudness).filter(|&l| l > gate).collect();
+            levels.sort_by(f64::total_cmp);
+            let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+            if levels.is_empty() { 0.0 } else { percentile(0.95) - percentile(0.10) }
+        };
+
+        TrackLoudness {
+            integrated_lufs,
+            range_lu,
+            true_peak_dbtp: 20.0 * self.true_peak.peak.max(1e-10).log10(),
+            histogram: histogram.into_iter().collect(),
+        }
+    }
+}
+
+pub(crate) fn replaygain_tags(track: Option<&TrackLoudness>, album: Option<&AlbumLoudness>) -> Option<Vec<(&'static str, String)>> {
+    let track = track?;
+    let peak = |dbtp: f64| format!("{:.6}", 10f64.powf(dbtp / 20.0));
+    let mut tags = vec![
+        ("REPLAYGAIN_TRACK_GAIN", format!("{:.2} dB", REFERENCE_LUFS - track.integrated_lufs?)),
+        ("REPLAYGAIN_TRACK_PEAK", peak(track.true_peak_dbtp)),
+    ];
+    if let Some((integrated, album)) = album.and_then(|a| Some((a.integrated_lufs?, a))) {
+        tags.push(("REPLAYGAIN_ALBUM_GAIN", format!("{:.2} dB", REFERENCE_LUFS - integrated)));
+        tags.push(("REPLAYGAIN_ALBUM_PEAK", peak(album.true_peak_dbtp)));
+    }
+    Some(tags)
+}
+
+// Replace the REPLAYGAIN_* Vorbis comments of a FLAC file. When the new
+// comment block fits in the old one plus padding, only the metadata is
+// overwritten in place, so the file keeps its inode, owner, permissions and
+// extended attributes. Otherwise the file is rewritten through a temporary
+// copy (with fresh padding for next time) that takes over the original's
+// permissions and owner. Returns false if the tags were already up to date.
+pub(crate) fn write_flac_replaygain(path: &Path, tags: &[(&str, String)]) -> Result<bool, String> {
+    let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let (blocks, audio_start) = read_flac_metadata(&mut BufReader::new(&mut file))?;
+
+    let (vendor, comments) = match blocks.iter().find(|(kind, _)| *kind == VORBIS_COMMENT) {
+        Some((_, body)) => parse_vorbis_comment(body).ok_or("malformed Vorbis comment")?,
+        None => (b"rust-audio-player".to_vec(), Vec::new()),
+    };
+    let is_replaygain = |c: &[u8]| c.len() >= 11 && c[..11].eq_ignore_ascii_case(b"REPLAYGAIN_");
+    let mut old: Vec<&[u8]> = comments.iter().map(Vec::as_slice).filter(|c| is_replaygain(c)).collect();
+    let new: Vec<Vec<u8>> = tags.iter().map(|(k, v)| format!("{k}={v}").into_bytes()).collect();
+    let mut wanted: Vec<&[u8]> = new.iter().map(Vec::as_slice).collect();
+    old.sort();
+    wanted.sort();
+    if old == wanted {
+        return Ok(false);
+    }
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    body.extend_from_slice(&vendor);
+    let kept: Vec<&Vec<u8>> = comments.iter().filter(|c| !is_replaygain(c)).collect();
+    body.extend_from_slice(&((kept.len() + new.len()) as u32).to_le_bytes());
+    for comment in kept.into_iter().chain(&new) {
+        body.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        body.extend_from_slice(comment);
+    }
+    if body.len() > MAX_BLOCK_LEN {
+        return Err("Vorbis comment too large".into());
+    }
+
+    // Put the new comment block where the old one was, or right after
+    // STREAMINFO; padding is collected into one block at the end
+    let mut out_blocks: Vec<(u8, &[u8])> = blocks
+        .iter()
+        .filter(|(kind, _)| *kind != VORBIS_COMMENT && *kind != PADDING)
+        .map(|(kind, body)| (*kind, body.as_slice()))
+        .collect();
+    let at = blocks.iter().position(|(kind, _)| *kind == VORBIS_COMMENT).unwrap_or(1).min(out_blocks.len());
+    out_blocks.insert(at, (VORBIS_COMMENT, &body));
+    let used = 4 + out_blocks.iter().map(|(_, b)| 4 + b.len() as u64).sum::<u64>();
+
+    // In place: exactly the old size, or the rest filled with padding
+    let room = audio_start.checked_sub(used).filter(|&r| r == 0 || (4..=MAX_BLOCK_LEN as u64 + 4).contains(&r));
+    if let Some(room) = room {
+        let padding = (room > 0).then(|| room as usize - 4);
+        let metadata = encode_flac_metadata(&out_blocks, padding);
+        let mut file = fs::OpenOptions::new().write(true).open(path).map_err(|e| e.to_string())?;
+        file.write_all(&metadata).and_then(|_| file.sync_all()).map_err(|e| e.to_string())?;
+        return Ok(true);
+    }
+
+    let metadata = encode_flac_metadata(&out_blocks, Some(REWRITE_PADDING));
+    let tmp = path.with_extension("flac.rg-tmp");
+    let result = (|| -> std::io::Result<()> {
+        let mut out = fs::File::create(&tmp)?;
+        out.write_all(&metadata)?;
+        file.seek(SeekFrom::Start(audio_start))?;
+        std::io::copy(&mut file, &mut out)?;
+        out.sync_all()?;
+        let original = file.metadata()?;
+        out.set_permissions(original.permissions())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // Only possible for root or when the owner doesn't change; keep going either way
+            let _ = std::os::unix::fs::fchown(&out, Some(original.uid()), Some(original.gid()));
+        }
+        drop(out);
+        fs::rename(&tmp, path)
+    })();
+    result.map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        e.to_string()
+    })?;
+    Ok(true)
+}
+
+// Metadata block type and body
+type FlacBlock = (u8, Vec<u8>);
+
+// Metadata blocks of a FLAC stream and the offset of its first audio frame
+fn read_flac_metadata(reader: &mut impl Read) -> Result<(Vec<FlacBlock>, u64), String> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).map_err(|_| "not a FLAC file")?;
+    if &magic != b"fLaC" {
+        return Err("not a FLAC file".into());
+    }
+    let mut blocks = Vec::new();
+    let mut pos = 4;
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).map_err(|_| "truncated metadata")?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).map_err(|_| "truncated metadata")?;
+        blocks.push((header[0] & 0x7f, body));
+        pos += 4 + len as u64;
+        if header[0] & 0x80 != 0 {
+            return Ok((blocks, pos));
+        }
+    }
+}
+
+fn encode_flac_metadata(blocks: &[(u8, &[u8])], padding: Option<usize>) -> Vec<u8> {
+    let zeros = vec![0; padding.unwrap_or(0)];
+    let padding = padding.map(|_| (PADDING, zeros.as_slice()));
+    let all: Vec<(u8, &[u8])> = blocks.iter().copied().chain(padding).collect();
+    let mut out = b"fLaC".to_vec();
+    for (i, (kind, block)) in all.iter().enumerate() {
+        let last = if i + 1 == all.len() { 0x80 } else { 0 };
+        out.push(kind | last);
+        out.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+// Vendor string and comments ("KEY=value") of a Vorbis comment block
+fn parse_vorbis_comment(body: &[u8]) -> Option<(Vec<u8>, Vec<Vec<u8>>)> {
+    let mut pos = 0;
+    let mut read = |len: usize| {
+        let bytes = body.get(pos..pos + len)?;
+        pos += len;
+        Some(bytes)
+    };
+    let u32_at = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize;
+    let vendor_len = u32_at(read(4)?);
+    let vendor = read(vendor_len)?.to_vec();
+    let count = u32_at(read(4)?);
+    let mut comments = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let len = u32_at(read(4)?);
+        comments.push(read(len)?.to_vec());
+    }
+    Some((vendor, comments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAMINFO: u8 = 0;
+
+    // A FLAC file with a zeroed STREAMINFO, the given comments and padding,
+    // and some bytes standing in for audio frames
+    fn flac_file(name: &str, comments: &[&str], padding: Option<usize>) -> (std::path::PathBuf, Vec<u8>) {
+        let mut vc = Vec::new();
+        vc.extend_from_slice(&4u32.to_le_bytes());
+        vc.extend_from_slice(b"test");
+        vc.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for c in comments {
+            vc.extend_from_slice(&(c.len() as u32).to_le_bytes());
+            vc.extend_from_slice(c.as_bytes());
+        }
+        let streaminfo = [0; 34];
+        let mut data = encode_flac_metadata(&[(STREAMINFO, &streaminfo), (VORBIS_COMMENT, &vc)], padding);
+        let audio: Vec<u8> = (0..=255).collect();
+        data.extend_from_slice(&audio);
+        let path = std::env::temp_dir().join(format!("rg-test-{}-{name}.flac", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        (path, audio)
+    }
+
+    fn comments(path: &Path) -> (Vec<FlacBlock>, u64, Vec<String>) {
+        let (blocks, audio_start) = read_flac_metadata(&mut fs::File::open(path).unwrap()).unwrap();
+        let vc = &blocks.iter().find(|(kind, _)| *kind == VORBIS_COMMENT).unwrap().1;
+        let (_, comments) = parse_vorbis_comment(vc).unwrap();
+        let comments = comments.into_iter().map(|c| String::from_utf8(c).unwrap()).collect();
+        (blocks, audio_start, comments)
+    }
+
+    fn tags() -> Vec<(&'static str, String)> {
+        vec![("REPLAYGAIN_TRACK_GAIN", "-3.20 dB".into()), ("REPLAYGAIN_TRACK_PEAK", "0.988000".into())]
+    }
+
+    #[test]
+    fn replaygain_fits_in_padding_in_place() {
+        let (path, audio) = flac_file("inplace", &["TITLE=Song", "replaygain_track_gain=+1.00 dB"], Some(1024));
+        let (_, audio_start, _) = comments(&path);
+        let len = fs::metadata(&path).unwrap().len();
+        assert!(write_flac_replaygain(&path, &tags()).unwrap());
+        let (blocks, new_start, found) = comments(&path);
+        assert_eq!(new_start, audio_start);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(found, ["TITLE=Song", "REPLAYGAIN_TRACK_GAIN=-3.20 dB", "REPLAYGAIN_TRACK_PEAK=0.988000"]);
+        assert_eq!(blocks.last().unwrap().0, PADDING);
+        assert!(fs::read(&path).unwrap().ends_with(&audio));
+        // Writing the same values again changes nothing
+        assert!(!write_flac_replaygain(&path, &tags()).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaygain_without_room_rewrites_with_padding() {
+        let (path, audio) = flac_file("rewrite", &["TITLE=Song"], None);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        assert!(write_flac_replaygain(&path, &tags()).unwrap());
+        let (blocks, _, found) = comments(&path);
+        assert_eq!(found.len(), 3);
+        assert_eq!(blocks.last().map(|(kind, body)| (*kind, body.len())), Some((PADDING, REWRITE_PADDING)));
+        assert!(fs::read(&path).unwrap().ends_with(&audio));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaygain_refuses_other_files() {
+        let path = std::env::temp_dir().join(format!("rg-test-{}-notflac.flac", std::process::id()));
+        fs::write(&path, b"ID3\x04 not a flac file").unwrap();
+        assert!(write_flac_replaygain(&path, &tags()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
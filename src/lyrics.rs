@@ -0,0 +1,368 @@
+// Lyrics loading: a sidecar `.lrc` file wins, then an embedded ID3 SYLT frame,
+// then plain lyric tags (ID3 USLT / Vorbis LYRICS), which may themselves
+// contain LRC timestamps.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use symphonia::core::formats::FormatOptions as SymFormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions as SymMetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint as SymHint;
+use symphonia::default::get_probe as sym_get_probe;
+
+#[derive(Debug, Clone)]
+pub(crate) struct LyricLine {
+    pub(crate) time: Duration,
+    pub(crate) text: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Lyrics {
+    // Sorted by time when `synced`; in file order otherwise
+    pub(crate) lines: Vec<LyricLine>,
+    pub(crate) synced: bool,
+}
+
+impl Lyrics {
+    // Index of the line being sung at `position` (the last line that has started).
+    pub(crate) fn line_at(&self, position: Duration) -> Option<usize> {
+        if !self.synced {
+            return None;
+        }
+        let after = self.lines.partition_point(|l| l.time <= position);
+        after.checked_sub(1)
+    }
+}
+
+// Load lyrics for a track. Does file IO, so call it off the UI thread.
+pub(crate) fn load_lyrics(track: &Path) -> Option<Lyrics> {
+    if let Some(lrc) = sidecar_lrc(track).and_then(|p| fs::read(p).ok()) {
+        let text = String::from_utf8_lossy(&lrc);
+        let lyrics = parse_lrc(&text);
+        if !lyrics.lines.is_empty() {
+            return Some(lyrics);
+        }
+    }
+    if let Some(lyrics) = read_sylt(track) {
+        return Some(lyrics);
+    }
+    let text = embedded_lyrics(track)?;
+    let lyrics = parse_lrc(&text);
+    if lyrics.lines.is_empty() { None } else { Some(lyrics) }
+}
+
+// Parse LRC text. Supports `[mm:ss]`, `[mm:ss.xx]`, `[mm:ss:xx]`, several
+// timestamps per line and the `[offset:±ms]` tag. Text without any timestamps
+// is returned as unsynced lines.
+pub(crate) fn parse_lrc(text: &str) -> Lyrics {
+    let mut timed: Vec<(i64, String)> = Vec::new();
+    let mut plain: Vec<String> = Vec::new();
+    let mut offset_ms: i64 = 0;
+
+    for raw in text.lines() {
+        let mut rest = raw.trim().trim_start_matches('\u{feff}');
+        let mut stamps: Vec<i64> = Vec::new();
+        let mut is_tag = false;
+        while let Some(inner) = rest.strip_prefix('[') {
+            let Some(end) = inner.find(']') else { break };
+            let tag = &inner[..end];
+            if let Some(ms) = parse_timestamp(tag) {
+                stamps.push(ms);
+            } else if let Some((key, value)) = tag.split_once(':') {
+                // ID tags such as [ar:...], [ti:...]; only offset affects timing
+                if key.trim().eq_ignore_ascii_case("offset") {
+                    offset_ms = value.trim().parse().unwrap_or(0);
+                }
+                is_tag = true;
+            } else {
+                break;
+            }
+            rest = &inner[end + 1..];
+        }
+        let line = rest.trim().to_string();
+        if !stamps.is_empty() {
+            for ms in stamps {
+                timed.push((ms, line.clone()));
+            }
+        } else if !is_tag && !line.is_empty() {
+            plain.push(line);
+        }
+    }
+
+    if timed.is_empty() {
+        return Lyrics {
+            lines: plain.into_iter().map(|text| LyricLine { time: Duration::ZERO, text }).collect(),
+            synced: false,
+        };
+    }
+
+    // A positive offset makes lyrics appear sooner
+    let mut lines: Vec<LyricLine> = timed
+        .into_iter()
+        .map(|(ms, text)| LyricLine { time: Duration::from_millis((ms - offset_ms).max(0) as u64), text })
+        .collect();
+    lines.sort_by_key(|l| l.time);
+    Lyrics { lines, synced: true }
+}
+
+// `mm:ss`, `mm:ss.xx`, `mm:ss.xxx` or `mm:ss:xx` -> milliseconds
+fn parse_timestamp(tag: &str) -> Option<i64> {
+    let (min, rest) = tag.split_once(':')?;
+    let min: i64 = min.trim().parse().ok()?;
+    let (sec, frac) = match rest.find(['.', ':']) {
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+        None => (rest, None),
+    };
+    let sec: i64 = sec.trim().parse().ok()?;
+    let frac_ms = match frac {
+        Some(f) if !f.is_empty() => {
+            let digits: String = f.chars().take(3).collect();
+            let value: i64 = digits.parse().ok()?;
+            match digits.len() {
+                1 => value * 100,
+                2 => value * 10,
+                _ => value,
+            }
+        }
+        _ => 0,
+    };
+    Some(min * 60_000 + sec * 1000 + frac_ms)
+}
+
+// `song.lrc` next to `song.flac` (extension matched case-insensitively)
+fn sidecar_lrc(track: &Path) -> Option<PathBuf> {
+    let stem = track.file_stem()?.to_str()?;
+    let dir = track.parent()?;
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| {
+            p.file_stem().and_then(|s| s.to_str()) == Some(stem)
+                && p.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("lrc"))
+        })
+}
+
+// USLT / Vorbis LYRICS / UNSYNCEDLYRICS via Symphonia's tag mapping
+fn embedded_lyrics(path: &Path) -> Option<String> {
+    let mut hint = SymHint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let file = fs::File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut probed = sym_get_probe()
+        .format(&hint, mss, &SymFormatOptions::default(), &SymMetadataOptions::default())
+        .ok()?;
+
+    fn pick(rev: &MetadataRevision) -> Option<String> {
+        rev.tags()
+            .iter()
+            .find(|t| t.std_key == Some(StandardTagKey::Lyrics))
+            .map(|t| t.value.to_string())
+    }
+
+    if let Some(found) = probed.metadata.get().as_ref().and_then(|m| m.current()).and_then(pick) {
+        return Some(found);
+    }
+    let mut format = probed.format;
+    let meta = format.metadata();
+    meta.current().and_then(pick)
+}
+
+// Symphonia skips SYLT frames, so read them straight from the ID3v2 tag.
+// Only millisecond timestamps (format 2) are supported.
+fn read_sylt(path: &Path) -> Option<Lyrics> {
+    use std::io::Read;
+
+    let mut file = fs::File::open(path).ok()?;
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header).ok()?;
+    if &header[0..3] != b"ID3" {
+        return None;
+    }
+    let version = header[3];
+    let flags = header[5];
+    if !(3..=4).contains(&version) || flags & 0x80 != 0 {
+        // v2.2 uses 3-char frame ids; whole-tag unsynchronisation is rare enough to skip
+        return None;
+    }
+    let tag_size = syncsafe(&header[6..10]) as usize;
+    let mut tag = vec![0u8; tag_size];
+    file.read_exact(&mut tag).ok()?;
+
+    let mut pos = 0usize;
+    if flags & 0x40 != 0 {
+        // Extended header: v2.3 size excludes the size field itself, v2.4 includes it
+        let raw = tag.get(0..4)?;
+        pos = if version == 4 { syncsafe(raw) as usize } else { u32::from_be_bytes(raw.try_into().ok()?) as usize + 4 };
+    }
+
+    while pos + 10 <= tag.len() {
+        let id = &tag[pos..pos + 4];
+        if id[0] == 0 {
+            break; // padding
+        }
+        let raw_size = &tag[pos + 4..pos + 8];
+        let size = if version == 4 { syncsafe(raw_size) } else { u32::from_be_bytes(raw_size.try_into().ok()?) } as usize;
+        let body = tag.get(pos + 10..pos + 10 + size)?;
+        let lyrics = if id == b"SYLT" { parse_sylt(body) } else { None };
+        if lyrics.is_some() {
+            return lyrics;
+        }
+        pos += 10 + size;
+    }
+    None
+}
+
+fn parse_sylt(body: &[u8]) -> Option<Lyrics> {
+    // encoding(1) language(3) timestamp format(1) content type(1) descriptor
+    let encoding = *body.first()?;
+    let format = *body.get(4)?;
+    if format != 2 {
+        return None;
+    }
+    let mut rest = body.get(6..)?;
+    let (_, after) = split_text(rest, encoding)?;
+    rest = after;
+
+    let mut lines = Vec::new();
+    while !rest.is_empty() {
+        let (text, after) = split_text(rest, encoding)?;
+        let stamp = after.get(0..4)?;
+        let ms = u32::from_be_bytes(stamp.try_into().ok()?);
+        // SYLT entries often start with a newline to mark a new line of text
+        lines.push(LyricLine { time: Duration::from_millis(ms as u64), text: text.trim().to_string() });
+        rest = &after[4..];
+    }
+    lines.retain(|l| !l.text.is_empty());
+    if lines.is_empty() {
+        return None;
+    }
+    lines.sort_by_key(|l| l.time);
+    Some(Lyrics { lines, synced: true })
+}
+
+// Split a null-terminated string in the given ID3 text encoding off the front of `data`.
+fn split_text(data: &[u8], encoding: u8) -> Option<(String, &[u8])> {
+    match encoding {
+        // ISO-8859-1 and UTF-8 use a single zero byte terminator
+        0 | 3 => {
+            let end = data.iter().position(|&b| b == 0)?;
+            let raw = &data[..end];
+            let text = if encoding == 0 {
+                raw.iter().map(|&b| b as char).collect()
+            } else {
+                String::from_utf8_lossy(raw).into_owned()
+            };
+            Some((text, &data[end + 1..]))
+        }
+        // UTF-16 with BOM, or UTF-16BE; terminated by two zero bytes on an even boundary
+        1 | 2 => {
+            let end = data.chunks_exact(2).position(|c| c == [0, 0])? * 2;
+            let mut raw = &data[..end];
+            let mut big_endian = encoding == 2;
+            if raw.starts_with(&[0xFF, 0xFE]) {
+                big_endian = false;
+                raw = &raw[2..];
+            } else if raw.starts_with(&[0xFE, 0xFF]) {
+                big_endian = true;
+                raw = &raw[2..];
+            }
+            let units: Vec<u16> = raw
+                .chunks_exact(2)
+                .map(|c| if big_endian { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) })
+                .collect();
+            Some((String::from_utf16_lossy(&units), &data[end + 2..]))
+        }
+        _ => None,
+    }
+}
+
+fn syncsafe(b: &[u8]) -> u32 {
+    b.iter().take(4).fold(0u32, |acc, &x| (acc << 7) | (x & 0x7F) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(lyrics: &Lyrics) -> Vec<u64> {
+        lyrics.lines.iter().map(|l| l.time.as_millis() as u64).collect()
+    }
+
+    #[test]
+    fn timestamps_in_every_format() {
+        assert_eq!(parse_timestamp("01:02"), Some(62_000));
+        assert_eq!(parse_timestamp("01:02.5"), Some(62_500));
+        assert_eq!(parse_timestamp("01:02.34"), Some(62_340));
+        assert_eq!(parse_timestamp("01:02.345"), Some(62_345));
+        assert_eq!(parse_timestamp("01:02:34"), Some(62_340));
+        assert_eq!(parse_timestamp("ar:Someone"), None);
+        assert_eq!(parse_timestamp("1:xx"), None);
+    }
+
+    #[test]
+    fn lines_with_several_timestamps_repeat_in_order() {
+        let lyrics = parse_lrc("[ti:Song]\n[00:10.00][00:30.00]Chorus\n[00:20.00]Verse\n");
+        assert!(lyrics.synced);
+        assert_eq!(times(&lyrics), [10_000, 20_000, 30_000]);
+        let text: Vec<_> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(text, ["Chorus", "Verse", "Chorus"]);
+        assert_eq!(lyrics.line_at(Duration::from_secs(25)), Some(1));
+        assert_eq!(lyrics.line_at(Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn offset_moves_lines_earlier_but_not_before_zero() {
+        let lyrics = parse_lrc("[offset:+500]\n[00:00.20]First\n[00:02.00]Second\n");
+        assert_eq!(times(&lyrics), [0, 1_500]);
+        let lyrics = parse_lrc("[offset:-250]\n[00:01.00]Late\n");
+        assert_eq!(times(&lyrics), [1_250]);
+    }
+
+    #[test]
+    fn text_without_timestamps_is_unsynced() {
+        let lyrics = parse_lrc("[ar:Someone]\nFirst line\n\nSecond line\n");
+        assert!(!lyrics.synced);
+        assert_eq!(lyrics.lines.len(), 2);
+        assert_eq!(lyrics.line_at(Duration::from_secs(1)), None);
+    }
+
+    // SYLT frame body: encoding, language, format 2 (ms), content type, descriptor
+    fn sylt(encoding: u8, entries: &[(&[u8], u32)]) -> Vec<u8> {
+        let terminator: &[u8] = if matches!(encoding, 1 | 2) { &[0, 0] } else { &[0] };
+        let mut body = vec![encoding, b'e', b'n', b'g', 2, 1];
+        body.extend_from_slice(terminator);
+        for (text, ms) in entries {
+            body.extend_from_slice(text);
+            body.extend_from_slice(terminator);
+            body.extend_from_slice(&ms.to_be_bytes());
+        }
+        body
+    }
+
+    #[test]
+    fn sylt_latin1_entries_are_sorted_and_trimmed() {
+        let body = sylt(0, &[(b"\nSecond", 2_000), (b"First", 1_000), (b"\n", 3_000)]);
+        let lyrics = parse_sylt(&body).unwrap();
+        assert_eq!(times(&lyrics), [1_000, 2_000]);
+        assert_eq!(lyrics.lines[1].text, "Second");
+    }
+
+    #[test]
+    fn sylt_utf16_with_bom() {
+        let text: Vec<u8> = [0xFF, 0xFE].into_iter().chain("Hé".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        let lyrics = parse_sylt(&sylt(1, &[(&text, 500)])).unwrap();
+        assert_eq!(lyrics.lines[0].text, "Hé");
+        assert_eq!(times(&lyrics), [500]);
+    }
+
+    #[test]
+    fn sylt_with_frame_timestamps_is_ignored() {
+        let mut body = sylt(0, &[(b"Line", 10)]);
+        body[4] = 1;
+        assert!(parse_sylt(&body).is_none());
+    }
+}
@@ -1,5 +1,14 @@
 #![cfg_attr(all(target_os = "windows", not(debug_assertions)), windows_subsystem = "windows")]
 
 fn main() -> iced::Result {
+    // `rust-audio-player play ...` plays in the terminal without a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "play") {
+        if let Err(e) = rust_audio_player::run_cli(&args[1..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     rust_audio_player::run_app()
-}
\ No newline at end of file
+}
@@ -0,0 +1,137 @@
+// Peak/RMS level metering. The audio thread folds every frame into a few
+// atomics; the UI reads (and resets) them on each tick.
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::time::Instant;
+
+pub(crate) const MAX_CHANNELS: usize = 8;
+pub(crate) const FLOOR_DB: f32 = -60.0;
+// Integration time of the RMS reading
+const RMS_WINDOW_SECS: f32 = 0.3;
+// How long the peak marker holds before falling, and how fast it falls
+const HOLD_SECS: f32 = 1.5;
+const HOLD_FALL_DB_PER_SEC: f32 = 20.0;
+
+// Shared between the audio thread (writer) and UI (reader). Peaks are the
+// largest magnitude since the last read; positive floats order like their
+// bits, so `fetch_max` on the bits works without a lock.
+pub(crate) struct LevelMeter {
+    channels: AtomicUsize,
+    peak_bits: [AtomicU32; MAX_CHANNELS],
+    mean_square_bits: [AtomicU32; MAX_CHANNELS],
+    clipped: [AtomicBool; MAX_CHANNELS],
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self {
+            channels: AtomicUsize::new(0),
+            peak_bits: std::array::from_fn(|_| AtomicU32::new(0)),
+            mean_square_bits: std::array::from_fn(|_| AtomicU32::new(0)),
+            clipped: std::array::from_fn(|_| AtomicBool::new(false)),
+        }
+    }
+}
+
+// Audio-thread side: running mean square per channel.
+pub(crate) struct MeterWriter {
+    mean_square: [f32; MAX_CHANNELS],
+    alpha: f32,
+    frames: u32,
+    publish_every: u32,
+}
+
+impl MeterWriter {
+    pub(crate) fn new(meter: &LevelMeter, channels: usize, sample_rate: u32) -> Self {
+        meter.channels.store(channels.min(MAX_CHANNELS), Ordering::Relaxed);
+        let rate = sample_rate.max(1) as f32;
+        Self {
+            mean_square: [0.0; MAX_CHANNELS],
+            alpha: 1.0 - (-1.0 / (RMS_WINDOW_SECS * rate)).exp(),
+            frames: 0,
+            // Publish the RMS reading about every 10 ms
+            publish_every: (rate / 100.0) as u32,
+        }
+    }
+
+    pub(crate) fn process(&mut self, meter: &LevelMeter, frame: &[f32]) {
+        for (c, &x) in frame.iter().take(MAX_CHANNELS).enumerate() {
+            let magnitude = x.abs();
+            meter.peak_bits[c].fetch_max(magnitude.to_bits(), Ordering::Relaxed);
+            // Anything past full scale is clipped by the output
+            if magnitude > 1.0 {
+                meter.clipped[c].store(true, Ordering::Relaxed);
+            }
+            self.mean_square[c] += (x * x - self.mean_square[c]) * self.alpha;
+        }
+        self.frames += 1;
+        if self.frames >= self.publish_every {
+            self.frames = 0;
+            for (bits, ms) in meter.mean_square_bits.iter().zip(self.mean_square) {
+                bits.store(ms.to_bits(), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChannelLevel {
+    pub(crate) peak_db: f32,
+    pub(crate) rms_db: f32,
+    pub(crate) hold_db: f32,
+    hold_age: f32,
+    // Sticky until the user resets it
+    pub(crate) clipped: bool,
+}
+
+impl Default for ChannelLevel {
+    fn default() -> Self {
+        Self { peak_db: FLOOR_DB, rms_db: FLOOR_DB, hold_db: FLOOR_DB, hold_age: 0.0, clipped: false }
+    }
+}
+
+// What the UI shows, refreshed on every tick.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MeterDisplay {
+    pub(crate) channels: Vec<ChannelLevel>,
+    last_update: Option<Instant>,
+}
+
+impl MeterDisplay {
+    // Silence the readings while paused/stopped so the bars drop.
+    pub(crate) fn update(&mut self, meter: &LevelMeter, playing: bool) {
+        let now = Instant::now();
+        let dt = self.last_update.map_or(0.0, |t| now.duration_since(t).as_secs_f32());
+        self.last_update = Some(now);
+        let n = meter.channels.load(Ordering::Relaxed);
+        self.channels.resize(n, ChannelLevel::default());
+        for (c, level) in self.channels.iter_mut().enumerate() {
+            let peak = f32::from_bits(meter.peak_bits[c].swap(0, Ordering::Relaxed));
+            let rms = f32::from_bits(meter.mean_square_bits[c].load(Ordering::Relaxed)).sqrt();
+            let (peak, rms) = if playing { (peak, rms) } else { (0.0, 0.0) };
+            level.peak_db = to_db(peak);
+            level.rms_db = to_db(rms);
+            level.clipped |= meter.clipped[c].swap(false, Ordering::Relaxed);
+            if level.peak_db >= level.hold_db {
+                level.hold_db = level.peak_db;
+                level.hold_age = 0.0;
+            } else {
+                level.hold_age += dt;
+                if level.hold_age > HOLD_SECS {
+                    level.hold_db = (level.hold_db - HOLD_FALL_DB_PER_SEC * dt).max(level.peak_db);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn reset_clips(&mut self) {
+        self.channels.iter_mut().for_each(|c| c.clipped = false);
+    }
+
+    pub(crate) fn any_clipped(&self) -> bool {
+        self.channels.iter().any(|c| c.clipped)
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(1e-9).log10()).max(FLOOR_DB)
+}
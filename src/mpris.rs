@@ -0,0 +1,706 @@
+// MPRIS2 D-Bus server so desktop media widgets, media keys and `playerctl`
+// can control the player (Linux only). The D-Bus side runs on its own thread:
+// the app hands it a PlayerState after every update and gets MprisEvents
+// back, whose commands it turns into the same Messages as the buttons.
+#![cfg_attr(not(target_os = "linux"), allow(dead_code))]
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use crate::cue::TrackRange;
+use crate::engine::AudioFile;
+
+// Requests from D-Bus clients. Track references are indices into the app's file list.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MprisCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    // Relative seek in microseconds; may be negative
+    Seek(i64),
+    SetPosition(usize, Duration),
+    SetVolume(f64),
+    SetRate(f64),
+    GoTo(usize),
+}
+
+// What the server thread sends back to the app
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MprisEvent {
+    Command(MprisCommand),
+    // The server couldn't start or stopped; nothing follows
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum PlaybackStatus {
+    Playing,
+    Paused,
+    #[default]
+    Stopped,
+}
+
+impl PlaybackStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            PlaybackStatus::Playing => "Playing",
+            PlaybackStatus::Paused => "Paused",
+            PlaybackStatus::Stopped => "Stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TrackInfo {
+    pub(crate) index: usize,
+    pub(crate) path: PathBuf,
+    pub(crate) name: String,
+    pub(crate) cue: Option<TrackRange>,
+    pub(crate) length: Option<Duration>,
+}
+
+impl TrackInfo {
+    // Length is only known up front for CUE tracks with an end
+    pub(crate) fn new(index: usize, file: &AudioFile) -> Self {
+        let length = file.cue.and_then(|r| Some(r.end?.saturating_sub(r.start)));
+        Self { index, path: file.path.clone(), name: file.name.clone(), cue: file.cue, length }
+    }
+}
+
+// What the player looks like from the outside. `tracks` is the play order
+// (the filtered list); a new Arc means the list was replaced.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct PlayerState {
+    pub(crate) status: PlaybackStatus,
+    pub(crate) current: Option<TrackInfo>,
+    pub(crate) position: Duration,
+    pub(crate) volume: f64,
+    pub(crate) rate: f64,
+    pub(crate) min_rate: f64,
+    pub(crate) max_rate: f64,
+    pub(crate) can_go_next: bool,
+    pub(crate) can_go_previous: bool,
+    pub(crate) tracks: Arc<Vec<TrackInfo>>,
+}
+
+pub(crate) type SharedReceiver<T> = Arc<Mutex<Option<mpsc::Receiver<T>>>>;
+
+pub(crate) struct Mpris {
+    updates: mpsc::Sender<PlayerState>,
+    last: Option<PlayerState>,
+    tracks: Arc<Vec<TrackInfo>>,
+    events: SharedReceiver<MprisEvent>,
+}
+
+impl Mpris {
+    // Start the server thread. Returns None where MPRIS doesn't exist; a
+    // missing session bus shows up later as MprisEvent::Failed.
+    pub(crate) fn start() -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let (updates, update_rx) = mpsc::channel();
+            let (event_tx, events) = mpsc::channel();
+            std::thread::spawn(move || {
+                if let Err(e) = server::run(update_rx, event_tx.clone()) {
+                    let _ = event_tx.send(MprisEvent::Failed(e.to_string()));
+                }
+            });
+            Some(Self { updates, last: None, tracks: Arc::default(), events: Arc::new(Mutex::new(Some(events))) })
+        }
+        #[cfg(not(target_os = "linux"))]
+        None
+    }
+
+    // Publish the player state; unchanged states aren't sent again.
+    pub(crate) fn update(&mut self, state: PlayerState) {
+        if self.last.as_ref() != Some(&state) {
+            let _ = self.updates.send(state.clone());
+            self.last = Some(state);
+        }
+    }
+
+    // Track list for `order` (indices into `files`), reusing the last one while
+    // it still matches so an unchanged list isn't announced as replaced.
+    pub(crate) fn track_list(&mut self, order: &[usize], files: &[AudioFile]) -> Arc<Vec<TrackInfo>> {
+        let unchanged = self.tracks.len() == order.len()
+            && self.tracks.iter().zip(order).all(|(t, &i)| files.get(i).is_some_and(|f| t.index == i && t.path == f.path && t.cue == f.cue));
+        if !unchanged {
+            self.tracks = Arc::new(order.iter().filter_map(|&i| Some(TrackInfo::new(i, files.get(i)?))).collect());
+        }
+        self.tracks.clone()
+    }
+
+    // Event channel, taken once by the subscription that forwards it.
+    pub(crate) fn events(&self) -> SharedReceiver<MprisEvent> {
+        self.events.clone()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod server {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use symphonia::core::formats::FormatOptions as SymFormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::{MetadataOptions as SymMetadataOptions, MetadataRevision, StandardTagKey};
+    use symphonia::core::probe::Hint as SymHint;
+    use symphonia::default::get_probe as sym_get_probe;
+    use zbus::blocking::connection::Builder;
+    use zbus::interface;
+    use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+    use super::{MprisCommand, MprisEvent, PlaybackStatus, PlayerState, TrackInfo};
+    use crate::artwork;
+
+    const BUS_NAME: &str = "org.mpris.MediaPlayer2.rust_audio_player";
+    const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+    const PLAYER_IFACE: &str = "org.mpris.MediaPlayer2.Player";
+    const TRACKLIST_IFACE: &str = "org.mpris.MediaPlayer2.TrackList";
+    const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+    // Track ids may not live under /org/mpris; index into the app's file list
+    const TRACK_PATH_PREFIX: &str = "/dev/RustSamples/RustAudioPlayer/Track/";
+    // Position jumps bigger than this (beyond normal playback) count as seeks
+    const SEEK_TOLERANCE: Duration = Duration::from_secs(1);
+
+    type Metadata = HashMap<String, OwnedValue>;
+
+    #[derive(Default)]
+    struct Published {
+        state: PlayerState,
+        metadata: Metadata,
+        // When `state` arrived, to extrapolate the position in between updates
+        since: Option<Instant>,
+    }
+
+    impl Published {
+        fn position(&self) -> Duration {
+            match (self.state.status, self.since) {
+                (PlaybackStatus::Playing, Some(since)) => self.state.position + since.elapsed().mul_f64(self.state.rate),
+                _ => self.state.position,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Tags {
+        title: Option<String>,
+        artist: Option<String>,
+        album: Option<String>,
+        // Cached cover image
+        art: Option<PathBuf>,
+    }
+
+    // Tags and artwork files by path; both need file IO, so look them up once
+    #[derive(Clone, Default)]
+    struct TagCache(Arc<Mutex<HashMap<PathBuf, Tags>>>);
+
+    impl TagCache {
+        fn get(&self, path: &Path) -> Tags {
+            if let Some(found) = self.0.lock().ok().and_then(|c| c.get(path).cloned()) {
+                return found;
+            }
+            let found = Tags { art: artwork::artwork_file(path, artwork::NOW_PLAYING_SIZE), ..read_tags(path) };
+            if let Ok(mut cache) = self.0.lock() {
+                cache.insert(path.to_path_buf(), found.clone());
+            }
+            found
+        }
+
+        fn metadata(&self, track: &TrackInfo) -> Metadata {
+            let tags = self.get(&track.path);
+            let mut m = Metadata::new();
+            insert(&mut m, "mpris:trackid", track_id(track.index).into());
+            if let Some(length) = track.length {
+                insert(&mut m, "mpris:length", Value::from(length.as_micros() as i64));
+            }
+            // CUE tracks have their own titles; the file's tags describe the album
+            let title = if track.cue.is_some() { Some(track.name.clone()) } else { tags.title };
+            insert(&mut m, "xesam:title", Value::from(title.unwrap_or_else(|| track.name.clone())));
+            if let Some(artist) = tags.artist {
+                insert(&mut m, "xesam:artist", Value::from(vec![artist]));
+            }
+            if let Some(album) = tags.album {
+                insert(&mut m, "xesam:album", Value::from(album));
+            }
+            insert(&mut m, "xesam:url", Value::from(file_url(&track.path)));
+            if let Some(art) = tags.art {
+                insert(&mut m, "mpris:artUrl", Value::from(file_url(&art)));
+            }
+            m
+        }
+    }
+
+    fn insert(map: &mut Metadata, key: &str, value: Value<'_>) {
+        if let Ok(value) = value.try_into_owned() {
+            map.insert(key.to_string(), value);
+        }
+    }
+
+    fn track_id(index: usize) -> OwnedObjectPath {
+        ObjectPath::try_from(format!("{TRACK_PATH_PREFIX}{index}"))
+            .map(Into::into)
+            .unwrap_or_else(|_| no_track())
+    }
+
+    fn no_track() -> OwnedObjectPath {
+        ObjectPath::from_static_str_unchecked(NO_TRACK).into()
+    }
+
+    fn track_index(id: &ObjectPath<'_>) -> Option<usize> {
+        id.as_str().strip_prefix(TRACK_PATH_PREFIX)?.parse().ok()
+    }
+
+    // file:// URL with everything outside the unreserved set percent-encoded
+    fn file_url(path: &Path) -> String {
+        let mut url = String::from("file://");
+        for &b in path.as_os_str().as_encoded_bytes() {
+            if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+                url.push(b as char);
+            } else {
+                url.push_str(&format!("%{b:02X}"));
+            }
+        }
+        url
+    }
+
+    // Title, artist and album, from probe-time metadata (ID3v2) first, then the container's.
+    fn read_tags(path: &Path) -> Tags {
+        let mut tags = Tags::default();
+        let mut hint = SymHint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+        let Ok(file) = std::fs::File::open(path) else { return tags };
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let Ok(mut probed) = sym_get_probe().format(&hint, mss, &SymFormatOptions::default(), &SymMetadataOptions::default())
+        else {
+            return tags;
+        };
+        let mut fill = |rev: &MetadataRevision| {
+            for tag in rev.tags() {
+                let slot = match tag.std_key {
+                    Some(StandardTagKey::TrackTitle) => &mut tags.title,
+                    Some(StandardTagKey::Artist) => &mut tags.artist,
+                    Some(StandardTagKey::Album) => &mut tags.album,
+                    _ => continue,
+                };
+                // RIFF INFO strings keep their NUL padding
+                let value = tag.value.to_string();
+                let value = value.trim_end_matches('\0').trim();
+                if slot.is_none() && !value.is_empty() {
+                    *slot = Some(value.to_string());
+                }
+            }
+        };
+        if let Some(rev) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            fill(rev);
+        }
+        if let Some(rev) = probed.format.metadata().current() {
+            fill(rev);
+        }
+        tags
+    }
+
+    struct Root;
+
+    #[interface(name = "org.mpris.MediaPlayer2")]
+    impl Root {
+        fn raise(&self) {}
+
+        fn quit(&self) {}
+
+        #[zbus(property)]
+        fn can_quit(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn can_raise(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn has_track_list(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn identity(&self) -> &str {
+            "Rust Audio Player"
+        }
+
+        #[zbus(property)]
+        fn supported_uri_schemes(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        #[zbus(property)]
+        fn supported_mime_types(&self) -> Vec<String> {
+            Vec::new()
+        }
+    }
+
+    struct Player {
+        published: Arc<Mutex<Published>>,
+        events: mpsc::Sender<MprisEvent>,
+    }
+
+    impl Player {
+        fn send(&self, command: MprisCommand) {
+            let _ = self.events.send(MprisEvent::Command(command));
+        }
+
+        fn read<T>(&self, f: impl FnOnce(&Published) -> T) -> T
+        where
+            T: Default,
+        {
+            self.published.lock().map(|p| f(&p)).unwrap_or_default()
+        }
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl Player {
+        fn next(&self) {
+            self.send(MprisCommand::Next);
+        }
+
+        fn previous(&self) {
+            self.send(MprisCommand::Previous);
+        }
+
+        fn pause(&self) {
+            self.send(MprisCommand::Pause);
+        }
+
+        fn play_pause(&self) {
+            self.send(MprisCommand::PlayPause);
+        }
+
+        fn stop(&self) {
+            self.send(MprisCommand::Stop);
+        }
+
+        fn play(&self) {
+            self.send(MprisCommand::Play);
+        }
+
+        fn seek(&self, offset: i64) {
+            self.send(MprisCommand::Seek(offset));
+        }
+
+        // Ignored unless it names the current track (stale requests)
+        fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+            let current = self.read(|p| p.state.current.as_ref().map(|t| t.index));
+            let index = track_index(&track_id).filter(|&i| current == Some(i));
+            if let (Some(index), Ok(us)) = (index, u64::try_from(position)) {
+                self.send(MprisCommand::SetPosition(index, Duration::from_micros(us)));
+            }
+        }
+
+        fn open_uri(&self, _uri: String) -> zbus::fdo::Result<()> {
+            Err(zbus::fdo::Error::NotSupported("Opening URIs is not supported".into()))
+        }
+
+        #[zbus(property)]
+        fn playback_status(&self) -> String {
+            self.read(|p| p.state.status.as_str().to_string())
+        }
+
+        #[zbus(property)]
+        fn rate(&self) -> f64 {
+            self.read(|p| p.state.rate)
+        }
+
+        #[zbus(property)]
+        fn set_rate(&mut self, rate: f64) {
+            self.send(MprisCommand::SetRate(rate));
+        }
+
+        #[zbus(property)]
+        fn minimum_rate(&self) -> f64 {
+            self.read(|p| p.state.min_rate)
+        }
+
+        #[zbus(property)]
+        fn maximum_rate(&self) -> f64 {
+            self.read(|p| p.state.max_rate)
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> Metadata {
+            self.read(|p| p.metadata.clone())
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            self.read(|p| p.state.volume)
+        }
+
+        #[zbus(property)]
+        fn set_volume(&mut self, volume: f64) {
+            self.send(MprisCommand::SetVolume(volume));
+        }
+
+        // Clients ask for the position when they need it; only jumps are signalled (Seeked)
+        #[zbus(property(emits_changed_signal = "false"))]
+        fn position(&self) -> i64 {
+            self.read(|p| p.position().as_micros() as i64)
+        }
+
+        #[zbus(property)]
+        fn can_go_next(&self) -> bool {
+            self.read(|p| p.state.can_go_next)
+        }
+
+        #[zbus(property)]
+        fn can_go_previous(&self) -> bool {
+            self.read(|p| p.state.can_go_previous)
+        }
+
+        #[zbus(property)]
+        fn can_play(&self) -> bool {
+            self.read(|p| can_play(&p.state))
+        }
+
+        #[zbus(property)]
+        fn can_pause(&self) -> bool {
+            self.read(|p| can_pause(&p.state))
+        }
+
+        #[zbus(property)]
+        fn can_seek(&self) -> bool {
+            self.read(|p| can_seek(&p.state))
+        }
+
+        #[zbus(property(emits_changed_signal = "const"))]
+        fn can_control(&self) -> bool {
+            true
+        }
+    }
+
+    struct TrackList {
+        published: Arc<Mutex<Published>>,
+        events: mpsc::Sender<MprisEvent>,
+        tags: TagCache,
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.TrackList")]
+    impl TrackList {
+        fn get_tracks_metadata(&self, track_ids: Vec<OwnedObjectPath>) -> Vec<Metadata> {
+            let tracks = self.published.lock().map(|p| p.state.tracks.clone()).unwrap_or_default();
+            track_ids
+                .iter()
+                .filter_map(|id| {
+                    let index = track_index(id)?;
+                    tracks.iter().find(|t| t.index == index)
+                })
+                .map(|t| self.tags.metadata(t))
+                .collect()
+        }
+
+        fn add_track(&self, _uri: String, _after_track: ObjectPath<'_>, _set_as_current: bool) -> zbus::fdo::Result<()> {
+            Err(zbus::fdo::Error::NotSupported("The track list follows the chosen folder".into()))
+        }
+
+        fn remove_track(&self, _track_id: ObjectPath<'_>) -> zbus::fdo::Result<()> {
+            Err(zbus::fdo::Error::NotSupported("The track list follows the chosen folder".into()))
+        }
+
+        fn go_to(&self, track_id: ObjectPath<'_>) {
+            if let Some(index) = track_index(&track_id) {
+                let _ = self.events.send(MprisEvent::Command(MprisCommand::GoTo(index)));
+            }
+        }
+
+        // Changes are announced with TrackListReplaced
+        #[zbus(property(emits_changed_signal = "invalidates"))]
+        fn tracks(&self) -> Vec<OwnedObjectPath> {
+            let tracks = self.published.lock().map(|p| p.state.tracks.clone()).unwrap_or_default();
+            tracks.iter().map(|t| track_id(t.index)).collect()
+        }
+
+        #[zbus(property)]
+        fn can_edit_tracks(&self) -> bool {
+            false
+        }
+    }
+
+    // Serve on the session bus until the app drops its end of `updates`.
+    pub(super) fn run(updates: mpsc::Receiver<PlayerState>, events: mpsc::Sender<MprisEvent>) -> zbus::Result<()> {
+        serve(Builder::session()?, updates, events)
+    }
+
+    fn serve(builder: Builder<'_>, updates: mpsc::Receiver<PlayerState>, events: mpsc::Sender<MprisEvent>) -> zbus::Result<()> {
+        let published = Arc::new(Mutex::new(Published::default()));
+        let tags = TagCache::default();
+        let player = Player { published: published.clone(), events: events.clone() };
+        let track_list = TrackList { published: published.clone(), events, tags: tags.clone() };
+        let connection = builder
+            .serve_at(OBJECT_PATH, Root)?
+            .serve_at(OBJECT_PATH, player)?
+            .serve_at(OBJECT_PATH, track_list)?
+            .build()?;
+        // A second instance takes a unique name, as the spec suggests; a
+        // peer-to-peer connection has no names at all
+        if connection.unique_name().is_some() && connection.request_name(BUS_NAME).is_err() {
+            connection.request_name(format!("{BUS_NAME}.instance{}", std::process::id()))?;
+        }
+
+        for state in updates {
+            let Ok(mut p) = published.lock() else { break };
+            // Where the previous state says playback should be by now
+            let expected = p.position();
+            let old = std::mem::replace(&mut p.state, state);
+            p.since = Some(Instant::now());
+            let new = p.state.clone();
+            let metadata = (old.current != new.current).then(|| {
+                p.metadata = new.current.as_ref().map(|t| tags.metadata(t)).unwrap_or_default();
+                p.metadata.clone()
+            });
+            drop(p);
+            announce(&connection, &old, &new, metadata, expected)?;
+        }
+        Ok(())
+    }
+
+    // Emit the signals for whatever changed between two states.
+    fn announce(
+        connection: &zbus::blocking::Connection,
+        old: &PlayerState,
+        new: &PlayerState,
+        metadata: Option<Metadata>,
+        expected: Duration,
+    ) -> zbus::Result<()> {
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        if old.status != new.status {
+            changed.insert("PlaybackStatus", Value::from(new.status.as_str()));
+        }
+        let track_changed = metadata.is_some();
+        if let Some(metadata) = metadata {
+            changed.insert("Metadata", Value::from(metadata));
+        }
+        if old.volume != new.volume {
+            changed.insert("Volume", Value::from(new.volume));
+        }
+        if old.rate != new.rate {
+            changed.insert("Rate", Value::from(new.rate));
+        }
+        for (name, flag) in capabilities(new) {
+            if capabilities(old).iter().any(|(n, f)| *n == name && *f != flag) {
+                changed.insert(name, Value::from(flag));
+            }
+        }
+        if !changed.is_empty() {
+            let invalidated: Vec<String> = Vec::new();
+            connection.emit_signal(None::<&str>, OBJECT_PATH, "org.freedesktop.DBus.Properties", "PropertiesChanged", &(PLAYER_IFACE, changed, invalidated))?;
+        }
+        if !track_changed && new.current.is_some() && new.position.abs_diff(expected) > SEEK_TOLERANCE {
+            connection.emit_signal(None::<&str>, OBJECT_PATH, PLAYER_IFACE, "Seeked", &(new.position.as_micros() as i64))?;
+        }
+        if !Arc::ptr_eq(&old.tracks, &new.tracks) {
+            let ids: Vec<OwnedObjectPath> = new.tracks.iter().map(|t| track_id(t.index)).collect();
+            let current = new.current.as_ref().map_or_else(no_track, |t| track_id(t.index));
+            connection.emit_signal(None::<&str>, OBJECT_PATH, TRACKLIST_IFACE, "TrackListReplaced", &(ids, current))?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(s: &PlayerState) -> [(&'static str, bool); 5] {
+        [
+            ("CanGoNext", s.can_go_next),
+            ("CanGoPrevious", s.can_go_previous),
+            ("CanPlay", can_play(s)),
+            ("CanPause", can_pause(s)),
+            ("CanSeek", can_seek(s)),
+        ]
+    }
+
+    fn can_play(s: &PlayerState) -> bool {
+        s.current.is_some() || !s.tracks.is_empty()
+    }
+
+    fn can_pause(s: &PlayerState) -> bool {
+        s.current.is_some()
+    }
+
+    fn can_seek(s: &PlayerState) -> bool {
+        s.current.as_ref().is_some_and(|t| t.length.is_some())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::cue::TrackRange;
+        use zbus::blocking::Connection;
+
+        // Server and client ends of a private peer-to-peer connection
+        fn connect() -> (Connection, mpsc::Sender<PlayerState>, mpsc::Receiver<MprisEvent>, std::thread::JoinHandle<zbus::Result<()>>) {
+            let (server_end, client_end) = std::os::unix::net::UnixStream::pair().unwrap();
+            let (updates, update_rx) = mpsc::channel();
+            let (event_tx, events) = mpsc::channel();
+            #[allow(deprecated)]
+            let builder = Builder::unix_stream(server_end).server(zbus::Guid::generate()).unwrap().p2p();
+            let server = std::thread::spawn(move || serve(builder, update_rx, event_tx));
+            #[allow(deprecated)]
+            let client = Builder::unix_stream(client_end).p2p().build().unwrap();
+            (client, updates, events, server)
+        }
+
+        fn call(client: &Connection, method: &str, body: &(impl serde::Serialize + zbus::zvariant::DynamicType)) {
+            client.call_method(None::<&str>, OBJECT_PATH, Some(PLAYER_IFACE), method, body).unwrap();
+        }
+
+        fn metadata(client: &Connection) -> Metadata {
+            let reply = client
+                .call_method(None::<&str>, OBJECT_PATH, Some("org.freedesktop.DBus.Properties"), "Get", &(PLAYER_IFACE, "Metadata"))
+                .unwrap();
+            let value: OwnedValue = reply.body().deserialize().unwrap();
+            Metadata::try_from(value).unwrap()
+        }
+
+        #[test]
+        fn player_commands_and_metadata_on_a_private_connection() {
+            let (client, updates, events, server) = connect();
+            let next_event = || events.recv_timeout(Duration::from_secs(5)).unwrap();
+
+            call(&client, "PlayPause", &());
+            assert_eq!(next_event(), MprisEvent::Command(MprisCommand::PlayPause));
+            call(&client, "Seek", &(-5_000_000i64));
+            assert_eq!(next_event(), MprisEvent::Command(MprisCommand::Seek(-5_000_000)));
+
+            // A CUE track: its own title and a known length
+            let range = TrackRange { start: Duration::from_secs(60), end: Some(Duration::from_secs(240)) };
+            let file = crate::engine::AudioFile { name: "02. Artist - Song".into(), path: PathBuf::from("/nonexistent/album.flac"), cue: Some(range) };
+            let track = TrackInfo::new(7, &file);
+            updates
+                .send(PlayerState { status: PlaybackStatus::Playing, current: Some(track.clone()), rate: 1.0, tracks: Arc::new(vec![track]), ..Default::default() })
+                .unwrap();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut found = metadata(&client);
+            while found.is_empty() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+                found = metadata(&client);
+            }
+            let get = |key: &str| found.get(key).cloned().unwrap_or_else(|| panic!("no {key}"));
+            assert_eq!(OwnedObjectPath::try_from(get("mpris:trackid")).unwrap().as_str(), "/dev/RustSamples/RustAudioPlayer/Track/7");
+            assert_eq!(String::try_from(get("xesam:title")).unwrap(), "02. Artist - Song");
+            assert_eq!(i64::try_from(get("mpris:length")).unwrap(), 180_000_000);
+            assert_eq!(String::try_from(get("xesam:url")).unwrap(), "file:///nonexistent/album.flac");
+
+            // SetPosition only counts for the current track
+            let id = track_id(7);
+            call(&client, "SetPosition", &(&id, 90_000_000i64));
+            assert_eq!(next_event(), MprisEvent::Command(MprisCommand::SetPosition(7, Duration::from_secs(90))));
+
+            // Dropping the app's end stops the server
+            drop(updates);
+            server.join().unwrap().unwrap();
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Search box queries. Plain text is matched (case-insensitively) against the
+// track's name, BPM and key; smart rules narrow by analysis results:
+//   bpm:128        within half a BPM
+//   bpm:120-128    inclusive range (also bpm>120, bpm<100)
+//   key:Am         exact key; Camelot codes (key:8A) and lists (key:8A,9A) work too
+//   key:~8A        keys that mix harmonically with 8A
+use crate::tempo_key::{MusicalKey, TempoKey};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TrackQuery {
+    // Everything that isn't a rule, lowercased
+    text: String,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Bpm { min: f32, max: f32 },
+    Key(Vec<MusicalKey>),
+}
+
+impl Rule {
+    fn parse(token: &str) -> Option<Rule> {
+        let lower = token.to_ascii_lowercase();
+        if let Some(spec) = lower.strip_prefix("bpm") {
+            let (min, max) = if let Some(v) = spec.strip_prefix(">=").or_else(|| spec.strip_prefix('>')) {
+                (v.parse().ok()?, f32::INFINITY)
+            } else if let Some(v) = spec.strip_prefix("<=").or_else(|| spec.strip_prefix('<')) {
+                (0.0, v.parse().ok()?)
+            } else {
+                let spec = spec.strip_prefix(':')?;
+                match spec.split_once('-') {
+                    Some((lo, hi)) => (lo.parse().ok()?, hi.parse().ok()?),
+                    None => {
+                        let bpm: f32 = spec.parse().ok()?;
+                        (bpm - 0.5, bpm + 0.5)
+                    }
+                }
+            };
+            return Some(Rule::Bpm { min, max });
+        }
+        let spec = token.get(..4).filter(|p| p.eq_ignore_ascii_case("key:")).map(|_| &token[4..])?;
+        let mut keys = Vec::new();
+        for part in spec.split(',') {
+            match part.strip_prefix('~') {
+                Some(key) => keys.extend(key.parse::<MusicalKey>().ok()?.compatible()),
+                None => keys.push(part.parse().ok()?),
+            }
+        }
+        Some(Rule::Key(keys))
+    }
+
+    fn matches(&self, info: Option<&TempoKey>) -> bool {
+        match self {
+            Rule::Bpm { min, max } => info.and_then(|i| i.bpm).is_some_and(|bpm| (*min..=*max).contains(&bpm)),
+            Rule::Key(keys) => info.and_then(|i| i.key).is_some_and(|key| keys.contains(&key)),
+        }
+    }
+}
+
+impl TrackQuery {
+    // Tokens that don't parse as rules are searched for as plain text
+    pub(crate) fn parse(query: &str) -> Self {
+        let mut me = TrackQuery::default();
+        let mut text = Vec::new();
+        for token in query.split_whitespace() {
+            match Rule::parse(token) {
+                Some(rule) => me.rules.push(rule),
+                None => text.push(token),
+            }
+        }
+        me.text = text.join(" ").to_lowercase();
+        me
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.rules.is_empty()
+    }
+
+    pub(crate) fn matches(&self, name: &str, info: Option<&TempoKey>) -> bool {
+        if !self.rules.iter().all(|r| r.matches(info)) {
+            return false;
+        }
+        if self.text.is_empty() {
+            return true;
+        }
+        let mut haystack = name.to_lowercase();
+        if let Some(bpm) = info.and_then(|i| i.bpm) {
+            haystack.push_str(&format!(" {bpm:.1}"));
+        }
+        if let Some(key) = info.and_then(|i| i.key) {
+            haystack.push_str(&format!(" {} {}", key.to_string().to_lowercase(), key.camelot().to_lowercase()));
+        }
+        haystack.contains(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(bpm: f32, key: &str) -> TempoKey {
+        TempoKey { bpm: Some(bpm), key: key.parse().ok() }
+    }
+
+    #[test]
+    fn bpm_rules() {
+        let q = TrackQuery::parse("bpm:120-128");
+        assert!(q.matches("a", Some(&info(120.0, "Am"))));
+        assert!(q.matches("a", Some(&info(128.0, "Am"))));
+        assert!(!q.matches("a", Some(&info(128.5, "Am"))));
+        assert!(!q.matches("a", None));
+
+        let q = TrackQuery::parse("bpm:128");
+        assert!(q.matches("a", Some(&info(128.4, "Am"))));
+        assert!(!q.matches("a", Some(&info(129.0, "Am"))));
+
+        assert!(TrackQuery::parse("bpm>120").matches("a", Some(&info(121.0, "Am"))));
+        assert!(!TrackQuery::parse("bpm<100").matches("a", Some(&info(121.0, "Am"))));
+    }
+
+    #[test]
+    fn malformed_rules_are_searched_as_text() {
+        let q = TrackQuery::parse("bpm:fast");
+        assert!(q.matches("bpm:fast mix", None));
+        assert!(!q.matches("other", Some(&info(120.0, "Am"))));
+        let q = TrackQuery::parse("key:Xm");
+        assert!(q.matches("key:xm", None));
+    }
+
+    #[test]
+    fn key_rules() {
+        let q = TrackQuery::parse("key:Am");
+        assert!(q.matches("a", Some(&info(120.0, "8A"))));
+        assert!(!q.matches("a", Some(&info(120.0, "C"))));
+
+        let q = TrackQuery::parse("key:8A,C");
+        assert!(q.matches("a", Some(&info(120.0, "C"))));
+
+        // Harmonic neighbours of 8A: 7A, 9A and the relative major 8B
+        let q = TrackQuery::parse("key:~8A");
+        for k in ["Am", "Dm", "Em", "C"] {
+            assert!(q.matches("a", Some(&info(120.0, k))), "{k}");
+        }
+        assert!(!q.matches("a", Some(&info(120.0, "F#"))));
+    }
+
+    #[test]
+    fn text_matches_name_bpm_and_key() {
+        let i = info(124.0, "Am");
+        assert!(TrackQuery::parse("Deep").matches("deep house.flac", Some(&i)));
+        assert!(TrackQuery::parse("124.0").matches("x", Some(&i)));
+        assert!(TrackQuery::parse("8a").matches("x", Some(&i)));
+        assert!(TrackQuery::parse("deep bpm:120-130").matches("Deep House", Some(&i)));
+        assert!(!TrackQuery::parse("deep bpm:130-140").matches("Deep House", Some(&i)));
+        assert!(TrackQuery::parse("").is_empty());
+    }
+}
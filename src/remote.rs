@@ -0,0 +1,486 @@
+// Opt-in HTTP/JSON remote control on localhost. Each connection is served on
+// its own thread; API calls are handed to the app as RemoteCalls and answered
+// from its update loop, so they act exactly like the on-screen controls.
+// GET /api/events streams state changes as server-sent events.
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::mpris::SharedReceiver;
+
+pub(crate) const DEFAULT_PORT: u16 = 8787;
+// Requests are tiny; anything bigger is refused
+const MAX_BODY: usize = 64 * 1024;
+// Upper bound on everything read from one connection, headers included
+const MAX_REQUEST: u64 = MAX_BODY as u64 + 16 * 1024;
+const MAX_HEADERS: usize = 64;
+// Connections served at once, event streams included; more are turned away
+const MAX_CONNECTIONS: usize = 32;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// A client that doesn't take a response or event within this is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// State changes queued per event stream; a stream that falls this far behind is dropped
+const EVENT_QUEUE: usize = 16;
+// How long a request waits for the app to answer
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+// Comment lines keep event streams alive and notice clients that went away
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct RemoteSettings {
+    pub(crate) enabled: bool,
+    pub(crate) port: u16,
+    // Clients send it as "Authorization: Bearer <token>" or "?token=<token>"
+    pub(crate) token: String,
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: DEFAULT_PORT, token: new_token() }
+    }
+}
+
+// 128 random bits as hex; RandomState is seeded by the OS
+pub(crate) fn new_token() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+    (0..2u8)
+        .map(|i| {
+            let mut h = RandomState::new().build_hasher();
+            h.write_u8(i);
+            h.write_u128(nanos);
+            format!("{:016x}", h.finish())
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RemoteRequest {
+    Status,
+    NowPlaying,
+    Queue,
+    Search(String),
+    Play,
+    Pause,
+    Toggle,
+    Stop,
+    Next,
+    Previous,
+    // Seconds into the current track
+    Seek(f64),
+    Volume(f64),
+    EqGains(Vec<f32>),
+    EqPreset(String),
+}
+
+pub(crate) type Reply = Result<Value, String>;
+
+// A request waiting for the app's answer
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteCall {
+    pub(crate) request: RemoteRequest,
+    reply: mpsc::Sender<Reply>,
+}
+
+impl RemoteCall {
+    pub(crate) fn reply(&self, reply: Reply) {
+        let _ = self.reply.send(reply);
+    }
+}
+
+struct Shared {
+    token: String,
+    stop: AtomicBool,
+    connections: AtomicUsize,
+    calls: mpsc::Sender<RemoteCall>,
+    // Open event streams
+    listeners: Mutex<Vec<mpsc::SyncSender<String>>>,
+}
+
+impl Shared {
+    fn call(&self, request: RemoteRequest) -> Result<Value, (u16, String)> {
+        let (reply, answer) = mpsc::channel();
+        self.calls.send(RemoteCall { request, reply }).map_err(|_| (503, "Player is shutting down".to_string()))?;
+        match answer.recv_timeout(REPLY_TIMEOUT) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => Err((400, e)),
+            Err(_) => Err((503, "Player did not respond".into())),
+        }
+    }
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) struct RemoteServer {
+    id: u64,
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    calls: SharedReceiver<RemoteCall>,
+    accept: Option<JoinHandle<()>>,
+    last_state: Option<Value>,
+}
+
+impl RemoteServer {
+    pub(crate) fn start(settings: &RemoteSettings) -> Result<Self, String> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, settings.port))
+            .map_err(|e| format!("Remote control can't listen on port {}: {e}", settings.port))?;
+        let addr = listener.local_addr().map_err(|e| format!("Remote control: {e}"))?;
+        let (calls_tx, calls) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            token: settings.token.clone(),
+            stop: AtomicBool::new(false),
+            connections: AtomicUsize::new(0),
+            calls: calls_tx,
+            listeners: Mutex::new(Vec::new()),
+        });
+        let accept_shared = shared.clone();
+        let accept = std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accept_shared.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                if accept_shared.connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    accept_shared.connections.fetch_sub(1, Ordering::Relaxed);
+                    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                    respond(&mut &stream, 503, &json!({ "error": "Too many connections" }));
+                    continue;
+                }
+                let shared = accept_shared.clone();
+                std::thread::spawn(move || {
+                    serve(stream, &shared);
+                    shared.connections.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        Ok(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            addr,
+            shared,
+            calls: Arc::new(Mutex::new(Some(calls))),
+            accept: Some(accept),
+            last_state: None,
+        })
+    }
+
+    // Changes with every start, so the subscription picks up the new channel
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn calls(&self) -> SharedReceiver<RemoteCall> {
+        self.calls.clone()
+    }
+
+    pub(crate) fn has_listeners(&self) -> bool {
+        self.shared.listeners.lock().is_ok_and(|l| !l.is_empty())
+    }
+
+    // Send the state to every event stream if it changed since last time.
+    pub(crate) fn publish(&mut self, state: Value) {
+        if self.last_state.as_ref() == Some(&state) {
+            return;
+        }
+        let event = format!("event: state\ndata: {state}\n\n");
+        if let Ok(mut listeners) = self.shared.listeners.lock() {
+            // Never wait for a slow client: full or closed streams are dropped
+            listeners.retain(|tx| tx.try_send(event.clone()).is_ok());
+        }
+        self.last_state = Some(state);
+    }
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Ok(mut listeners) = self.shared.listeners.lock() {
+            listeners.clear();
+        }
+        // Wake the accept loop and wait for it so the port is free again
+        let _ = TcpStream::connect(self.addr);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    // Header names lowercased
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn authorized(&self, token: &str) -> bool {
+        let bearer = self.header("authorization").and_then(|v| v.strip_prefix("Bearer "));
+        !token.is_empty() && bearer.or(self.param("token")).is_some_and(|given| same_token(given, token))
+    }
+
+    fn json_body(&self) -> Result<Value, (u16, String)> {
+        serde_json::from_slice(&self.body).map_err(|e| (400, format!("Invalid JSON body: {e}")))
+    }
+}
+
+// Compares every byte whatever the first mismatch, so response times don't
+// give the token away
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn serve(stream: TcpStream, shared: &Shared) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    let mut out = &stream;
+    let request = match read_request(&mut BufReader::new((&stream).take(MAX_REQUEST))) {
+        Ok(request) => request,
+        Err((status, message)) => return respond(&mut out, status, &json!({ "error": message })),
+    };
+    if !request.authorized(&shared.token) {
+        return respond(&mut out, 401, &json!({ "error": "Missing or wrong token" }));
+    }
+    if request.method == "GET" && request.path == "/api/events" {
+        return stream_events(&mut out, shared);
+    }
+    let result = route(&request).and_then(|call| shared.call(call));
+    match result {
+        Ok(value) => respond(&mut out, 200, &value),
+        Err((status, message)) => respond(&mut out, status, &json!({ "error": message })),
+    }
+}
+
+fn route(request: &Request) -> Result<RemoteRequest, (u16, String)> {
+    let number = |key: &str| -> Result<f64, (u16, String)> {
+        request.json_body()?.get(key).and_then(Value::as_f64).ok_or_else(|| (400, format!("Expected {{\"{key}\": <number>}}")))
+    };
+    Ok(match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/api/status") => RemoteRequest::Status,
+        ("GET", "/api/now-playing") => RemoteRequest::NowPlaying,
+        ("GET", "/api/queue") => RemoteRequest::Queue,
+        ("GET", "/api/library") => RemoteRequest::Search(request.param("q").unwrap_or_default().to_string()),
+        ("POST", "/api/play") => RemoteRequest::Play,
+        ("POST", "/api/pause") => RemoteRequest::Pause,
+        ("POST", "/api/toggle") => RemoteRequest::Toggle,
+        ("POST", "/api/stop") => RemoteRequest::Stop,
+        ("POST", "/api/next") => RemoteRequest::Next,
+        ("POST", "/api/previous") => RemoteRequest::Previous,
+        ("POST", "/api/seek") => RemoteRequest::Seek(number("position")?),
+        ("POST", "/api/volume") => RemoteRequest::Volume(number("volume")?),
+        ("POST", "/api/eq") => {
+            let body = request.json_body()?;
+            match (body.get("preset").and_then(Value::as_str), body.get("gains").and_then(Value::as_array)) {
+                (Some(preset), _) => RemoteRequest::EqPreset(preset.to_string()),
+                (None, Some(gains)) => {
+                    let gains: Option<Vec<f32>> = gains.iter().map(|g| g.as_f64().map(|g| g as f32)).collect();
+                    RemoteRequest::EqGains(gains.ok_or((400, "EQ gains must be numbers".to_string()))?)
+                }
+                (None, None) => return Err((400, "Expected {\"preset\": <name>} or {\"gains\": [<dB>, ...]}".into())),
+            }
+        }
+        (
+            _,
+            "/api/status" | "/api/now-playing" | "/api/queue" | "/api/library" | "/api/events" | "/api/play" | "/api/pause"
+            | "/api/toggle" | "/api/stop" | "/api/next" | "/api/previous" | "/api/seek" | "/api/volume" | "/api/eq",
+        ) => return Err((405, "Method not allowed".into())),
+        _ => return Err((404, "Not found".into())),
+    })
+}
+
+// Server-sent events: the current state right away, then every change
+fn stream_events(out: &mut &TcpStream, shared: &Shared) {
+    let (tx, events) = mpsc::sync_channel(EVENT_QUEUE);
+    if let Ok(mut listeners) = shared.listeners.lock() {
+        listeners.push(tx);
+    }
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    if out.write_all(head.as_bytes()).is_err() {
+        return;
+    }
+    // Start with the current state, so clients don't have to fetch it separately
+    let first = shared.call(RemoteRequest::Status).map(|state| format!("event: state\ndata: {state}\n\n"));
+    if first.is_ok_and(|chunk| out.write_all(chunk.as_bytes()).is_err()) {
+        return;
+    }
+    loop {
+        let chunk = match events.recv_timeout(KEEP_ALIVE) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => ": keep-alive\n\n".to_string(),
+            // Server stopped, or this stream fell too far behind
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        if out.write_all(chunk.as_bytes()).and_then(|_| out.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request, (u16, String)> {
+    let bad = |message: &str| (400, message.to_string());
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|_| bad("Unreadable request"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else { return Err(bad("Malformed request line")) };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect();
+    let mut request = Request { method: method.to_string(), path: path.to_string(), query, headers: Vec::new(), body: Vec::new() };
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(|_| bad("Unreadable headers"))?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if request.headers.len() >= MAX_HEADERS {
+            return Err((431, "Too many headers".into()));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            request.headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let length: usize = request.header("content-length").map_or(Ok(0), str::parse).map_err(|_| bad("Bad Content-Length"))?;
+    if length > MAX_BODY {
+        return Err((413, "Request body too large".into()));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).map_err(|_| bad("Incomplete body"))?;
+    Ok(request)
+}
+
+// Query string decoding: %XX escapes and '+' for spaces
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => match bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok()) {
+                Some(b) => {
+                    out.push(b);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn respond(out: &mut &TcpStream, status: u16, body: &Value) {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Service Unavailable",
+    };
+    let body = body.to_string();
+    let head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    let _ = out.write_all(head.as_bytes()).and_then(|_| out.write_all(body.as_bytes())).and_then(|_| out.flush());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(same_token("abc123", "abc123"));
+        assert!(!same_token("abc124", "abc123"));
+        assert!(!same_token("abc12", "abc123"));
+        assert!(!same_token("", "abc123"));
+    }
+
+    #[test]
+    fn event_streams_that_stop_reading_are_dropped() {
+        let settings = RemoteSettings { enabled: true, port: 0, token: "secret".into() };
+        let mut server = RemoteServer::start(&settings).unwrap();
+        // Answer the status request each stream starts with
+        let calls = server.calls().lock().unwrap().take().unwrap();
+        std::thread::spawn(move || {
+            for call in calls {
+                call.reply(Ok(json!({ "state": "idle" })));
+            }
+        });
+        let mut client = TcpStream::connect(server.addr).unwrap();
+        client.write_all(b"GET /api/events?token=secret HTTP/1.1\r\n\r\n").unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !server.has_listeners() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(server.has_listeners());
+
+        // The client never reads: once the socket buffers and the queue are full,
+        // publishing drops the stream instead of queuing without bound
+        let padding = "x".repeat(16 * 1024);
+        for i in 0..5000 {
+            server.publish(json!({ "n": i, "padding": padding }));
+            if !server.has_listeners() {
+                break;
+            }
+        }
+        assert!(!server.has_listeners());
+
+        // The write timeout frees the connection's slot. Queued events can
+        // still trickle out first on a loaded machine, so allow a few timeouts.
+        let deadline = std::time::Instant::now() + WRITE_TIMEOUT * 4;
+        while server.shared.connections.load(Ordering::Relaxed) > 0 && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(server.shared.connections.load(Ordering::Relaxed), 0);
+        drop(client);
+    }
+
+    #[test]
+    fn connections_over_the_limit_are_turned_away() {
+        let settings = RemoteSettings { enabled: true, port: 0, token: "secret".into() };
+        let server = RemoteServer::start(&settings).unwrap();
+        // Idle connections hold their threads until the read times out
+        let idle: Vec<TcpStream> = (0..MAX_CONNECTIONS).map(|_| TcpStream::connect(server.addr).unwrap()).collect();
+        let mut extra = TcpStream::connect(server.addr).unwrap();
+        extra.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut response = String::new();
+        let _ = extra.read_to_string(&mut response);
+        assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+
+        // Closing them frees their slots
+        drop(idle);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while server.shared.connections.load(Ordering::Relaxed) > 0 && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.shared.connections.load(Ordering::Relaxed), 0);
+    }
+}
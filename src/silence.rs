@@ -0,0 +1,90 @@
+// Leading/trailing silence detection for trimming playback. Finds the first
+// and last frame where any channel rises above a threshold; results are cached
+// on disk per file and threshold.
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cue::TrackRange;
+
+pub(crate) const THRESHOLD_RANGE: std::ops::RangeInclusive<f32> = -90.0..=-30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub(crate) struct SilenceTrimSettings {
+    pub(crate) enabled: bool,
+    // Anything quieter than this (sample peak, dBFS) counts as silence
+    pub(crate) threshold_db: f32,
+}
+
+impl Default for SilenceTrimSettings {
+    fn default() -> Self {
+        Self { enabled: false, threshold_db: -60.0 }
+    }
+}
+
+// Audible span of a file at `threshold_db`, from the cache or by decoding it.
+// None if the file can't be decoded or is silent throughout. Does file IO and
+// decoding, so call it off the UI thread.
+pub(crate) fn detect_audible_span(path: &Path, threshold_db: f32) -> Option<TrackRange> {
+    let cache = crate::app::cache_entry("silence", path, "txt");
+    if let Some(cached) = cache.as_deref().and_then(|c| read_cache(c, threshold_db)) {
+        return cached;
+    }
+    let span = compute(path, threshold_db)?;
+    if let Some(cache) = &cache {
+        write_cache(cache, threshold_db, span);
+    }
+    span
+}
+
+// Outer None: decoding failed; inner None: nothing above the threshold.
+fn compute(path: &Path, threshold_db: f32) -> Option<Option<TrackRange>> {
+    let threshold = 10f32.powf(threshold_db / 20.0);
+    let (mut format, track) = crate::engine::open_with_symphonia(path)?;
+    let mut first: Option<f64> = None;
+    let mut last = 0.0f64;
+    // Running position in seconds; the rate may change between packets
+    let mut position = 0.0f64;
+    crate::engine::decode_with_symphonia(format.as_mut(), &track, |samples, channels, rate| {
+        let frame_secs = 1.0 / rate as f64;
+        for frame in samples.chunks(channels) {
+            if frame.iter().any(|x| x.abs() > threshold) {
+                first.get_or_insert(position);
+                last = position + frame_secs;
+            }
+            position += frame_secs;
+        }
+        true
+    })?;
+    Some(first.map(|first| TrackRange { start: Duration::from_secs_f64(first), end: Some(Duration::from_secs_f64(last)) }))
+}
+
+// Cache format: one line "threshold start_us end_us", or "threshold silent".
+// A different threshold means the entry is recomputed.
+fn read_cache(path: &Path, threshold_db: f32) -> Option<Option<TrackRange>> {
+    let data = fs::read_to_string(path).ok()?;
+    let mut parts = data.split_whitespace();
+    let cached: f32 = parts.next()?.parse().ok()?;
+    if (cached - threshold_db).abs() > 0.05 {
+        return None;
+    }
+    match parts.next()? {
+        "silent" => Some(None),
+        start => {
+            let start = Duration::from_micros(start.parse().ok()?);
+            let end = Duration::from_micros(parts.next()?.parse().ok()?);
+            Some(Some(TrackRange { start, end: Some(end) }))
+        }
+    }
+}
+
+fn write_cache(path: &Path, threshold_db: f32, span: Option<TrackRange>) {
+    let line = match span {
+        Some(TrackRange { start, end }) => {
+            format!("{threshold_db} {} {}", start.as_micros(), end.unwrap_or_default().as_micros())
+        }
+        None => format!("{threshold_db} silent"),
+    };
+    let _ = fs::write(path, line);
+}
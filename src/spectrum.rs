@@ -0,0 +1,204 @@
+// Spectrum analyzer: the audio thread writes a mono mixdown into a lock-free
+// ring buffer, and the UI periodically transforms the newest samples into
+// log-spaced bars with peak hold.
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crate::fft::{Complex, Fft};
+
+// Ring buffer size in samples; must hold the largest FFT
+const TAP_CAPACITY: usize = 16384;
+
+pub(crate) const BARS: usize = 64;
+pub(crate) const FLOOR_DB: f32 = -90.0;
+const MIN_FREQ: f32 = 20.0;
+const MAX_FREQ: f32 = 20_000.0;
+// How fast bars and held peaks fall, and how long peaks hold
+const BAR_FALL_DB_PER_SEC: f32 = 60.0;
+const PEAK_FALL_DB_PER_SEC: f32 = 20.0;
+const PEAK_HOLD_SECS: f32 = 1.0;
+
+// Single writer (audio thread), any number of readers. Samples are stored as
+// f32 bits so writes are plain atomic stores: no locks, no allocation.
+pub(crate) struct AudioTap {
+    samples: Box<[AtomicU32]>,
+    // Total samples ever written; the newest is at (written - 1) % capacity
+    written: AtomicUsize,
+    sample_rate: AtomicU32,
+}
+
+impl Default for AudioTap {
+    fn default() -> Self {
+        Self {
+            samples: (0..TAP_CAPACITY).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            sample_rate: AtomicU32::new(44_100),
+        }
+    }
+}
+
+impl AudioTap {
+    pub(crate) fn set_sample_rate(&self, rate: u32) {
+        self.sample_rate.store(rate, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn push(&self, x: f32) {
+        let n = self.written.load(Ordering::Relaxed);
+        self.samples[n % TAP_CAPACITY].store(x.to_bits(), Ordering::Relaxed);
+        self.written.store(n.wrapping_add(1), Ordering::Release);
+    }
+
+    pub(crate) fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
+    }
+
+    // Copy the newest `out.len()` samples, oldest first. A sample may be
+    // overwritten mid-copy; that only matters for display.
+    pub(crate) fn read_latest(&self, out: &mut [f32]) {
+        let end = self.written();
+        let len = out.len().min(TAP_CAPACITY);
+        for (i, dst) in out.iter_mut().take(len).enumerate() {
+            let idx = (end + TAP_CAPACITY * 2 - len + i) % TAP_CAPACITY;
+            *dst = f32::from_bits(self.samples[idx].load(Ordering::Relaxed));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) enum SpectrumResolution {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Ultra,
+}
+
+impl SpectrumResolution {
+    pub(crate) const ALL: [SpectrumResolution; 4] =
+        [SpectrumResolution::Low, SpectrumResolution::Medium, SpectrumResolution::High, SpectrumResolution::Ultra];
+
+    fn fft_size(self) -> usize {
+        match self {
+            SpectrumResolution::Low => 1024,
+            SpectrumResolution::Medium => 2048,
+            SpectrumResolution::High => 4096,
+            SpectrumResolution::Ultra => 8192,
+        }
+    }
+}
+
+impl std::fmt::Display for SpectrumResolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-point FFT", self.fft_size())
+    }
+}
+
+pub(crate) struct SpectrumAnalyzer {
+    resolution: SpectrumResolution,
+    fft: Fft,
+    window: Vec<f32>,
+    window_sum: f32,
+    samples: Vec<f32>,
+    buf: Vec<Complex>,
+    // Displayed level per bar and held peaks, in dBFS
+    pub(crate) bars: Vec<f32>,
+    pub(crate) peaks: Vec<f32>,
+    peak_age: Vec<f32>,
+    last_update: Option<Instant>,
+    last_written: usize,
+}
+
+impl SpectrumAnalyzer {
+    pub(crate) fn new(resolution: SpectrumResolution) -> Self {
+        let n = resolution.fft_size();
+        // Hann window
+        let window: Vec<f32> = (0..n).map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / n as f32).cos()).collect();
+        let window_sum = window.iter().sum();
+        Self {
+            resolution,
+            fft: Fft::new(n),
+            window,
+            window_sum,
+            samples: vec![0.0; n],
+            buf: vec![Complex::ZERO; n],
+            bars: vec![FLOOR_DB; BARS],
+            peaks: vec![FLOOR_DB; BARS],
+            peak_age: vec![0.0; BARS],
+            last_update: None,
+            last_written: 0,
+        }
+    }
+
+    pub(crate) fn resolution(&self) -> SpectrumResolution {
+        self.resolution
+    }
+
+    // Horizontal position (0..1) of a frequency on the log axis, for labels
+    pub(crate) fn bar_position(freq: f32) -> f32 {
+        ((freq / MIN_FREQ).ln() / (MAX_FREQ / MIN_FREQ).ln()).clamp(0.0, 1.0)
+    }
+
+    // Analyse the newest samples from the tap. With nothing new (paused,
+    // stopped) the bars just fall back to the floor.
+    pub(crate) fn update(&mut self, tap: &AudioTap) {
+        let now = Instant::now();
+        let dt = self.last_update.map_or(0.0, |t| now.duration_since(t).as_secs_f32());
+        self.last_update = Some(now);
+
+        let written = tap.written();
+        let fresh = written != self.last_written;
+        self.last_written = written;
+        let mut target = [FLOOR_DB; BARS];
+        if fresh {
+            self.analyse(tap, &mut target);
+        }
+
+        for (i, &level) in target.iter().enumerate() {
+            // Rise instantly, fall at a fixed rate
+            self.bars[i] = level.max(self.bars[i] - BAR_FALL_DB_PER_SEC * dt).max(FLOOR_DB);
+            if self.bars[i] >= self.peaks[i] {
+                self.peaks[i] = self.bars[i];
+                self.peak_age[i] = 0.0;
+            } else {
+                self.peak_age[i] += dt;
+                if self.peak_age[i] > PEAK_HOLD_SECS {
+                    self.peaks[i] = (self.peaks[i] - PEAK_FALL_DB_PER_SEC * dt).max(self.bars[i]);
+                }
+            }
+        }
+    }
+
+    fn analyse(&mut self, tap: &AudioTap, target: &mut [f32; BARS]) {
+        let n = self.fft.len();
+        tap.read_latest(&mut self.samples);
+        for ((dst, &x), &w) in self.buf.iter_mut().zip(&self.samples).zip(&self.window) {
+            *dst = Complex::new(x * w, 0.0);
+        }
+        self.fft.forward(&mut self.buf);
+
+        let rate = tap.sample_rate().max(1) as f32;
+        let bin_hz = rate / n as f32;
+        // Amplitude of a full-scale sine reads 0 dBFS
+        let scale = 2.0 / self.window_sum;
+        let magnitude = |bin: usize| self.buf[bin].norm_sqr().sqrt() * scale;
+        let ratio = MAX_FREQ / MIN_FREQ;
+        for (i, level) in target.iter_mut().enumerate() {
+            let lo = MIN_FREQ * ratio.powf(i as f32 / BARS as f32);
+            let hi = MIN_FREQ * ratio.powf((i + 1) as f32 / BARS as f32);
+            let first = (lo / bin_hz).ceil() as usize;
+            let last = ((hi / bin_hz).floor() as usize).min(n / 2);
+            let mag = if first <= last {
+                (first..=last).map(magnitude).fold(0.0, f32::max)
+            } else {
+                // Narrower than one bin: use the bin nearest the band centre
+                let centre = ((lo * hi).sqrt() / bin_hz).round() as usize;
+                magnitude(centre.min(n / 2))
+            };
+            *level = (20.0 * mag.max(1e-9).log10()).max(FLOOR_DB);
+        }
+    }
+}
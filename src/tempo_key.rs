@@ -0,0 +1,367 @@
+// Offline tempo (BPM) and musical key estimation. Tempo comes from the
+// autocorrelation of a spectral-flux onset envelope; key from a chromagram
+// matched against the Krumhansl-Schmuckler key profiles.
+use crate::fft::{Complex, Fft};
+
+// Onset envelope: ~200 frames per second from 1024-point spectra
+const ONSET_FFT: usize = 1024;
+const ONSET_RATE: f32 = 200.0;
+// Chromagram: long windows for semitone resolution in the bass
+const CHROMA_FFT: usize = 8192;
+const CHROMA_MIN_HZ: f32 = 55.0;
+const CHROMA_MAX_HZ: f32 = 2000.0;
+// Tempo search range, and the tempo the prior favours
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+const PREFERRED_BPM: f32 = 120.0;
+
+const NOTE_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+const MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct MusicalKey {
+    // Pitch class of the tonic, 0 = C
+    pub(crate) tonic: u8,
+    pub(crate) minor: bool,
+}
+
+impl MusicalKey {
+    // Camelot wheel number, 1-12; minor keys are "A", major "B"
+    pub(crate) fn camelot_number(self) -> u32 {
+        let major_tonic = if self.minor { (self.tonic + 3) % 12 } else { self.tonic } as u32;
+        (7 * major_tonic + 7) % 12 + 1
+    }
+
+    fn from_camelot(number: u32, minor: bool) -> Self {
+        // Inverse of `camelot_number`
+        let major_tonic = ((7 * (number - 1) + 11) % 12) as u8;
+        let tonic = if minor { (major_tonic + 9) % 12 } else { major_tonic };
+        MusicalKey { tonic, minor }
+    }
+
+    // Position on the Camelot wheel, e.g. "8A" for A minor
+    pub(crate) fn camelot(self) -> String {
+        format!("{}{}", self.camelot_number(), if self.minor { 'A' } else { 'B' })
+    }
+
+    // Keys that mix harmonically with this one: itself, its neighbours on the
+    // wheel and its relative major/minor
+    pub(crate) fn compatible(self) -> [MusicalKey; 4] {
+        let n = self.camelot_number();
+        [
+            self,
+            MusicalKey::from_camelot(n % 12 + 1, self.minor),
+            MusicalKey::from_camelot((n + 10) % 12 + 1, self.minor),
+            MusicalKey::from_camelot(n, !self.minor),
+        ]
+    }
+}
+
+impl std::fmt::Display for MusicalKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", NOTE_NAMES[self.tonic as usize % 12], if self.minor { "m" } else { "" })
+    }
+}
+
+// Parse "Am", "F#", "Dbm", or a Camelot code like "8A"
+impl std::str::FromStr for MusicalKey {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let s = s.trim();
+        if let Some(number) = s.strip_suffix(['A', 'a', 'B', 'b']).and_then(|n| n.parse::<u32>().ok()).filter(|n| (1..=12).contains(n)) {
+            return Ok(MusicalKey::from_camelot(number, s.ends_with(['A', 'a'])));
+        }
+        let (name, minor) = match s.strip_suffix('m') {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        let sharps = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+        let tonic = NOTE_NAMES
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(name))
+            .or_else(|| sharps.iter().position(|n| n.eq_ignore_ascii_case(name)))
+            .ok_or(())?;
+        Ok(MusicalKey { tonic: tonic as u8, minor })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct TempoKey {
+    // None when no steady beat or tonal content was found
+    pub(crate) bpm: Option<f32>,
+    pub(crate) key: Option<MusicalKey>,
+}
+
+// Collects overlapping frames from a stream of mono samples.
+struct Framer {
+    size: usize,
+    hop: usize,
+    buf: Vec<f32>,
+    start: usize,
+}
+
+impl Framer {
+    fn new(size: usize, hop: usize) -> Self {
+        Self { size, hop: hop.max(1), buf: Vec::new(), start: 0 }
+    }
+
+    fn push(&mut self, samples: &[f32], mut on_frame: impl FnMut(&[f32])) {
+        self.buf.extend_from_slice(samples);
+        while self.buf.len() - self.start >= self.size {
+            on_frame(&self.buf[self.start..self.start + self.size]);
+            self.start += self.hop;
+        }
+        // Drop consumed samples now and then instead of on every push
+        if self.start > 1 << 16 {
+            self.buf.drain(..self.start);
+            self.start = 0;
+        }
+    }
+}
+
+fn hann(n: usize) -> Vec<f32> {
+    (0..n).map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / n as f32).cos()).collect()
+}
+
+pub(crate) struct TempoKeyAnalyzer {
+    onset_framer: Framer,
+    onset_fft: Fft,
+    onset_window: Vec<f32>,
+    prev_spectrum: Vec<f32>,
+    // Spectral flux per onset frame
+    envelope: Vec<f32>,
+    frame_rate: f32,
+    chroma_framer: Framer,
+    chroma_fft: Fft,
+    chroma_window: Vec<f32>,
+    // Pitch class of each chroma FFT bin within range
+    bin_pitch: Vec<Option<usize>>,
+    chroma: [f64; 12],
+    buf: Vec<Complex>,
+    mono: Vec<f32>,
+}
+
+impl TempoKeyAnalyzer {
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate.max(1) as f32;
+        let onset_hop = (rate / ONSET_RATE).round() as usize;
+        let bin_pitch = (0..=CHROMA_FFT / 2)
+            .map(|bin| {
+                let freq = bin as f32 * rate / CHROMA_FFT as f32;
+                (CHROMA_MIN_HZ..=CHROMA_MAX_HZ).contains(&freq).then(|| {
+                    let midi = 69.0 + 12.0 * (freq / 440.0).log2();
+                    (midi.round() as i32).rem_euclid(12) as usize
+                })
+            })
+            .collect();
+        Self {
+            onset_framer: Framer::new(ONSET_FFT, onset_hop),
+            onset_fft: Fft::new(ONSET_FFT),
+            onset_window: hann(ONSET_FFT),
+            prev_spectrum: vec![0.0; ONSET_FFT / 2 + 1],
+            envelope: Vec::new(),
+            frame_rate: rate / onset_hop.max(1) as f32,
+            chroma_framer: Framer::new(CHROMA_FFT, CHROMA_FFT / 2),
+            chroma_fft: Fft::new(CHROMA_FFT),
+            chroma_window: hann(CHROMA_FFT),
+            bin_pitch,
+            chroma: [0.0; 12],
+            buf: vec![Complex::ZERO; CHROMA_FFT],
+            mono: Vec::new(),
+        }
+    }
+
+    // Interleaved samples
+    pub(crate) fn push(&mut self, samples: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        self.mono.clear();
+        self.mono.extend(samples.chunks_exact(channels).map(|f| f.iter().sum::<f32>() / channels as f32));
+
+        let Self { onset_framer, onset_fft, onset_window, prev_spectrum, envelope, buf, mono, .. } = self;
+        onset_framer.push(mono, |frame| {
+            let buf = &mut buf[..ONSET_FFT];
+            for ((dst, &x), &w) in buf.iter_mut().zip(frame).zip(onset_window.iter()) {
+                *dst = Complex::new(x * w, 0.0);
+            }
+            onset_fft.forward(buf);
+            // Log-compressed spectral flux: sum of rises in magnitude
+            let mut flux = 0.0;
+            for (prev, c) in prev_spectrum.iter_mut().zip(buf.iter()) {
+                let mag = (1.0 + 100.0 * c.norm_sqr().sqrt()).ln();
+                flux += (mag - *prev).max(0.0);
+                *prev = mag;
+            }
+            envelope.push(flux);
+        });
+
+        let Self { chroma_framer, chroma_fft, chroma_window, bin_pitch, chroma, buf, mono, .. } = self;
+        chroma_framer.push(mono, |frame| {
+            for ((dst, &x), &w) in buf.iter_mut().zip(frame).zip(chroma_window.iter()) {
+                *dst = Complex::new(x * w, 0.0);
+            }
+            chroma_fft.forward(buf);
+            for (c, pitch) in buf.iter().zip(bin_pitch.iter()) {
+                if let Some(pitch) = pitch {
+                    chroma[*pitch] += c.norm_sqr().sqrt() as f64;
+                }
+            }
+        });
+    }
+
+    pub(crate) fn finish(self) -> TempoKey {
+        TempoKey { bpm: estimate_tempo(&self.envelope, self.frame_rate), key: estimate_key(&self.chroma) }
+    }
+}
+
+fn estimate_tempo(envelope: &[f32], frame_rate: f32) -> Option<f32> {
+    // Remove the slowly varying part (about a second) so only the beats remain
+    let half = (frame_rate / 2.0) as usize;
+    let mut prefix = vec![0.0f64; envelope.len() + 1];
+    for (i, &x) in envelope.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + x as f64;
+    }
+    let onsets: Vec<f32> = (0..envelope.len())
+        .map(|i| {
+            let (lo, hi) = (i.saturating_sub(half), (i + half + 1).min(envelope.len()));
+            let mean = (prefix[hi] - prefix[lo]) / (hi - lo) as f64;
+            (envelope[i] - mean as f32).max(0.0)
+        })
+        .collect();
+
+    let lag_of = |bpm: f32| 60.0 * frame_rate / bpm;
+    let max_lag = lag_of(MIN_BPM).ceil() as usize;
+    // Need a few beats at the slowest tempo
+    if onsets.len() < max_lag * 8 {
+        return None;
+    }
+    let longest = (max_lag * 4).min(onsets.len() / 2);
+    let autocorr: Vec<f32> = (0..=longest)
+        .map(|lag| {
+            let n = onsets.len() - lag;
+            onsets[..n].iter().zip(&onsets[lag..]).map(|(a, b)| a * b).sum::<f32>() / n as f32
+        })
+        .collect();
+    if autocorr[0] <= 0.0 {
+        return None;
+    }
+    let at = |lag: f32| {
+        let i = lag.floor() as usize;
+        match (autocorr.get(i), autocorr.get(i + 1)) {
+            (Some(&a), Some(&b)) => a + (b - a) * (lag - i as f32),
+            _ => 0.0,
+        }
+    };
+    // Score a tempo by the periodicity at its beat and a few multiples,
+    // weighted towards moderate tempos to settle half/double ambiguity
+    let score = |bpm: f32| {
+        let lag = lag_of(bpm);
+        let comb: f32 = (1..=4).map(|k| at(lag * k as f32) / k as f32).sum();
+        let octaves = (bpm / PREFERRED_BPM).log2();
+        comb * (-0.5 * (octaves / 0.9).powi(2)).exp()
+    };
+    let coarse = (0..=((MAX_BPM - MIN_BPM) * 4.0) as usize)
+        .map(|i| MIN_BPM + i as f32 * 0.25)
+        .max_by(|a, b| score(*a).total_cmp(&score(*b)))?;
+    // Refine on a 0.01 BPM grid around the coarse pick
+    let fine = (-25..=25)
+        .map(|i| coarse + i as f32 * 0.01)
+        .max_by(|a, b| score(*a).total_cmp(&score(*b)))?;
+    (score(fine) > 0.0).then_some((fine * 10.0).round() / 10.0)
+}
+
+fn estimate_key(chroma: &[f64; 12]) -> Option<MusicalKey> {
+    let total: f64 = chroma.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let correlation = |profile: &[f32; 12], tonic: usize| {
+        let x: Vec<f64> = (0..12).map(|i| chroma[(i + tonic) % 12]).collect();
+        let mean_x = x.iter().sum::<f64>() / 12.0;
+        let mean_y = profile.iter().map(|&v| v as f64).sum::<f64>() / 12.0;
+        let (mut num, mut dx, mut dy) = (0.0, 0.0, 0.0);
+        for (a, &b) in x.iter().zip(profile) {
+            let (a, b) = (a - mean_x, b as f64 - mean_y);
+            num += a * b;
+            dx += a * a;
+            dy += b * b;
+        }
+        num / (dx * dy).sqrt().max(1e-12)
+    };
+    (0..12)
+        .flat_map(|tonic| [(tonic, false), (tonic, true)])
+        .map(|(tonic, minor)| {
+            let r = correlation(if minor { &MINOR_PROFILE } else { &MAJOR_PROFILE }, tonic);
+            (MusicalKey { tonic: tonic as u8, minor }, r)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(key, _)| key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> MusicalKey {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn camelot_numbers_round_trip() {
+        for tonic in 0..12 {
+            for minor in [false, true] {
+                let k = MusicalKey { tonic, minor };
+                assert_eq!(MusicalKey::from_camelot(k.camelot_number(), minor), k);
+            }
+        }
+        assert_eq!(key("Am").camelot(), "8A");
+        assert_eq!(key("C").camelot(), "8B");
+        assert_eq!(key("F#").camelot(), "2B");
+    }
+
+    #[test]
+    fn keys_parse_from_names_and_camelot_codes() {
+        assert_eq!(key("Am"), MusicalKey { tonic: 9, minor: true });
+        assert_eq!(key("F#"), MusicalKey { tonic: 6, minor: false });
+        assert_eq!(key("Bb"), MusicalKey { tonic: 10, minor: false });
+        assert_eq!(key("8A"), key("Am"));
+        assert_eq!(key("12b"), key("E"));
+        assert!("13A".parse::<MusicalKey>().is_err());
+        assert!("H".parse::<MusicalKey>().is_err());
+        assert_eq!(key("Dbm").to_string(), "Dbm");
+    }
+
+    #[test]
+    fn compatible_keys_are_wheel_neighbours_and_relative() {
+        let names: Vec<_> = key("8A").compatible().iter().map(|k| k.camelot()).collect();
+        assert_eq!(names, ["8A", "9A", "7A", "8B"]);
+        let names: Vec<_> = key("12B").compatible().iter().map(|k| k.camelot()).collect();
+        assert_eq!(names, ["12B", "1B", "11B", "12A"]);
+    }
+
+    #[test]
+    fn tempo_of_a_click_track() {
+        let rate = 44_100;
+        let beat = rate * 60 / 120;
+        let samples: Vec<f32> = (0..rate * 20)
+            .map(|i| {
+                let t = (i % beat) as f32;
+                // Short decaying noise-like burst at each beat
+                if t < 400.0 { (t * 1.7).sin() * (-t / 80.0).exp() } else { 0.0 }
+            })
+            .collect();
+        let mut analyzer = TempoKeyAnalyzer::new(rate as u32);
+        for chunk in samples.chunks(4096) {
+            analyzer.push(chunk, 1);
+        }
+        let bpm = analyzer.finish().bpm.expect("a tempo");
+        assert!((bpm - 120.0).abs() < 1.0, "{bpm}");
+    }
+
+    #[test]
+    fn silence_has_no_tempo_or_key() {
+        let mut analyzer = TempoKeyAnalyzer::new(44_100);
+        analyzer.push(&vec![0.0; 44_100 * 10], 1);
+        assert_eq!(analyzer.finish(), TempoKey { bpm: None, key: None });
+    }
+}
@@ -0,0 +1,109 @@
+// Waveform overview for the seek bar: a fixed number of min/max pairs over
+// the whole file, decoded with Symphonia off the UI thread and cached on disk.
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// Columns in the overview; plenty for a seek bar at any window width
+pub(crate) const POINTS: usize = 1024;
+// Frames folded into one intermediate bucket while decoding (length unknown up front)
+const CHUNK_FRAMES: usize = 256;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Waveform {
+    pub(crate) duration: Duration,
+    // Per column: lowest and highest sample over all channels
+    pub(crate) peaks: Vec<(f32, f32)>,
+}
+
+impl Waveform {
+    // Columns covering `start..end` of the file (for CUE virtual tracks)
+    pub(crate) fn span(&self, start: Duration, end: Option<Duration>) -> &[(f32, f32)] {
+        let total = self.duration.as_secs_f64();
+        if total <= 0.0 {
+            return &self.peaks;
+        }
+        let index = |d: Duration| ((d.as_secs_f64() / total * self.peaks.len() as f64) as usize).min(self.peaks.len());
+        let first = index(start);
+        let last = end.map_or(self.peaks.len(), index).max(first);
+        &self.peaks[first..last]
+    }
+}
+
+// Load the overview from the cache or compute it. Does file IO and decoding,
+// so call it off the UI thread.
+pub(crate) fn load_waveform(path: &Path) -> Option<Waveform> {
+    let cache = crate::app::cache_entry("waveform", path, "peaks");
+    if let Some(waveform) = cache.as_deref().and_then(read_cache) {
+        return Some(waveform);
+    }
+    let waveform = compute(path)?;
+    if let Some(cache) = &cache {
+        write_cache(cache, &waveform);
+    }
+    Some(waveform)
+}
+
+fn compute(path: &Path) -> Option<Waveform> {
+    let (mut format, track) = crate::engine::open_with_symphonia(path)?;
+    let mut chunks: Vec<(f32, f32)> = Vec::new();
+    let mut current = (0.0f32, 0.0f32);
+    let mut in_chunk = 0;
+    let mut frames: u64 = 0;
+    let mut rate = track.codec_params.sample_rate.unwrap_or(0);
+    crate::engine::decode_with_symphonia(format.as_mut(), &track, |samples, channels, sample_rate| {
+        rate = sample_rate;
+        for frame in samples.chunks(channels) {
+            for &x in frame {
+                current = (current.0.min(x), current.1.max(x));
+            }
+            in_chunk += 1;
+            if in_chunk == CHUNK_FRAMES {
+                chunks.push(current);
+                current = (0.0, 0.0);
+                in_chunk = 0;
+            }
+        }
+        frames += (samples.len() / channels) as u64;
+        true
+    })?;
+    if in_chunk > 0 {
+        chunks.push(current);
+    }
+    if chunks.is_empty() || rate == 0 {
+        return None;
+    }
+    // Fold the chunks into POINTS columns
+    let peaks = (0..POINTS)
+        .map(|i| {
+            let first = i * chunks.len() / POINTS;
+            let last = ((i + 1) * chunks.len() / POINTS).max(first + 1).min(chunks.len());
+            chunks[first.min(chunks.len() - 1)..last]
+                .iter()
+                .fold((0.0f32, 0.0f32), |(lo, hi), &(a, b)| (lo.min(a), hi.max(b)))
+        })
+        .collect();
+    Some(Waveform { duration: Duration::from_secs_f64(frames as f64 / rate as f64), peaks })
+}
+
+// Cache format: duration in microseconds (u64), then POINTS pairs of f32, little endian.
+fn read_cache(path: &Path) -> Option<Waveform> {
+    let data = fs::read(path).ok()?;
+    if data.len() != 8 + POINTS * 8 {
+        return None;
+    }
+    let duration = Duration::from_micros(u64::from_le_bytes(data[..8].try_into().ok()?));
+    let float = |b: &[u8]| f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let peaks = data[8..].chunks_exact(8).map(|p| (float(&p[..4]), float(&p[4..]))).collect();
+    Some(Waveform { duration, peaks })
+}
+
+fn write_cache(path: &Path, waveform: &Waveform) {
+    let mut data = Vec::with_capacity(8 + waveform.peaks.len() * 8);
+    data.extend_from_slice(&(waveform.duration.as_micros() as u64).to_le_bytes());
+    for &(lo, hi) in &waveform.peaks {
+        data.extend_from_slice(&lo.to_le_bytes());
+        data.extend_from_slice(&hi.to_le_bytes());
+    }
+    let _ = fs::write(path, data);
+}